@@ -0,0 +1,51 @@
+//! Compares the plain interpreter with the block cache on a tight loop.
+//!
+//! ```text
+//! cargo bench --bench block_cache
+//! ```
+
+use chip8_wasm::block_cache::BlockEngine;
+use chip8_wasm::chip8_cpu::Chip8CPU;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const INSTRUCTIONS_PER_FRAME: u32 = 10_000;
+
+/// Arithmetic in a loop, so the time goes on fetching, decoding and executing.
+const LOOP_ROM: [u8; 14] = [
+    0x71, 0x01, // ADD V1, 1
+    0x82, 0x14, // ADD V2, V1
+    0x83, 0x23, // XOR V3, V2
+    0x84, 0x31, // OR V4, V3
+    0x85, 0x45, // SUB V5, V4
+    0x86, 0x56, // SHR V6, V5
+    0xB2, 0x00, // JP V0, 0x200
+];
+
+fn cpu() -> Chip8CPU {
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(&LOOP_ROM).unwrap();
+
+    cpu
+}
+
+fn engines(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("frame");
+    group.throughput(Throughput::Elements(INSTRUCTIONS_PER_FRAME as u64));
+
+    let mut interpreter = cpu();
+    group.bench_function("interpreter", |bencher| {
+        bencher.iter(|| interpreter.run_frame(INSTRUCTIONS_PER_FRAME))
+    });
+
+    let mut cached = cpu();
+    let mut engine = BlockEngine::new();
+    group.bench_function("block_cache", |bencher| {
+        bencher.iter(|| engine.run_frame(&mut cached, INSTRUCTIONS_PER_FRAME))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
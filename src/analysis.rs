@@ -0,0 +1,518 @@
+//! Static analysis of a ROM: traces every path from the entry point through
+//! jumps, calls, returns and skips to build a control-flow graph, and works out
+//! which bytes are code and which are sprites.
+//!
+//! Jumps follow their documented targets. Computed jumps (BNNN) and invalid
+//! opcodes end a path, so anything only they lead to shows up as unknown
+//! bytes. Sprite data is found by following I from ANNN to the DXYN that draws
+//! with it.
+
+use crate::chip8_cpu::MEMORY_SIZE;
+use crate::instruction::Instruction;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+pub const ENTRY_POINT: u16 = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteKind {
+    Unknown,
+    Code,
+    Sprite,
+}
+
+impl ByteKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ByteKind::Unknown => "unknown",
+            ByteKind::Code => "code",
+            ByteKind::Sprite => "sprite",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// To the next instruction, including the return address of a call.
+    Next,
+    Jump,
+    Call,
+    /// To the instruction after next, when a skip is taken.
+    Skip,
+}
+
+impl EdgeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Next => "next",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+            EdgeKind::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// Address after the last instruction.
+    pub end: u16,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subroutine {
+    pub address: u16,
+    /// Addresses of the 2NNN instructions calling it.
+    pub callers: Vec<u16>,
+}
+
+/// A DXYN drawing a sprite from a known address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpriteReference {
+    /// Where I points when it's drawn.
+    pub address: u16,
+    /// The N of DXYN, where 0 means a 16x16 sprite on SUPER-CHIP.
+    pub height: u8,
+}
+
+/// A run of bytes from `start` up to `end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16,
+    pub kind: ByteKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomAnalysis {
+    rom: Vec<u8>,
+    /// What each byte of the ROM is, from [`ENTRY_POINT`] on.
+    bytes: Vec<ByteKind>,
+    pub blocks: Vec<BasicBlock>,
+    pub subroutines: Vec<Subroutine>,
+    /// Addresses of reachable BNNN instructions.
+    pub computed_jumps: Vec<u16>,
+    /// Addresses of reachable opcodes that don't decode.
+    pub invalid_opcodes: Vec<u16>,
+    /// Unknown bytes that decode as a run of at least two instructions, which
+    /// is most likely dead code or code only a computed jump leads to.
+    pub unreachable_code: Vec<Region>,
+    /// Every distinct sprite drawn, by address.
+    pub sprites: Vec<SpriteReference>,
+}
+
+/// Analyses `rom` as loaded at [`ENTRY_POINT`]. Anything past the end of
+/// memory is ignored.
+pub fn analyze_rom(rom: &[u8]) -> RomAnalysis {
+    let rom = &rom[..rom.len().min(MEMORY_SIZE - ENTRY_POINT as usize)];
+    let opcode_at = |address: u16| {
+        let offset = address.checked_sub(ENTRY_POINT)? as usize;
+        Some(((*rom.get(offset)? as u16) << 8) | *rom.get(offset + 1)? as u16)
+    };
+
+    let mut bytes = vec![ByteKind::Unknown; rom.len()];
+    let mut sprites = Vec::new();
+    let mut edges = BTreeMap::new();
+    let mut computed_jumps = Vec::new();
+    let mut invalid_opcodes = Vec::new();
+
+    // Trace every path, carrying I along where it's known.
+    let mut pending = vec![(ENTRY_POINT, None)];
+    while let Some((address, mut index_register)) = pending.pop() {
+        if edges.contains_key(&address) {
+            continue;
+        }
+        let opcode = match opcode_at(address) {
+            None => continue,
+            Some(opcode) => opcode,
+        };
+
+        let offset = (address - ENTRY_POINT) as usize;
+        bytes[offset] = ByteKind::Code;
+        bytes[offset + 1] = ByteKind::Code;
+
+        let next = |kind| Edge {
+            target: address.wrapping_add(2),
+            kind,
+        };
+        let skip = Edge {
+            target: address.wrapping_add(4),
+            kind: EdgeKind::Skip,
+        };
+        let instruction = Instruction::decode(opcode);
+        let successors = match instruction {
+            Instruction::Return => vec![],
+            Instruction::Jump { address } => vec![Edge {
+                target: address,
+                kind: EdgeKind::Jump,
+            }],
+            Instruction::Call { address } => vec![
+                Edge {
+                    target: address,
+                    kind: EdgeKind::Call,
+                },
+                next(EdgeKind::Next),
+            ],
+            Instruction::SkipIfEqual { .. }
+            | Instruction::SkipIfNotEqual { .. }
+            | Instruction::SkipIfRegistersEqual { .. }
+            | Instruction::SkipIfRegistersNotEqual { .. }
+            | Instruction::SkipIfKeyPressed { .. }
+            | Instruction::SkipIfKeyNotPressed { .. } => vec![next(EdgeKind::Next), skip],
+            Instruction::JumpWithOffset { .. } => {
+                computed_jumps.push(address);
+                vec![]
+            }
+            Instruction::Unknown { .. } => {
+                invalid_opcodes.push(address);
+                vec![]
+            }
+            _ => vec![next(EdgeKind::Next)],
+        };
+
+        match instruction {
+            Instruction::SetIndex { address } => index_register = Some(address),
+            Instruction::Draw { height, .. } => {
+                if let Some(index_register) = index_register {
+                    sprites.push((index_register, height));
+                }
+            }
+            // These leave I somewhere that depends on registers or quirks.
+            Instruction::AddToIndex { .. }
+            | Instruction::SetIndexToFont { .. }
+            | Instruction::StoreRegisters { .. }
+            | Instruction::LoadRegisters { .. } => index_register = None,
+            // A call can change I before it returns.
+            Instruction::Call { .. } => index_register = None,
+            _ => {}
+        }
+
+        pending.extend(
+            successors
+                .iter()
+                .rev()
+                .map(|edge| (edge.target, index_register)),
+        );
+        edges.insert(address, successors);
+    }
+
+    for (start, height) in sprites.iter().copied() {
+        for address in start..start.saturating_add(height) {
+            let offset = match address.checked_sub(ENTRY_POINT) {
+                None => continue,
+                Some(offset) => offset as usize,
+            };
+            if bytes.get(offset) == Some(&ByteKind::Unknown) {
+                bytes[offset] = ByteKind::Sprite;
+            }
+        }
+    }
+
+    let blocks = find_blocks(&edges);
+    let subroutines = find_subroutines(&edges);
+    let unreachable_code = find_unreachable_code(&bytes, opcode_at);
+    computed_jumps.sort_unstable();
+    invalid_opcodes.sort_unstable();
+    let mut sprites = sprites
+        .into_iter()
+        .map(|(address, height)| SpriteReference {
+            address,
+            height: height as u8,
+        })
+        .collect::<Vec<_>>();
+    sprites.sort_unstable();
+    sprites.dedup();
+
+    RomAnalysis {
+        rom: rom.to_vec(),
+        bytes,
+        blocks,
+        subroutines,
+        computed_jumps,
+        invalid_opcodes,
+        unreachable_code,
+        sprites,
+    }
+}
+
+/// Splits the traced instructions into basic blocks. Blocks start at the entry
+/// point and at every address reached by anything but running on from a plain
+/// instruction.
+fn find_blocks(edges: &BTreeMap<u16, Vec<Edge>>) -> Vec<BasicBlock> {
+    let is_plain = |address: u16, successors: &[Edge]| {
+        successors
+            == [Edge {
+                target: address.wrapping_add(2),
+                kind: EdgeKind::Next,
+            }]
+    };
+
+    let mut leaders = BTreeSet::new();
+    leaders.insert(ENTRY_POINT);
+    edges
+        .iter()
+        .filter(|(address, successors)| !is_plain(**address, successors))
+        .flat_map(|(_, successors)| successors.iter())
+        .for_each(|edge| {
+            leaders.insert(edge.target);
+        });
+
+    leaders
+        .iter()
+        .filter(|leader| edges.contains_key(leader))
+        .map(|start| {
+            let mut address = *start;
+            loop {
+                let successors = &edges[&address];
+                let next = address.wrapping_add(2);
+                if !is_plain(address, successors)
+                    || leaders.contains(&next)
+                    || !edges.contains_key(&next)
+                {
+                    return BasicBlock {
+                        start: *start,
+                        end: next,
+                        successors: successors.clone(),
+                    };
+                }
+
+                address = next;
+            }
+        })
+        .collect()
+}
+
+fn find_subroutines(edges: &BTreeMap<u16, Vec<Edge>>) -> Vec<Subroutine> {
+    let mut subroutines: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    edges.iter().for_each(|(address, successors)| {
+        successors
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Call)
+            .for_each(|edge| subroutines.entry(edge.target).or_default().push(*address));
+    });
+
+    subroutines
+        .into_iter()
+        .map(|(address, callers)| Subroutine { address, callers })
+        .collect()
+}
+
+fn find_unreachable_code(
+    bytes: &[ByteKind],
+    opcode_at: impl Fn(u16) -> Option<u16>,
+) -> Vec<Region> {
+    let is_dead_instruction = |address: u16| {
+        let offset = (address - ENTRY_POINT) as usize;
+        let opcode = match opcode_at(address) {
+            None => return false,
+            Some(opcode) => opcode,
+        };
+
+        bytes[offset] == ByteKind::Unknown
+            && bytes[offset + 1] == ByteKind::Unknown
+            // Zeroed padding decodes as 00E0.
+            && opcode != 0x0000
+            && !matches!(Instruction::decode(opcode), Instruction::Unknown { .. })
+    };
+
+    let mut regions = Vec::new();
+    let mut run_start = None;
+    let end = ENTRY_POINT + bytes.len() as u16;
+    for address in (ENTRY_POINT..end + 2).step_by(2) {
+        match (run_start, is_dead_instruction(address)) {
+            (None, true) => run_start = Some(address),
+            (Some(start), false) => {
+                if address - start >= 4 {
+                    regions.push(Region {
+                        start,
+                        end: address,
+                        kind: ByteKind::Unknown,
+                    });
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    regions
+}
+
+impl RomAnalysis {
+    /// What the byte at `address` is, or `None` outside the ROM.
+    pub fn byte_kind(&self, address: u16) -> Option<ByteKind> {
+        let offset = address.checked_sub(ENTRY_POINT)?;
+        self.bytes.get(offset as usize).copied()
+    }
+
+    /// The ROM as runs of bytes of the same kind.
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        self.bytes.iter().enumerate().for_each(|(offset, kind)| {
+            let address = ENTRY_POINT + offset as u16;
+            match regions.last_mut() {
+                Some(region) if region.kind == *kind => region.end = address + 1,
+                _ => regions.push(Region {
+                    start: address,
+                    end: address + 1,
+                    kind: *kind,
+                }),
+            }
+        });
+
+        regions
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(json, "{{\"entry_point\":{},\"blocks\":[", ENTRY_POINT).unwrap();
+        self.blocks.iter().enumerate().for_each(|(index, block)| {
+            if index > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "{{\"start\":{},\"end\":{},\"successors\":[",
+                block.start, block.end
+            )
+            .unwrap();
+            block
+                .successors
+                .iter()
+                .enumerate()
+                .for_each(|(index, edge)| {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    write!(
+                        json,
+                        "{{\"target\":{},\"kind\":\"{}\"}}",
+                        edge.target,
+                        edge.kind.name()
+                    )
+                    .unwrap();
+                });
+            json.push_str("]}");
+        });
+
+        json.push_str("],\"subroutines\":[");
+        self.subroutines
+            .iter()
+            .enumerate()
+            .for_each(|(index, subroutine)| {
+                if index > 0 {
+                    json.push(',');
+                }
+                write!(json, "{{\"address\":{},\"callers\":", subroutine.address).unwrap();
+                write_json_numbers(&mut json, &subroutine.callers);
+                json.push('}');
+            });
+
+        json.push_str("],\"computed_jumps\":");
+        write_json_numbers(&mut json, &self.computed_jumps);
+        json.push_str(",\"invalid_opcodes\":");
+        write_json_numbers(&mut json, &self.invalid_opcodes);
+        json.push_str(",\"unreachable_code\":");
+        write_json_regions(&mut json, &self.unreachable_code);
+        json.push_str(",\"regions\":");
+        write_json_regions(&mut json, &self.regions());
+        json.push('}');
+
+        json
+    }
+
+    /// A Graphviz graph of the basic blocks. Subroutine entries have a double
+    /// border and blocks ending in a computed jump or invalid opcode are red.
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph rom {\n    node [shape=box, fontname=\"monospace\"];\n");
+        self.blocks.iter().for_each(|block| {
+            write!(dot, "    \"{:03X}\" [label=\"", block.start).unwrap();
+            (block.start..block.end).step_by(2).for_each(|address| {
+                let offset = (address - ENTRY_POINT) as usize;
+                write!(
+                    dot,
+                    "{:03X}: {:02X}{:02X}\\l",
+                    address,
+                    self.rom[offset],
+                    self.rom[offset + 1]
+                )
+                .unwrap();
+            });
+            dot.push('"');
+
+            if self
+                .subroutines
+                .iter()
+                .any(|subroutine| subroutine.address == block.start)
+            {
+                dot.push_str(", peripheries=2");
+            }
+            let last = block.end - 2;
+            if self.computed_jumps.contains(&last) || self.invalid_opcodes.contains(&last) {
+                dot.push_str(", color=red");
+            }
+            dot.push_str("];\n");
+        });
+
+        self.blocks.iter().for_each(|block| {
+            block.successors.iter().for_each(|edge| {
+                write!(
+                    dot,
+                    "    \"{:03X}\" -> \"{:03X}\"",
+                    block.start, edge.target
+                )
+                .unwrap();
+                match edge.kind {
+                    EdgeKind::Next | EdgeKind::Jump => {}
+                    EdgeKind::Call => dot.push_str(" [style=dashed, label=\"call\"]"),
+                    EdgeKind::Skip => dot.push_str(" [label=\"skip\"]"),
+                }
+                dot.push_str(";\n");
+            });
+        });
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+fn write_json_numbers(json: &mut String, numbers: &[u16]) {
+    json.push('[');
+    numbers.iter().enumerate().for_each(|(index, number)| {
+        if index > 0 {
+            json.push(',');
+        }
+        write!(json, "{}", number).unwrap();
+    });
+    json.push(']');
+}
+
+fn write_json_regions(json: &mut String, regions: &[Region]) {
+    json.push('[');
+    regions.iter().enumerate().for_each(|(index, region)| {
+        if index > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "{{\"start\":{},\"end\":{},\"kind\":\"{}\"}}",
+            region.start,
+            region.end,
+            region.kind.name()
+        )
+        .unwrap();
+    });
+    json.push(']');
+}
@@ -0,0 +1,419 @@
+//! Adding many ROMs to a [`GameLibrary`] at once, from a zip archive or, for
+//! native frontends, a directory.
+//!
+//! Files ending in `.ch8`, `.c8`, `.sc8` or `.xo8` are added, named after the
+//! file. A `manifest.json` at the top of the archive can name and describe
+//! them, and list ROMs with other names:
+//!
+//! ```json
+//! {
+//!   "games": [
+//!     {
+//!       "file": "builds/maze.ch8",
+//!       "title": "Maze",
+//!       "author": "Someone",
+//!       "year": 2026,
+//!       "platform": "chip8",
+//!       "description": "Find the way out.",
+//!       "tags": ["puzzle"],
+//!       "licence": "CC0-1.0",
+//!       "quirks": { "shiftUsesVy": true, "vfReset": true },
+//!       "instructionsPerFrame": 15,
+//!       "keys": { "5": "Jump", "4": "Left", "6": "Right" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Every member is optional except `file`. Quirks that aren't given take their
+//! default, and `keys` maps keypad keys, in hexadecimal, to what they do.
+
+use crate::chip8_cpu::MAX_ROM_SIZE;
+use crate::crc32::crc32;
+use crate::inflate::{inflate, InflateError};
+use crate::json::{JsonError, JsonValue};
+use crate::library::{GameLibrary, GameMetadata, GamePlatform};
+use crate::quirks::Quirks;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+/// Far beyond any ROM or manifest. Entries claiming more are refused before
+/// they're inflated.
+const MAX_FILE_SIZE: usize = 0x100_0000;
+/// What all the entries together may inflate to. Without it a zip bomb of
+/// many entries, each under [`MAX_FILE_SIZE`], could still exhaust memory.
+const MAX_TOTAL_SIZE: usize = 0x400_0000;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4B50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4B50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4B50;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArchiveError {
+    NotAZip,
+    /// The archive ends in the middle of a header or a file.
+    Truncated,
+    /// The file is encrypted, in a zip64 archive, or compressed with
+    /// something other than DEFLATE.
+    Unsupported(String),
+    /// The files add up to more than the archive is allowed to inflate to.
+    TooLarge,
+    Corrupt(String, InflateError),
+    ChecksumMismatch(String),
+    InvalidJson(JsonError),
+    /// A game in the manifest, counting from 0, has a missing or invalid member.
+    InvalidManifest {
+        game: usize,
+        member: &'static str,
+    },
+    /// The manifest lists a file that isn't there.
+    MissingFile(String),
+    /// A game's name is empty, or already taken in the library or by another
+    /// game in the archive.
+    InvalidName(String),
+    /// The ROM in this file is too large to fit in memory, see
+    /// [`MAX_ROM_SIZE`].
+    RomTooLarge(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveFile {
+    /// Relative to the top of the archive, with `/` between directories.
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// A game listed in a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub title: Option<String>,
+    pub metadata: GameMetadata,
+}
+
+/// Adds the ROMs in a zip archive to `library`, returning their names. Nothing
+/// is added when any of them can't be.
+pub fn load_zip(library: &mut GameLibrary, archive: &[u8]) -> Result<Vec<String>, ArchiveError> {
+    add_files(library, read_zip(archive)?)
+}
+
+/// Reads every file in a zip archive, checking their CRC-32s.
+pub fn read_zip(archive: &[u8]) -> Result<Vec<ArchiveFile>, ArchiveError> {
+    // The end of central directory record is followed by a comment of up to
+    // 64 KiB, so it has to be searched for from the end.
+    let end = (0..=archive.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+        .rev()
+        .take(0x10000 + END_OF_CENTRAL_DIRECTORY_SIZE)
+        .find(|offset| read_u32(archive, *offset) == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or(ArchiveError::NotAZip)?;
+    let entry_count = read_u16(archive, end + 10).ok_or(ArchiveError::Truncated)?;
+    let mut offset = read_u32(archive, end + 16).ok_or(ArchiveError::Truncated)? as usize;
+
+    let mut files = Vec::new();
+    let mut total_size = 0;
+    for _ in 0..entry_count {
+        if read_u32(archive, offset) != Some(CENTRAL_DIRECTORY_HEADER) {
+            return Err(ArchiveError::Truncated);
+        }
+        let field = |at: usize| read_u16(archive, add(offset, at)?).ok_or(ArchiveError::Truncated);
+        let flags = field(8)?;
+        let method = field(10)?;
+        let name_length = field(28)? as usize;
+        let extra_length = field(30)? as usize;
+        let comment_length = field(32)? as usize;
+        let field = |at: usize| read_u32(archive, add(offset, at)?).ok_or(ArchiveError::Truncated);
+        let checksum = field(16)?;
+        let compressed_size = field(20)?;
+        let size = field(24)?;
+        let local_offset = field(42)? as usize;
+        let name_start = add(offset, 46)?;
+        let name = archive
+            .get(name_start..add(name_start, name_length)?)
+            .ok_or(ArchiveError::Truncated)?;
+        let path = String::from_utf8_lossy(name).into_owned();
+        offset = add(name_start, name_length + extra_length + comment_length)?;
+
+        if path.ends_with('/') {
+            continue;
+        }
+        // Bit 0 is encryption, and all ones sizes mean the real ones are in
+        // a zip64 extra field.
+        let zip64 = compressed_size == u32::MAX || size == u32::MAX;
+        if flags & 0x1 != 0 || zip64 || (method != 0 && method != 8) {
+            return Err(ArchiveError::Unsupported(path));
+        }
+        if size as usize > MAX_FILE_SIZE {
+            return Err(ArchiveError::Corrupt(path, InflateError::TooLarge));
+        }
+        total_size += size as usize;
+        if total_size > MAX_TOTAL_SIZE {
+            return Err(ArchiveError::TooLarge);
+        }
+
+        if read_u32(archive, local_offset) != Some(LOCAL_FILE_HEADER) {
+            return Err(ArchiveError::Truncated);
+        }
+        let local_name_length =
+            read_u16(archive, add(local_offset, 26)?).ok_or(ArchiveError::Truncated)?;
+        let local_extra_length =
+            read_u16(archive, add(local_offset, 28)?).ok_or(ArchiveError::Truncated)?;
+        let start = add(
+            local_offset,
+            30 + local_name_length as usize + local_extra_length as usize,
+        )?;
+        let compressed = archive
+            .get(start..add(start, compressed_size as usize)?)
+            .ok_or(ArchiveError::Truncated)?;
+
+        let data = if method == 0 {
+            compressed.to_vec()
+        } else {
+            inflate(compressed, size as usize)
+                .map_err(|error| ArchiveError::Corrupt(path.clone(), error))?
+        };
+        if data.len() != size as usize || crc32(&data) != checksum {
+            return Err(ArchiveError::ChecksumMismatch(path));
+        }
+        files.push(ArchiveFile { path, data });
+    }
+
+    Ok(files)
+}
+
+/// Adds the ROMs among `files` to `library`, as described by the manifest if
+/// there is one, returning their names. Nothing is added when any of them
+/// can't be.
+pub fn add_files(
+    library: &mut GameLibrary,
+    files: Vec<ArchiveFile>,
+) -> Result<Vec<String>, ArchiveError> {
+    let manifest = match files.iter().find(|file| file.path == MANIFEST_NAME) {
+        None => Vec::new(),
+        Some(file) => parse_manifest(&String::from_utf8_lossy(&file.data))?,
+    };
+    if let Some(entry) = manifest
+        .iter()
+        .find(|entry| !files.iter().any(|file| file.path == entry.file))
+    {
+        return Err(ArchiveError::MissingFile(entry.file.clone()));
+    }
+
+    let mut games: Vec<(String, Vec<u8>, GameMetadata)> = Vec::new();
+    for file in files.into_iter() {
+        let (title, metadata) = match manifest.iter().find(|entry| entry.file == file.path) {
+            Some(entry) => (entry.title.clone(), entry.metadata.clone()),
+            None => match rom_platform(&file.path) {
+                None => continue,
+                Some(platform) => (
+                    None,
+                    GameMetadata {
+                        platform,
+                        ..GameMetadata::default()
+                    },
+                ),
+            },
+        };
+        if file.data.len() > MAX_ROM_SIZE {
+            return Err(ArchiveError::RomTooLarge(file.path));
+        }
+        let name = title.unwrap_or_else(|| file_stem(&file.path).to_string());
+        let taken = library.get(&name).is_some() || games.iter().any(|game| game.0 == name);
+        if taken || name.trim().is_empty() {
+            return Err(ArchiveError::InvalidName(name));
+        }
+        games.push((name, file.data, metadata));
+    }
+
+    let mut names = Vec::new();
+    for (name, rom, metadata) in games.into_iter() {
+        library
+            .add(&name, rom, metadata)
+            .expect("Names and sizes are checked before adding any");
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+pub fn parse_manifest(text: &str) -> Result<Vec<ManifestEntry>, ArchiveError> {
+    let manifest = JsonValue::parse(text).map_err(ArchiveError::InvalidJson)?;
+    let games = manifest.get("games").and_then(JsonValue::as_array).ok_or(
+        ArchiveError::InvalidManifest {
+            game: 0,
+            member: "games",
+        },
+    )?;
+
+    games
+        .iter()
+        .enumerate()
+        .map(|(game, entry)| {
+            parse_entry(entry).map_err(|member| ArchiveError::InvalidManifest { game, member })
+        })
+        .collect()
+}
+
+/// Fails with the name of the member that's missing or invalid.
+fn parse_entry(entry: &JsonValue) -> Result<ManifestEntry, &'static str> {
+    // A member that's there has to have the right type.
+    let string = |member: &'static str| match entry.get(member) {
+        None => Ok(None),
+        Some(value) => value.as_str().map(Some).ok_or(member),
+    };
+    let integer = |member: &'static str, max: f64| match entry.get(member) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .filter(|number| *number >= 0.0 && *number <= max && *number == *number as u32 as f64)
+            .map(Some)
+            .ok_or(member),
+    };
+
+    let mut metadata = GameMetadata {
+        author: string("author")?.unwrap_or("").to_string(),
+        year: integer("year", u16::MAX as f64)?.map(|year| year as u16),
+        description: string("description")?.unwrap_or("").to_string(),
+        licence: string("licence")?.unwrap_or("").to_string(),
+        instructions_per_frame: integer("instructionsPerFrame", 10_000.0)?
+            .map(|speed| speed as u32)
+            .filter(|speed| *speed > 0),
+        ..GameMetadata::default()
+    };
+    let file = string("file")?.ok_or("file")?;
+    metadata.platform = match string("platform")? {
+        None => rom_platform(file).unwrap_or_default(),
+        Some(platform) => GamePlatform::from_name(platform).ok_or("platform")?,
+    };
+    if let Some(tags) = entry.get("tags") {
+        metadata.tags = tags
+            .as_array()
+            .and_then(|tags| {
+                tags.iter()
+                    .map(|tag| tag.as_str().map(str::to_string))
+                    .collect()
+            })
+            .ok_or("tags")?;
+    }
+    if let Some(quirks) = entry.get("quirks") {
+        metadata.quirks = Some(parse_quirks(quirks).ok_or("quirks")?);
+    }
+    if let Some(keys) = entry.get("keys") {
+        metadata.key_help = parse_keys(keys).ok_or("keys")?;
+    }
+
+    Ok(ManifestEntry {
+        file: file.to_string(),
+        title: string("title")?.map(str::to_string),
+        metadata,
+    })
+}
+
+fn parse_quirks(value: &JsonValue) -> Option<Quirks> {
+    let mut quirks = Quirks::default();
+    for (name, value) in value.as_object()? {
+        let quirk = match name.as_str() {
+            "shiftUsesVy" => &mut quirks.shift_uses_vy,
+            "loadStoreIncrementsI" => &mut quirks.load_store_increments_i,
+            "jumpUsesVx" => &mut quirks.jump_uses_vx,
+            "vfReset" => &mut quirks.vf_reset,
+            "keyWaitRelease" => &mut quirks.key_wait_release,
+            _ => return None,
+        };
+        *quirk = value.as_bool()?;
+    }
+
+    Some(quirks)
+}
+
+/// Key help text like "4: Left, 5: Jump, 6: Right", in keypad order.
+fn parse_keys(value: &JsonValue) -> Option<String> {
+    let mut keys = value
+        .as_object()?
+        .iter()
+        .map(|(key, action)| {
+            let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16)?;
+            Some((key, action.as_str()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    keys.sort_by_key(|(key, _)| *key);
+
+    Some(
+        keys.iter()
+            .map(|(key, action)| format!("{:X}: {}", key, action))
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+/// The platform a ROM's file extension is used for, if it's a ROM.
+fn rom_platform(path: &str) -> Option<GamePlatform> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    match extension.as_str() {
+        "ch8" | "c8" => Some(GamePlatform::Chip8),
+        "sc8" => Some(GamePlatform::SuperChip),
+        "xo8" => Some(GamePlatform::XoChip),
+        _ => None,
+    }
+}
+
+fn file_stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    }
+}
+
+/// An offset into the archive, failing rather than overflowing on one that
+/// points far beyond it.
+fn add(offset: usize, length: usize) -> Result<usize, ArchiveError> {
+    offset.checked_add(length).ok_or(ArchiveError::Truncated)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset.checked_add(2)?)?;
+
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads every file under `directory`, for [`add_files`].
+#[cfg(feature = "std")]
+pub fn read_directory(directory: &std::path::Path) -> std::io::Result<Vec<ArchiveFile>> {
+    fn visit(
+        directory: &std::path::Path,
+        prefix: &str,
+        files: &mut Vec<ArchiveFile>,
+    ) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+        // Directory order depends on the file system.
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                visit(&entry.path(), &format!("{}/", path), files)?;
+            } else {
+                files.push(ArchiveFile {
+                    path,
+                    data: std::fs::read(entry.path())?,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    visit(directory, "", &mut files)?;
+
+    Ok(files)
+}
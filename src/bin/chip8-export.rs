@@ -0,0 +1,125 @@
+//! Plays an input movie without a screen, writing what's on it as a Y4M
+//! video and the buzzer as a WAV, for editing into videos.
+//!
+//! ```text
+//! chip8-export [--scale <pixels>] [--sample-rate <hz>] <rom> <movie> <video> <audio>
+//! ```
+//!
+//! Both files cover the whole movie, at 60 frames a second, see
+//! `chip8_wasm::export`. `--scale` makes each pixel that many pixels across,
+//! 8 by default, and `--sample-rate` defaults to 48000. The WAV only has the
+//! CHIP-8 buzzer, not XO-CHIP audio.
+
+use chip8_wasm::export::{MovieExporter, DEFAULT_SAMPLE_RATE};
+use chip8_wasm::image::MONOCHROME;
+use chip8_wasm::movie::Movie;
+
+use std::convert::TryInto;
+use std::io::{self, BufWriter, Write};
+use std::{env, fs, process};
+
+const DEFAULT_SCALE: u32 = 8;
+
+struct Options {
+    rom_path: String,
+    movie_path: String,
+    video_path: String,
+    audio_path: String,
+    scale: u32,
+    sample_rate: u32,
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!(
+                "usage: chip8-export [--scale <pixels>] [--sample-rate <hz>] \
+                 <rom> <movie> <video> <audio>"
+            );
+            process::exit(2);
+        }
+    };
+
+    if let Err(message) = export(&options) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut scale = DEFAULT_SCALE;
+    let mut sample_rate = DEFAULT_SAMPLE_RATE;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                let value = args.next().ok_or("--scale needs a value")?;
+                scale = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --scale value {}", value))?;
+            }
+            "--sample-rate" => {
+                let value = args.next().ok_or("--sample-rate needs a value")?;
+                sample_rate = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --sample-rate value {}", value))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => paths.push(arg),
+        }
+    }
+
+    let [rom_path, movie_path, video_path, audio_path]: [String; 4] = paths
+        .try_into()
+        .map_err(|_| "Expected a ROM, a movie and the two files to write")?;
+
+    Ok(Options {
+        rom_path,
+        movie_path,
+        video_path,
+        audio_path,
+        scale,
+        sample_rate,
+    })
+}
+
+fn export(options: &Options) -> Result<(), String> {
+    let read =
+        |path: &str| fs::read(path).map_err(|error| format!("Failed to read {}: {}", path, error));
+    let rom = read(&options.rom_path)?;
+    let movie = Movie::from_bytes(&read(&options.movie_path)?)
+        .map_err(|error| format!("Invalid movie in {}: {:?}", options.movie_path, error))?;
+    let mut exporter =
+        MovieExporter::new(&rom, movie, options.scale, &MONOCHROME, options.sample_rate)
+            .map_err(|error| format!("Can't export {}: {:?}", options.movie_path, error))?;
+
+    let create = |path: &str| {
+        fs::File::create(path)
+            .map(BufWriter::new)
+            .map_err(|error| format!("Failed to create {}: {}", path, error))
+    };
+    let mut video = create(&options.video_path)?;
+    let mut audio = create(&options.audio_path)?;
+
+    write_export(&mut exporter, &mut video, &mut audio)
+        .map_err(|error| format!("Failed to write the export: {}", error))
+}
+
+fn write_export(
+    exporter: &mut MovieExporter,
+    video: &mut impl Write,
+    audio: &mut impl Write,
+) -> io::Result<()> {
+    video.write_all(&exporter.video_header())?;
+    audio.write_all(&exporter.audio_header())?;
+    while let Some((video_frame, audio_frame)) = exporter.next_frame() {
+        video.write_all(&video_frame)?;
+        audio.write_all(&audio_frame)?;
+    }
+    video.flush()?;
+
+    audio.flush()
+}
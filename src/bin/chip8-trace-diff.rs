@@ -0,0 +1,40 @@
+//! Reports the first line where two execution traces differ, such as ones
+//! written by `chip8-tui --trace` or by another emulator following the format
+//! in `chip8_wasm::trace`.
+//!
+//! ```text
+//! chip8-trace-diff <trace> <trace>
+//! ```
+//!
+//! Exits with 0 when the traces match and 1 when they don't.
+
+use chip8_wasm::trace::first_divergence;
+
+use std::{env, fs, process};
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.len() != 2 {
+        eprintln!("usage: chip8-trace-diff <trace> <trace>");
+        process::exit(2);
+    }
+
+    let traces: Vec<String> = paths
+        .iter()
+        .map(|path| match fs::read_to_string(path) {
+            Ok(trace) => trace,
+            Err(error) => {
+                eprintln!("Failed to read {}: {}", path, error);
+                process::exit(2);
+            }
+        })
+        .collect();
+
+    match first_divergence(&traces[0], &traces[1]) {
+        None => println!("Traces match"),
+        Some(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,674 @@
+//! Runs a ROM in the terminal, for machines without a browser.
+//!
+//! ```text
+//! chip8-tui [--ips <instructions per second>] [--braille] [--coverage <file>]
+//!           [--profile <file>] [--flamegraph <file>] [--trace <file>] [--differential]
+//!           [--cheats <file>] [--patch <file>] [--game <name>] [--movie <file>]
+//!           [--gif <file>] [--block-cache] <rom>
+//! ```
+//!
+//! The keypad is mapped onto the left hand side of a QWERTY keyboard, press
+//! `Esc` to quit. With `--coverage` an annotated dump of the ROM, listing how
+//! often each instruction was executed and each byte was read or written, is
+//! written to `file` on quitting. Likewise `--profile` writes a flat profile,
+//! and `--flamegraph` the call tree in the folded stack format flame graph
+//! tools read. `--trace` writes a line per executed instruction as it runs,
+//! see `chip8_wasm::trace`, to compare with `chip8-trace-diff`.
+//! `--differential` runs the reference interpreter alongside, and quits with
+//! a report at the first instruction they disagree on. `--cheats` applies a
+//! cheat file, see `chip8_wasm::cheats`, after every frame. `--patch` applies an
+//! IPS or BPS patch to the ROM before running it.
+//!
+//! `--movie` plays an input movie recorded in the browser, see
+//! `chip8_wasm::movie`, ignoring the keyboard, and quits once it's over. It
+//! can't be combined with `--cheats` or `--differential`. `--gif` records
+//! everything on screen into an animated GIF, written as it runs.
+//! `--block-cache` runs the ROM on `chip8_wasm::block_cache::BlockEngine`
+//! rather than decoding every instruction as it goes. It can't be combined with
+//! `--movie` or `--differential`, which run the plain interpreter.
+//!
+//! `rom` can also be a directory or zip archive of ROMs, optionally with a
+//! manifest, see `chip8_wasm::archive`, and `--game` picks one of them by name.
+//! The quirks and speed the manifest gives are used unless `--ips` is.
+
+use chip8_wasm::archive::{add_files, read_directory, read_zip};
+use chip8_wasm::block_cache::BlockEngine;
+use chip8_wasm::cheats::CheatList;
+use chip8_wasm::chip8_cpu::Chip8CPU;
+use chip8_wasm::crc32::crc32;
+use chip8_wasm::gif::GifRecorder;
+use chip8_wasm::image::{Bitmap, MONOCHROME};
+use chip8_wasm::library::{GameLibrary, GameMetadata};
+use chip8_wasm::movie::{Movie, MoviePlayer};
+use chip8_wasm::patch::apply_patch;
+use chip8_wasm::platform::XorShiftRng;
+use chip8_wasm::reference::Differential;
+use chip8_wasm::traits::{Audio, Logger, Observer, RandomSource};
+
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, panic, process};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+const DEFAULT_IPS: u32 = 600;
+/// Without key release events a key counts as held for this many frames after
+/// the terminal last reported it, which covers the keyboard repeat delay.
+const KEY_HOLD_FRAMES: u8 = 8;
+/// Pixels across each pixel of the screen in GIFs.
+const GIF_SCALE: u32 = 4;
+
+/// COSMAC VIP keypad layout on the left hand side of a QWERTY keyboard.
+const KEY_MAP: [(char, usize); 16] = [
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('4', 0xC),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('r', 0xD),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('f', 0xE),
+    ('z', 0xA),
+    ('x', 0x0),
+    ('c', 0xB),
+    ('v', 0xF),
+];
+
+struct Options {
+    rom_path: String,
+    /// Overrides the speed the game is known to need.
+    instructions_per_second: Option<u32>,
+    braille: bool,
+    coverage_path: Option<String>,
+    profile_path: Option<String>,
+    flamegraph_path: Option<String>,
+    trace_path: Option<String>,
+    differential: bool,
+    cheats_path: Option<String>,
+    patch_path: Option<String>,
+    game: Option<String>,
+    movie_path: Option<String>,
+    gif_path: Option<String>,
+    block_cache: bool,
+}
+
+/// Platform for the terminal: the buzzer rings the terminal bell and log
+/// messages end up in the status line.
+struct TerminalPlatform {
+    rng: XorShiftRng,
+    last_message: String,
+}
+
+impl RandomSource for TerminalPlatform {
+    fn random_byte(&mut self) -> u8 {
+        self.rng.random_byte()
+    }
+}
+
+impl Logger for TerminalPlatform {
+    fn log(&mut self, message: fmt::Arguments) {
+        self.last_message = message.to_string();
+    }
+}
+
+impl Audio for TerminalPlatform {
+    fn set_buzzer(&mut self, on: bool) {
+        if on {
+            let _ = io::stdout().write_all(b"\x07");
+        }
+    }
+}
+
+impl Observer for TerminalPlatform {}
+
+/// Restores the terminal when dropped, including while unwinding.
+struct RawTerminal {
+    enhanced_keyboard: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All)
+        )?;
+
+        let enhanced_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced_keyboard {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(RawTerminal { enhanced_keyboard })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        restore_terminal(self.enhanced_keyboard);
+    }
+}
+
+fn restore_terminal(enhanced_keyboard: bool) {
+    let mut stdout = io::stdout();
+    if enhanced_keyboard {
+        let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(stdout, Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!(
+                "usage: chip8-tui [--ips <instructions per second>] [--braille] [--coverage <file>] \
+                 [--profile <file>] [--flamegraph <file>] [--trace <file>] [--differential] \
+                 [--cheats <file>] [--patch <file>] [--game <name>] [--movie <file>] \
+                 [--gif <file>] [--block-cache] <rom>"
+            );
+            process::exit(2);
+        }
+    };
+
+    let (mut rom, metadata) = match load_game(&options) {
+        Ok(game) => game,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
+    if let Some(path) = &options.patch_path {
+        let patched = fs::read(path)
+            .map_err(|error| format!("Failed to read {}: {}", path, error))
+            .and_then(|patch| {
+                apply_patch(&rom, &patch)
+                    .map_err(|error| format!("Invalid patch in {}: {:?}", path, error))
+            });
+        rom = match patched {
+            Ok(patched) => patched,
+            Err(message) => {
+                eprintln!("{}", message);
+                process::exit(1);
+            }
+        };
+    }
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The panic message would otherwise be lost on the alternate screen.
+        restore_terminal(true);
+        default_hook(info);
+    }));
+
+    if let Err(error) = run(&options, &rom, &metadata) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut instructions_per_second = None;
+    let mut braille = false;
+    let mut coverage_path = None;
+    let mut profile_path = None;
+    let mut flamegraph_path = None;
+    let mut trace_path = None;
+    let mut differential = false;
+    let mut cheats_path = None;
+    let mut patch_path = None;
+    let mut game = None;
+    let mut movie_path = None;
+    let mut gif_path = None;
+    let mut block_cache = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ips" => {
+                let value = args.next().ok_or("--ips needs a value")?;
+                instructions_per_second = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|ips| *ips > 0)
+                        .ok_or_else(|| format!("Invalid --ips value {}", value))?,
+                );
+            }
+            "--braille" => braille = true,
+            "--coverage" => {
+                coverage_path = Some(args.next().ok_or("--coverage needs a file")?);
+            }
+            "--profile" => profile_path = Some(args.next().ok_or("--profile needs a file")?),
+            "--flamegraph" => {
+                flamegraph_path = Some(args.next().ok_or("--flamegraph needs a file")?);
+            }
+            "--trace" => trace_path = Some(args.next().ok_or("--trace needs a file")?),
+            "--differential" => differential = true,
+            "--cheats" => cheats_path = Some(args.next().ok_or("--cheats needs a file")?),
+            "--patch" => patch_path = Some(args.next().ok_or("--patch needs a file")?),
+            "--game" => game = Some(args.next().ok_or("--game needs a name")?),
+            "--movie" => movie_path = Some(args.next().ok_or("--movie needs a file")?),
+            "--gif" => gif_path = Some(args.next().ok_or("--gif needs a file")?),
+            "--block-cache" => block_cache = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    // The movie decides every input, and cheats would make it play out
+    // differently.
+    if movie_path.is_some() && (cheats_path.is_some() || differential) {
+        return Err("--movie can't be used with --cheats or --differential".to_string());
+    }
+    if block_cache && (movie_path.is_some() || differential) {
+        return Err("--block-cache can't be used with --movie or --differential".to_string());
+    }
+
+    Ok(Options {
+        rom_path: rom_path.ok_or("No ROM given")?,
+        instructions_per_second,
+        braille,
+        coverage_path,
+        profile_path,
+        flamegraph_path,
+        trace_path,
+        differential,
+        cheats_path,
+        patch_path,
+        game,
+        movie_path,
+        gif_path,
+        block_cache,
+    })
+}
+
+/// Reads the ROM, or picks one out of a directory or zip archive of them.
+fn load_game(options: &Options) -> Result<(Vec<u8>, GameMetadata), String> {
+    let path = Path::new(&options.rom_path);
+    let failed = |error: io::Error| format!("Failed to read {}: {}", options.rom_path, error);
+    let is_zip = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    if !is_zip && !path.is_dir() {
+        let rom = fs::read(path).map_err(failed)?;
+        return Ok((rom, GameMetadata::default()));
+    }
+
+    let files = if is_zip {
+        read_zip(&fs::read(path).map_err(failed)?)
+    } else {
+        Ok(read_directory(path).map_err(failed)?)
+    };
+    let mut library = GameLibrary::new();
+    files
+        .and_then(|files| add_files(&mut library, files))
+        .map_err(|error| format!("Invalid games in {}: {:?}", options.rom_path, error))?;
+
+    let game = match &options.game {
+        Some(name) => library
+            .get(name)
+            .ok_or_else(|| format!("No game named {} in {}", name, options.rom_path))?,
+        None if library.len() == 1 => library.iter().next().unwrap(),
+        None => {
+            let names = library.names().collect::<Vec<_>>().join("\n");
+            return Err(format!(
+                "Pick a game in {} with --game:\n{}",
+                options.rom_path, names
+            ));
+        }
+    };
+
+    Ok((game.rom.clone(), game.metadata.clone()))
+}
+
+fn run(options: &Options, rom: &[u8], metadata: &GameMetadata) -> io::Result<()> {
+    let mut player = match &options.movie_path {
+        None => None,
+        Some(path) => Some(load_movie(path)?),
+    };
+    // Movies replay the random numbers they were recorded with.
+    let seed = match &player {
+        Some(player) => player.get_movie().rng_seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0),
+    };
+    let mut cpu = Chip8CPU::with_platform(TerminalPlatform {
+        rng: XorShiftRng::new(seed),
+        last_message: String::new(),
+    });
+    if options.coverage_path.is_some() {
+        cpu.enable_coverage();
+    }
+    if options.profile_path.is_some() || options.flamegraph_path.is_some() {
+        cpu.enable_profiler();
+    }
+    let cycles_per_frame = match (&player, options.instructions_per_second) {
+        (Some(player), _) => player.get_movie().instructions_per_frame,
+        (None, Some(instructions_per_second)) => (instructions_per_second / 60).max(1),
+        (None, None) => metadata.instructions_per_frame.unwrap_or(DEFAULT_IPS / 60),
+    };
+    match &player {
+        Some(player) => player.prepare(&mut cpu, rom).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The movie can't be played on this ROM: {:?}", error),
+            )
+        })?,
+        None => {
+            if let Some(quirks) = metadata.quirks {
+                cpu.set_quirks(quirks);
+            }
+            cpu.load_rom(rom).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The ROM can't be loaded: {:?}", error),
+                )
+            })?;
+        }
+    }
+    let mut trace_file = match &options.trace_path {
+        None => None,
+        Some(path) => {
+            // Drained every frame, so a frame's worth of lines is never dropped.
+            cpu.enable_trace(cycles_per_frame as usize);
+            Some(BufWriter::new(fs::File::create(path)?))
+        }
+    };
+
+    let mut cheats = match &options.cheats_path {
+        None => None,
+        Some(path) => Some(load_cheats(path, rom)?),
+    };
+    let mut differential = if options.differential {
+        Some(Differential::new(&cpu))
+    } else {
+        None
+    };
+    let mut engine = if options.block_cache {
+        Some(BlockEngine::new())
+    } else {
+        None
+    };
+
+    let mut gif = match &options.gif_path {
+        None => None,
+        Some(path) => {
+            let recorder = GifRecorder::new(
+                cpu.display.width,
+                cpu.display.height,
+                GIF_SCALE,
+                &MONOCHROME,
+            )
+            .expect("GIF_SCALE is valid");
+            Some((recorder, BufWriter::new(fs::File::create(path)?)))
+        }
+    };
+
+    let terminal = RawTerminal::enter()?;
+    let mut held_frames = [0u8; 16];
+
+    'frames: loop {
+        let frame_start = Instant::now();
+
+        while event::poll(Duration::ZERO)? {
+            let key_event = match event::read()? {
+                Event::Key(key_event) => key_event,
+                _ => continue,
+            };
+            let character = match key_event.code {
+                KeyCode::Esc => break 'frames,
+                KeyCode::Char(character) => character.to_ascii_lowercase(),
+                _ => continue,
+            };
+            let key = match KEY_MAP.iter().find(|(mapped, _)| *mapped == character) {
+                None => continue,
+                Some((_, key)) => *key,
+            };
+
+            held_frames[key] = match key_event.kind {
+                KeyEventKind::Release => 0,
+                // With release events the key stays down until released.
+                _ if terminal.enhanced_keyboard => u8::MAX,
+                _ => KEY_HOLD_FRAMES,
+            };
+        }
+
+        match player.as_mut() {
+            Some(player) => {
+                if !player.run_frame(&mut cpu) {
+                    break 'frames;
+                }
+            }
+            None => {
+                held_frames
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(key, frames)| {
+                        cpu.set_key(key, *frames > 0);
+                        if *frames > 0 && *frames != u8::MAX {
+                            *frames -= 1;
+                        }
+                    });
+
+                match (differential.as_mut(), engine.as_mut()) {
+                    (None, None) => cpu.run_frame(cycles_per_frame),
+                    (None, Some(engine)) => engine.run_frame(&mut cpu, cycles_per_frame),
+                    (Some(differential), _) => {
+                        if let Err(mismatch) = differential.run_frame(&mut cpu, cycles_per_frame) {
+                            drop(terminal);
+                            return Err(io::Error::other(mismatch.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(cheats) = cheats.as_mut() {
+            cheats.apply(&mut cpu);
+            // Cheats can write anywhere, code included.
+            if let Some(engine) = engine.as_mut() {
+                engine.invalidate_all();
+            }
+        }
+        if let (Some(file), Some(trace)) = (trace_file.as_mut(), cpu.get_trace_mut()) {
+            for entry in trace.drain() {
+                writeln!(file, "{}", entry)?;
+            }
+        }
+        if let Some((recorder, file)) = gif.as_mut() {
+            recorder.add_frame(&Bitmap::from_display(&cpu.display));
+            file.write_all(&recorder.take_output())?;
+        }
+        draw(&cpu, options.braille)?;
+        if cpu.is_halted() {
+            break 'frames;
+        }
+
+        if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    drop(terminal);
+    if let Some(mut file) = trace_file {
+        file.flush()?;
+    }
+    if let Some((recorder, mut file)) = gif {
+        file.write_all(&recorder.finish())?;
+        file.flush()?;
+    }
+    if let (Some(path), Some(coverage)) = (&options.coverage_path, cpu.get_coverage()) {
+        fs::write(path, coverage.annotated_dump(rom))?;
+    }
+    if let Some(profiler) = cpu.get_profiler() {
+        if let Some(path) = &options.profile_path {
+            fs::write(path, profiler.flat_profile())?;
+        }
+        if let Some(path) = &options.flamegraph_path {
+            fs::write(path, profiler.folded_stacks())?;
+        }
+    }
+    if cpu.is_halted() {
+        let program_counter = cpu.get_program_counter() as usize;
+        let opcode = cpu.read_range(program_counter, 2).unwrap_or(&[0, 0]);
+        return Err(io::Error::other(format!(
+            "Stopped at unknown opcode {:02X}{:02X} at {:03X}",
+            opcode[0], opcode[1], program_counter
+        )));
+    }
+
+    Ok(())
+}
+
+fn load_cheats(path: &str, rom: &[u8]) -> io::Result<CheatList> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let cheats = CheatList::parse(&fs::read_to_string(path)?)
+        .map_err(|error| invalid(format!("Invalid cheats in {}: {:?}", path, error)))?;
+    if cheats.rom_crc32 != crc32(rom) {
+        return Err(invalid(format!(
+            "The cheats in {} are for another ROM",
+            path
+        )));
+    }
+
+    Ok(cheats)
+}
+
+fn load_movie(path: &str) -> io::Result<MoviePlayer> {
+    let movie = Movie::from_bytes(&fs::read(path)?).map_err(|error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid movie in {}: {:?}", path, error),
+        )
+    })?;
+
+    Ok(MoviePlayer::new(movie))
+}
+
+fn draw(cpu: &Chip8CPU<TerminalPlatform>, braille: bool) -> io::Result<()> {
+    let display = &cpu.display;
+    let screen = if braille {
+        render_braille(display.get_buffer(), display.width, display.height)
+    } else {
+        render_half_blocks(display.get_buffer(), display.width, display.height)
+    };
+    let panel = render_panel(cpu);
+    let screen_width = screen.first().map(|line| line.chars().count()).unwrap_or(0) as u16;
+
+    let mut stdout = io::stdout();
+    for (row, line) in screen.iter().enumerate() {
+        queue!(stdout, MoveTo(0, row as u16), Print(line))?;
+    }
+    for (row, line) in panel.iter().enumerate() {
+        queue!(stdout, MoveTo(screen_width + 2, row as u16), Print(line))?;
+    }
+    queue!(
+        stdout,
+        MoveTo(0, screen.len().max(panel.len()) as u16 + 1),
+        Clear(ClearType::CurrentLine),
+        Print(&cpu.platform().last_message)
+    )?;
+
+    stdout.flush()
+}
+
+/// Two vertically stacked pixels per character.
+fn render_half_blocks(buffer: &[u8], width: u32, height: u32) -> Vec<String> {
+    let pixel =
+        |column: u32, row: u32| row < height && buffer[(row * width + column) as usize] != 0;
+
+    (0..height)
+        .step_by(2)
+        .map(|row| {
+            (0..width)
+                .map(
+                    |column| match (pixel(column, row), pixel(column, row + 1)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+/// A 2x4 block of pixels per character.
+fn render_braille(buffer: &[u8], width: u32, height: u32) -> Vec<String> {
+    // Braille dot bits, indexed by [row][column] within the cell.
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let pixel = |column: u32, row: u32| {
+        column < width && row < height && buffer[(row * width + column) as usize] != 0
+    };
+
+    (0..height)
+        .step_by(4)
+        .map(|row| {
+            (0..width)
+                .step_by(2)
+                .map(|column| {
+                    let mut bits = 0;
+                    (0..4).for_each(|dot_row| {
+                        (0..2).for_each(|dot_column| {
+                            if pixel(column + dot_column, row + dot_row) {
+                                bits |= DOTS[dot_row as usize][dot_column as usize];
+                            }
+                        })
+                    });
+
+                    char::from_u32(0x2800 + bits).unwrap_or(' ')
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn render_panel(cpu: &Chip8CPU<TerminalPlatform>) -> Vec<String> {
+    let registers = cpu.get_registers();
+    let mut lines = (0..8)
+        .map(|index| {
+            format!(
+                "V{:X} {:02X}   V{:X} {:02X}",
+                index,
+                registers[index],
+                index + 8,
+                registers[index + 8]
+            )
+        })
+        .collect::<Vec<String>>();
+    lines.push(String::new());
+    lines.push(format!("PC {:04X}", cpu.get_program_counter()));
+    lines.push(format!("I  {:04X}", cpu.get_index_register()));
+    lines.push(format!("SP {:02X}", cpu.get_stack_pointer()));
+    lines.push(format!(
+        "DT {:02X}   ST {:02X}",
+        cpu.get_delay_timer(),
+        cpu.get_sound_timer()
+    ));
+
+    lines
+}
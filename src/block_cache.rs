@@ -0,0 +1,198 @@
+//! An execution engine that decodes straight-line runs of instructions once and
+//! replays the decoded instructions from a cache, instead of fetching and
+//! decoding every opcode on every cycle like [`Chip8CPU::cycle`].
+//!
+//! Blocks are keyed by their start address and end after the first control
+//! flow instruction. Instructions that write memory (FX33 and FX55) drop
+//! every block covering the written bytes, so self-modifying code still
+//! behaves exactly like the plain interpreter.
+
+use crate::chip8_cpu::{Chip8CPU, Processor, MEMORY_SIZE};
+use crate::instruction::Instruction;
+use crate::traits::Platform;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Upper bound on the instructions in a block, so a block over a long run of
+/// straight-line code doesn't have to be decoded in one go.
+const MAX_BLOCK_LENGTH: usize = 64;
+
+struct Block {
+    start: u16,
+    instructions: Vec<Instruction>,
+}
+
+impl Block {
+    fn end(&self) -> usize {
+        self.start as usize + self.instructions.len() * 2
+    }
+}
+
+pub struct BlockEngine {
+    /// Cached blocks by start address.
+    blocks: Vec<Option<Block>>,
+    /// Start addresses of the cached blocks.
+    block_starts: Vec<u16>,
+    /// Number of cached blocks containing each byte of memory.
+    coverage: Vec<u8>,
+    /// Block and instruction index of the next instruction, if the program
+    /// counter is still where the previous instruction left it.
+    cursor: Option<(u16, usize)>,
+}
+
+impl BlockEngine {
+    pub fn new() -> BlockEngine {
+        BlockEngine {
+            blocks: (0..MEMORY_SIZE).map(|_| None).collect(),
+            block_starts: Vec::new(),
+            coverage: vec![0; MEMORY_SIZE],
+            cursor: None,
+        }
+    }
+
+    /// Number of blocks currently cached.
+    pub fn len(&self) -> usize {
+        self.block_starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_starts.is_empty()
+    }
+
+    /// Drops every cached block. Needed whenever memory changes behind the
+    /// engine's back, like loading a ROM or a saved state.
+    pub fn invalidate_all(&mut self) {
+        for start in self.block_starts.drain(..) {
+            self.blocks[start as usize] = None;
+        }
+        self.coverage.iter_mut().for_each(|count| *count = 0);
+        self.cursor = None;
+    }
+
+    /// Drops the blocks containing any of the `length` bytes from `start`.
+    pub fn invalidate_range(&mut self, start: usize, length: usize) {
+        let end = (start + length).min(MEMORY_SIZE);
+        if start >= end || self.coverage[start..end].iter().all(|count| *count == 0) {
+            return;
+        }
+
+        let blocks = &mut self.blocks;
+        let coverage = &mut self.coverage;
+        self.block_starts.retain(|block_start| {
+            let block = match &blocks[*block_start as usize] {
+                None => return false,
+                Some(block) => block,
+            };
+            if block.end() <= start || block.start as usize >= end {
+                return true;
+            }
+
+            coverage[block.start as usize..block.end()]
+                .iter_mut()
+                .for_each(|count| *count -= 1);
+            blocks[*block_start as usize] = None;
+            false
+        });
+
+        if let Some((cursor_start, _)) = self.cursor {
+            if self.blocks[cursor_start as usize].is_none() {
+                self.cursor = None;
+            }
+        }
+    }
+
+    /// Equivalent of [`Chip8CPU::cycle`].
+    pub fn cycle<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>) {
+        if cpu.is_halted() {
+            return;
+        }
+        let program_counter = cpu.get_program_counter();
+        let (start, index) = match self.cursor {
+            Some((start, index)) if start as usize + index * 2 == program_counter as usize => {
+                (start, index)
+            }
+            _ => (self.compile(cpu, program_counter), 0),
+        };
+
+        let block = self.blocks[start as usize]
+            .as_ref()
+            .expect("Cursor points at a cached block");
+        let instruction = block.instructions[index];
+        self.cursor = if index + 1 < block.instructions.len() {
+            Some((start, index + 1))
+        } else {
+            None
+        };
+
+        let written = Self::written_range(cpu, instruction);
+        Processor::execute(cpu, instruction);
+        if let Some((start, length)) = written {
+            self.invalidate_range(start, length);
+        }
+
+        if !cpu.is_halted() {
+            Processor::update_timers(cpu);
+        }
+    }
+
+    /// Equivalent of [`Chip8CPU::run_frame`].
+    pub fn run_frame<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>, instructions_per_frame: u32) {
+        (0..instructions_per_frame).for_each(|_| self.cycle(cpu));
+        cpu.end_frame();
+    }
+
+    /// Decodes the block starting at `address`, unless it's already cached.
+    fn compile<P: Platform>(&mut self, cpu: &Chip8CPU<P>, address: u16) -> u16 {
+        if self.blocks[address as usize].is_some() {
+            return address;
+        }
+
+        let mut instructions = Vec::new();
+        let mut instruction_address = address;
+        loop {
+            let opcode = Processor::fetch_opcode_at(cpu, instruction_address);
+            let instruction = Instruction::decode(opcode);
+            instructions.push(instruction);
+
+            instruction_address += 2;
+            if instruction.is_control_flow()
+                || instructions.len() == MAX_BLOCK_LENGTH
+                || instruction_address as usize + 1 >= MEMORY_SIZE
+            {
+                break;
+            }
+        }
+
+        let block = Block {
+            start: address,
+            instructions,
+        };
+        self.coverage[block.start as usize..block.end()]
+            .iter_mut()
+            .for_each(|count| *count += 1);
+        self.blocks[address as usize] = Some(block);
+        self.block_starts.push(address);
+
+        address
+    }
+
+    /// The memory an instruction is about to write, as start and length.
+    pub(crate) fn written_range<P: Platform>(
+        cpu: &Chip8CPU<P>,
+        instruction: Instruction,
+    ) -> Option<(usize, usize)> {
+        let index_register = cpu.get_index_register() as usize;
+        match instruction {
+            Instruction::StoreBcd { .. } => Some((index_register, 3)),
+            Instruction::StoreRegisters { x } => Some((index_register, x + 1)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for BlockEngine {
+    fn default() -> BlockEngine {
+        BlockEngine::new()
+    }
+}
@@ -0,0 +1,244 @@
+//! RAM search, to find where a game keeps its lives or score, and cheats that
+//! write those addresses every frame.
+//!
+//! # Cheat file format
+//!
+//! Plain text, one entry per line. Blank lines and lines starting with `#` are
+//! ignored. The first entry names the ROM the cheats are for by its CRC-32,
+//! then each cheat is its kind, address and value in hexadecimal, and an
+//! optional description. A leading `-` keeps a cheat in the file but disabled.
+//!
+//! ```text
+//! rom 1A2B3C4D
+//! freeze 2F0 03 Infinite lives
+//! -set 2F4 09 Start on level 9
+//! ```
+//!
+//! A `freeze` cheat writes its value every frame, a `set` cheat once, on the
+//! first frame after it's enabled.
+
+use crate::chip8_cpu::{Chip8CPU, MEMORY_SIZE};
+use crate::traits::Platform;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// How a value has to relate to the previous search for its address to stay a
+/// candidate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchRelation {
+    EqualTo(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl SearchRelation {
+    fn holds(self, previous: u8, current: u8) -> bool {
+        match self {
+            SearchRelation::EqualTo(value) => current == value,
+            SearchRelation::Changed => current != previous,
+            SearchRelation::Unchanged => current == previous,
+            SearchRelation::Increased => current > previous,
+            SearchRelation::Decreased => current < previous,
+        }
+    }
+}
+
+/// Narrows the whole of memory down to the addresses whose values behaved a
+/// certain way between searches, typically a few frames apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RamSearch {
+    previous: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    /// Starts with every address as a candidate.
+    pub fn new(memory: &[u8; MEMORY_SIZE]) -> RamSearch {
+        RamSearch {
+            previous: memory.to_vec(),
+            candidates: (0..MEMORY_SIZE as u16).collect(),
+        }
+    }
+
+    /// Keeps the candidates for which `relation` holds between the previous
+    /// search and `memory`, returning how many are left.
+    pub fn filter(&mut self, memory: &[u8; MEMORY_SIZE], relation: SearchRelation) -> usize {
+        let previous = &self.previous;
+        self.candidates.retain(|address| {
+            let address = *address as usize;
+            relation.holds(previous[address], memory[address])
+        });
+        self.previous.copy_from_slice(memory);
+
+        self.candidates.len()
+    }
+
+    pub fn get_candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// The value of a candidate as of the last search.
+    pub fn get_previous_value(&self, address: u16) -> Option<u8> {
+        self.previous.get(address as usize).copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatKind {
+    /// Written every frame.
+    Freeze,
+    /// Written once.
+    Set,
+}
+
+impl CheatKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CheatKind::Freeze => "freeze",
+            CheatKind::Set => "set",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub kind: CheatKind,
+    pub address: u16,
+    pub value: u8,
+    pub description: String,
+    pub enabled: bool,
+    /// Whether a `set` cheat has been written since it was enabled.
+    applied: bool,
+}
+
+impl Cheat {
+    pub fn new(kind: CheatKind, address: u16, value: u8, description: &str) -> Cheat {
+        Cheat {
+            kind,
+            address,
+            value,
+            description: description.to_string(),
+            enabled: true,
+            applied: false,
+        }
+    }
+
+    /// Enabling a `set` cheat again writes it again.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.applied = false;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatError {
+    /// The file doesn't say which ROM it's for before its first cheat.
+    MissingRom,
+    /// The line, counting from 1, isn't a valid entry.
+    InvalidLine(usize),
+    /// The cheat's address is past the end of memory.
+    AddressOutOfRange(u16),
+}
+
+/// The cheats for one ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheatList {
+    pub rom_crc32: u32,
+    pub cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    pub fn new(rom_crc32: u32) -> CheatList {
+        CheatList {
+            rom_crc32,
+            cheats: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, cheat: Cheat) -> Result<(), CheatError> {
+        if cheat.address as usize >= MEMORY_SIZE {
+            return Err(CheatError::AddressOutOfRange(cheat.address));
+        }
+        self.cheats.push(cheat);
+
+        Ok(())
+    }
+
+    /// Writes the enabled cheats to memory. Run it after every frame.
+    pub fn apply<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>) {
+        for cheat in self.cheats.iter_mut().filter(|cheat| cheat.enabled) {
+            if cheat.kind == CheatKind::Set && cheat.applied {
+                continue;
+            }
+            // Addresses are checked when the cheat is added.
+            let _ = cpu.poke(cheat.address as usize, cheat.value);
+            cheat.applied = true;
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<CheatList, CheatError> {
+        let mut list: Option<CheatList> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = CheatError::InvalidLine(index + 1);
+
+            if let Some(hash) = line.strip_prefix("rom ") {
+                let rom_crc32 = u32::from_str_radix(hash.trim(), 16).map_err(|_| invalid)?;
+                list = Some(CheatList::new(rom_crc32));
+                continue;
+            }
+
+            let list = list.as_mut().ok_or(CheatError::MissingRom)?;
+            let (enabled, line) = match line.strip_prefix('-') {
+                None => (true, line),
+                Some(line) => (false, line),
+            };
+            let mut parts = line.splitn(4, ' ');
+            let kind = match parts.next() {
+                Some("freeze") => CheatKind::Freeze,
+                Some("set") => CheatKind::Set,
+                _ => return Err(invalid),
+            };
+            let address = parts
+                .next()
+                .and_then(|address| u16::from_str_radix(address, 16).ok())
+                .ok_or(invalid)?;
+            let value = parts
+                .next()
+                .and_then(|value| u8::from_str_radix(value, 16).ok())
+                .ok_or(invalid)?;
+            let mut cheat = Cheat::new(kind, address, value, parts.next().unwrap_or("").trim());
+            cheat.enabled = enabled;
+            list.add(cheat)?;
+        }
+
+        list.ok_or(CheatError::MissingRom)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("rom {:08X}\n", self.rom_crc32);
+        for cheat in self.cheats.iter() {
+            let disabled = if cheat.enabled { "" } else { "-" };
+            let line = format!(
+                "{}{} {:03X} {:02X} {}",
+                disabled,
+                cheat.kind.name(),
+                cheat.address,
+                cheat.value,
+                cheat.description
+            );
+            writeln!(text, "{}", line.trim_end()).unwrap();
+        }
+
+        text
+    }
+}
@@ -1,18 +1,117 @@
 #[path = "./utils.rs"]
 mod utils;
 
-#[path = "./chip8_cpu.rs"]
-mod chip8_cpu;
-use chip8_cpu::Chip8CPU;
+use crate::analysis::analyze_rom;
+use crate::archive::load_zip;
+use crate::cheats::{Cheat, CheatKind, CheatList, RamSearch, SearchRelation};
+#[cfg(feature = "recompiler")]
+use crate::chip8_cpu::STATE_SIZE;
+use crate::chip8_cpu::{AccessError, Chip8CPU, KEY_COUNT};
+use crate::coverage::MemoryAccess;
+use crate::crc32::crc32;
+use crate::gif::GifRecorder;
+use crate::image::{
+    decode_pbm, encode_pbm, encode_png, screenshot, Bitmap, ImageFormat, Rgb, MONOCHROME,
+};
+use crate::library::{GameLibrary, GameMetadata, GamePlatform, LibraryError};
+use crate::movie::{Movie, MoviePlayer, MovieRecorder};
+use crate::patch::apply_patch;
+use crate::platform::{DefaultPlatform, XorShiftRng};
+#[cfg(feature = "recompiler")]
+use crate::recompiler;
+use crate::snapshot::{FieldValue, StateHistory, StateSnapshot};
+use crate::speed::{FramesDue, SpeedControl, SpeedError};
+use crate::sprite::SpriteSheet;
+use crate::trace::first_divergence;
+use crate::traits::{Audio, CpuEvent, Logger, Observer, RandomSource};
 
-use std::collections::HashMap;
+use std::fmt;
 
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+/// For games that don't say how fast they should run.
+const INSTRUCTIONS_PER_FRAME: u32 = 10;
+/// How long an uncapped fast-forward runs frames for in each call to
+/// [`Chip8::run_frame`], leaving the rest of a 60 Hz frame to the browser.
+const UNCAPPED_BUDGET_MS: f64 = 12.0;
+
+/// Platform for the browser build: a seeded RNG, so movies can replay a run,
+/// logging to the browser console, and events kept for the event callback.
+pub struct BrowserPlatform {
+    rng: XorShiftRng,
+    /// Receives the events, see [`Chip8::set_event_callback`].
+    event_callback: Option<js_sys::Function>,
+    events: Vec<CpuEvent>,
+}
+
+impl BrowserPlatform {
+    pub fn with_seed(seed: u64) -> BrowserPlatform {
+        BrowserPlatform {
+            rng: XorShiftRng::new(seed),
+            event_callback: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Hands the kept events to the event callback.
+    fn flush_events(&mut self) {
+        if let Some(callback) = &self.event_callback {
+            self.events.drain(..).for_each(|event| {
+                let _ = callback.call1(&JsValue::NULL, &event_to_js(event));
+            });
+        }
+    }
+}
+
+impl RandomSource for BrowserPlatform {
+    fn random_byte(&mut self) -> u8 {
+        self.rng.random_byte()
+    }
+}
+
+impl Logger for BrowserPlatform {
+    fn log(&mut self, message: fmt::Arguments) {
+        log(&message.to_string());
+    }
+}
+
+impl Audio for BrowserPlatform {}
+
+impl Observer for BrowserPlatform {
+    fn on_event(&mut self, event: CpuEvent) {
+        if self.event_callback.is_none() {
+            return;
+        }
+        self.events.push(event);
+    }
+}
+
 #[wasm_bindgen]
 pub struct Chip8 {
-    cpu: Chip8CPU,
-    games: HashMap<String, Vec<u8>>,
+    cpu: Chip8CPU<BrowserPlatform>,
+    games: GameLibrary,
+    /// The ROM currently loaded, and the seed its CPU was created with.
+    rom: Vec<u8>,
+    rng_seed: u64,
+    /// The current game's speed.
+    instructions_per_frame: u32,
+    recorder: Option<MovieRecorder>,
+    player: Option<MoviePlayer>,
+    gif_recorder: Option<GifRecorder>,
+    /// Receives every trace line when set, see [`Chip8::set_trace_callback`].
+    trace_callback: Option<js_sys::Function>,
+    /// For [`Chip8::get_state_diff`].
+    state_history: StateHistory,
+    ram_search: Option<RamSearch>,
+    /// Cheats for the current ROM.
+    cheats: CheatList,
+    speed: SpeedControl,
 }
 
 #[wasm_bindgen]
@@ -20,46 +119,409 @@ impl Chip8 {
     pub fn new() -> Chip8 {
         utils::set_panic_hook();
 
+        let rng_seed = rand::random::<u64>();
+
         Chip8 {
-            cpu: Chip8CPU::new(),
+            cpu: Chip8CPU::with_platform(BrowserPlatform::with_seed(rng_seed)),
             games: Self::make_games(),
+            rom: Vec::new(),
+            rng_seed,
+            instructions_per_frame: INSTRUCTIONS_PER_FRAME,
+            recorder: None,
+            player: None,
+            gif_recorder: None,
+            trace_callback: None,
+            state_history: StateHistory::default(),
+            ram_search: None,
+            cheats: CheatList::new(crc32(&[])),
+            speed: SpeedControl::new(),
         }
     }
 
-    /// Load a ROM by name.
+    /// Load a ROM by name, with the quirks and speed it's known to need.
     /// Returns void on success and throws a JavaScript on failure.
     pub fn load_rom(&mut self, game_name: String) -> Result<(), js_sys::Error> {
-        let game_data = match self.get_game_with_name(game_name) {
+        let (game_data, metadata) = match self.games.get(&game_name) {
             None => return Err(js_sys::Error::new("Invalid game provided").into()),
-            Some(game_data) => game_data,
+            Some(game) => (game.rom.clone(), &game.metadata),
         };
 
-        let mut new_cpu = Chip8CPU::new();
-        new_cpu.load_rom(game_data);
-        self.cpu = new_cpu;
+        // Games that don't say which quirks they need get the defaults, not
+        // whatever the last game used.
+        self.cpu.set_quirks(metadata.quirks.unwrap_or_default());
+        self.instructions_per_frame = metadata
+            .instructions_per_frame
+            .unwrap_or(INSTRUCTIONS_PER_FRAME);
+        self.restart(game_data, rand::random::<u64>());
+        self.recorder = None;
+        self.player = None;
 
         Ok(())
     }
 
     pub fn cycle(&mut self) {
         self.cpu.cycle();
+        self.flush_trace();
+        self.cpu.platform_mut().flush_events();
+    }
+
+    /// Press or release a key (0x0-0xF). Keys take effect from the next frame
+    /// and are ignored while a movie plays.
+    pub fn set_key(&mut self, key: usize, pressed: bool) -> Result<(), js_sys::Error> {
+        if key >= KEY_COUNT {
+            return Err(js_sys::Error::new("Invalid key provided"));
+        }
+        if self.player.is_some() {
+            return Ok(());
+        }
+
+        match self.recorder.as_mut() {
+            None => self.cpu.set_key(key, pressed),
+            Some(recorder) => recorder.set_key(&mut self.cpu, key, pressed),
+        }
+
+        Ok(())
     }
 
-    // pub fn on_key_press(&self, symbol: String, modifier: String) {}
+    /// Called once per 60 Hz display frame. Runs as many frames as the speed
+    /// calls for: none while paused, fewer in slow motion and more when
+    /// fast-forwarding.
+    pub fn run_frame(&mut self) {
+        match self.speed.frames_due() {
+            FramesDue::Count(count) => (0..count).for_each(|_| self.emulate_frame()),
+            FramesDue::Uncapped => {
+                let deadline = js_sys::Date::now() + UNCAPPED_BUDGET_MS;
+                while js_sys::Date::now() < deadline {
+                    self.emulate_frame();
+                }
+            }
+        }
+    }
 
-    // pub fn on_key_release(&self, symbol: String, modifier: String) {}
+    pub fn pause(&mut self) {
+        self.speed.set_paused(true);
+    }
 
-    /// Get games names as a JavaScript Array of strings.
+    pub fn resume(&mut self) {
+        self.speed.set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.speed.is_paused()
+    }
+
+    /// Pause, if running, and run exactly one frame.
+    pub fn advance_frame(&mut self) {
+        self.speed.set_paused(true);
+        self.emulate_frame();
+    }
+
+    /// Set the speed as a multiple of normal, from 0.1 to 10. The CPU and its
+    /// timers both scale, since they run in whole frames.
+    /// Throws a JavaScript error when the speed is out of range.
+    pub fn set_speed(&mut self, multiplier: f64) -> Result<(), js_sys::Error> {
+        let speed_percent = (multiplier * 100.0).round();
+        if !speed_percent.is_finite() || speed_percent < 0.0 {
+            return Err(js_sys::Error::new("Invalid speed provided"));
+        }
+        self.speed
+            .set_speed_percent(speed_percent as u32)
+            .map_err(speed_error)
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        f64::from(self.speed.get_speed_percent()) / 100.0
+    }
+
+    /// Fast-forward while `held`, e.g. between a key's keydown and keyup.
+    pub fn set_fast_forward(&mut self, held: bool) {
+        self.speed.set_fast_forward(held);
+    }
+
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.speed.is_fast_forwarding()
+    }
+
+    /// Let fast-forward run as many frames as the browser can keep up with,
+    /// rather than at 10x.
+    pub fn set_fast_forward_uncapped(&mut self, uncapped: bool) {
+        self.speed.set_uncapped(uncapped);
+    }
+
+    /// Restart the current ROM and record every key press from here on.
+    pub fn start_recording(&mut self) {
+        self.restart(self.rom.clone(), rand::random::<u64>());
+        self.player = None;
+        self.recorder = Some(MovieRecorder::new(
+            &self.cpu,
+            &self.rom,
+            self.rng_seed,
+            self.instructions_per_frame,
+        ));
+    }
+
+    /// Stop recording and get the movie file.
+    /// Throws a JavaScript error when nothing is being recorded, or the
+    /// recording is too long or too fast to save.
+    pub fn stop_recording(&mut self) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        match self.recorder.take() {
+            None => Err(js_sys::Error::new("Not recording")),
+            Some(recorder) => recorder
+                .finish()
+                .to_bytes()
+                .map(|bytes| js_sys::Uint8Array::from(bytes.as_slice()))
+                .map_err(|error| js_sys::Error::new(&format!("Invalid movie: {:?}", error))),
+        }
+    }
+
+    /// Play a movie file, loading the game it was recorded on.
+    /// Throws a JavaScript error when the movie is invalid or its game isn't available.
+    pub fn play_movie(&mut self, movie: &[u8]) -> Result<(), js_sys::Error> {
+        let movie = Movie::from_bytes(movie)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid movie: {:?}", error)))?;
+        let game_data = match self.games.find_by_crc32(movie.rom_crc32) {
+            None => return Err(js_sys::Error::new("The movie's game is not available")),
+            Some(game) => game.rom.clone(),
+        };
+
+        let player = MoviePlayer::new(movie);
+        let mut new_cpu =
+            Chip8CPU::with_platform(BrowserPlatform::with_seed(player.get_movie().rng_seed));
+        self.carry_over_debugging(&mut new_cpu);
+        self.cpu = new_cpu;
+        player
+            .prepare(&mut self.cpu, &game_data)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid movie: {:?}", error)))?;
+        self.set_rom(game_data);
+        self.rng_seed = player.get_movie().rng_seed;
+        self.recorder = None;
+        self.player = Some(player);
+
+        Ok(())
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        self.player.is_some()
+    }
+
+    /// Start recording every frame from here on into an animated GIF, each
+    /// pixel `scale` pixels across. `palette` is as for `screenshot`. Keeps
+    /// recording through loading games and playing movies.
+    /// Throws a JavaScript error when the scale or palette is invalid.
+    pub fn start_gif_recording(&mut self, scale: u32, palette: &[u8]) -> Result<(), js_sys::Error> {
+        let display = &self.cpu.display;
+        let gif_recorder = GifRecorder::new(
+            display.width,
+            display.height,
+            scale,
+            &parse_palette(palette)?,
+        )
+        .map_err(|error| js_sys::Error::new(&format!("Invalid GIF: {:?}", error)))?;
+        self.gif_recorder = Some(gif_recorder);
+
+        Ok(())
+    }
+
+    pub fn is_recording_gif(&self) -> bool {
+        self.gif_recorder.is_some()
+    }
+
+    /// Get the part of the GIF encoded since this was last called, to save
+    /// long recordings bit by bit. The parts and what `stop_gif_recording`
+    /// returns make up the file.
+    /// Throws a JavaScript error when no GIF is being recorded.
+    pub fn take_gif_data(&mut self) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        match self.gif_recorder.as_mut() {
+            None => Err(js_sys::Error::new("Not recording a GIF")),
+            Some(gif_recorder) => Ok(js_sys::Uint8Array::from(
+                gif_recorder.take_output().as_slice(),
+            )),
+        }
+    }
+
+    /// Stop recording the GIF and get the rest of it.
+    /// Throws a JavaScript error when no GIF is being recorded.
+    pub fn stop_gif_recording(&mut self) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        match self.gif_recorder.take() {
+            None => Err(js_sys::Error::new("Not recording a GIF")),
+            Some(gif_recorder) => Ok(js_sys::Uint8Array::from(gif_recorder.finish().as_slice())),
+        }
+    }
+
+    /// Get games names, sorted, as a JavaScript Array of strings.
     pub fn get_game_names(&self) -> js_sys::Array {
+        self.games.names().map(JsValue::from_str).collect()
+    }
+
+    /// Add a ROM to the games under a new name.
+    /// Throws a JavaScript error when the name is empty or already taken, or
+    /// the ROM is too large to fit in memory.
+    pub fn add_game(&mut self, name: String, rom: &[u8]) -> Result<(), js_sys::Error> {
+        self.games
+            .add(&name, rom.to_vec(), GameMetadata::default())
+            .map_err(library_error)
+    }
+
+    /// Add every ROM in a zip archive to the games, described by the
+    /// `manifest.json` in it if there is one, see `chip8_wasm::archive`.
+    /// Returns the names of the games added, as a JavaScript Array of strings.
+    /// Throws a JavaScript error, without adding any, when the archive or its
+    /// manifest is invalid or a name is already taken.
+    pub fn load_archive(&mut self, archive: &[u8]) -> Result<js_sys::Array, js_sys::Error> {
+        let names = load_zip(&mut self.games, archive)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid archive: {:?}", error)))?;
+
+        Ok(names.iter().map(|name| JsValue::from_str(name)).collect())
+    }
+
+    /// Throws a JavaScript error when there's no game by that name.
+    pub fn remove_game(&mut self, name: String) -> Result<(), js_sys::Error> {
+        self.games.remove(&name).map(|_| ()).map_err(library_error)
+    }
+
+    /// Throws a JavaScript error when there's no game by that name or the new
+    /// name is empty or already taken.
+    pub fn rename_game(&mut self, name: String, new_name: String) -> Result<(), js_sys::Error> {
+        self.games.rename(&name, &new_name).map_err(library_error)
+    }
+
+    /// Describe a game. `platform` is "chip8", "schip" or "xochip", `tags` an
+    /// Array of strings, and empty strings mean unknown.
+    /// The licence, quirks, speed and key help already known are kept.
+    /// Throws a JavaScript error when there's no game by that name or the
+    /// platform is invalid.
+    pub fn set_game_metadata(
+        &mut self,
+        name: String,
+        platform: &str,
+        author: String,
+        year: Option<u16>,
+        description: String,
+        tags: js_sys::Array,
+    ) -> Result<(), js_sys::Error> {
+        let platform = GamePlatform::from_name(platform)
+            .ok_or_else(|| js_sys::Error::new("Invalid platform provided"))?;
+        let known = match self.games.get(&name) {
+            None => return Err(js_sys::Error::new("Invalid game provided")),
+            Some(game) => game.metadata.clone(),
+        };
+        let metadata = GameMetadata {
+            platform,
+            author,
+            year,
+            description,
+            tags: tags.iter().filter_map(|tag| tag.as_string()).collect(),
+            ..known
+        };
+
+        self.games
+            .set_metadata(&name, metadata)
+            .map_err(library_error)
+    }
+
+    /// Get a game's metadata as an object with `platform`, `author`, `year`,
+    /// `description`, `tags`, `licence`, `quirks`, `instructionsPerFrame` and
+    /// `keyHelp`. `year`, `quirks` and `instructionsPerFrame` are undefined
+    /// when unknown.
+    /// Throws a JavaScript error when there's no game by that name.
+    pub fn get_game_metadata(&self, name: String) -> Result<js_sys::Object, js_sys::Error> {
+        let metadata = match self.games.get(&name) {
+            None => return Err(js_sys::Error::new("Invalid game provided")),
+            Some(game) => &game.metadata,
+        };
+
+        let object = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            js_sys::Reflect::set(&object, &JsValue::from_str(key), &value)
+                .expect("Setting on a plain object");
+        };
+        set("platform", JsValue::from_str(metadata.platform.name()));
+        set("author", JsValue::from_str(&metadata.author));
+        set(
+            "year",
+            metadata.year.map_or(JsValue::UNDEFINED, JsValue::from),
+        );
+        set("description", JsValue::from_str(&metadata.description));
+        set(
+            "tags",
+            metadata
+                .tags
+                .iter()
+                .map(|tag| JsValue::from_str(tag))
+                .collect::<js_sys::Array>()
+                .into(),
+        );
+        set("licence", JsValue::from_str(&metadata.licence));
+        set(
+            "quirks",
+            metadata.quirks.map_or(JsValue::UNDEFINED, |quirks| {
+                FieldValue::Quirks(quirks).to_js()
+            }),
+        );
+        set(
+            "instructionsPerFrame",
+            metadata
+                .instructions_per_frame
+                .map_or(JsValue::UNDEFINED, JsValue::from),
+        );
+        set("keyHelp", JsValue::from_str(&metadata.key_help));
+
+        Ok(object)
+    }
+
+    /// Get the names of the games with `query` in their name, author,
+    /// description or tags, ignoring case, sorted.
+    pub fn search_games(&self, query: &str) -> js_sys::Array {
         self.games
-            .iter()
-            .map(|game| JsValue::from_str(game.0))
-            .collect::<js_sys::Array>()
+            .search(query)
+            .map(|game| JsValue::from_str(&game.name))
+            .collect()
+    }
+
+    /// Get the names of the games with a tag, ignoring case, sorted.
+    pub fn get_games_with_tag(&self, tag: &str) -> js_sys::Array {
+        self.games
+            .with_tag(tag)
+            .map(|game| JsValue::from_str(&game.name))
+            .collect()
+    }
+
+    /// Apply an IPS or BPS patch to a game and add the result as a new game,
+    /// named after the original and the patch. Returns the new game's name.
+    /// Throws a JavaScript error when the game isn't available, the patch
+    /// is invalid or for another ROM, or the patched ROM doesn't fit in
+    /// memory.
+    pub fn apply_patch(
+        &mut self,
+        game_name: String,
+        patch: &[u8],
+    ) -> Result<String, js_sys::Error> {
+        let game = match self.games.get(&game_name) {
+            None => return Err(js_sys::Error::new("Invalid game provided")),
+            Some(game) => game,
+        };
+        let patched = apply_patch(&game.rom, patch)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid patch: {:?}", error)))?;
+        let mut metadata = game.metadata.clone();
+        if !metadata.has_tag("patched") {
+            metadata.tags.push("patched".to_string());
+        }
+
+        // The same patch on the same game gives the same ROM, so applying it
+        // again just names the one already added.
+        let patched_name = format!("{} (patch {:08X})", game_name, crc32(patch));
+        if self.games.get(&patched_name).is_none() {
+            self.games
+                .add(&patched_name, patched, metadata)
+                .map_err(library_error)?;
+        }
+
+        Ok(patched_name)
     }
 
     /// Get display buffer as a flat JavaScript array.
     pub fn get_display_buffer_array(&self) -> js_sys::Uint8Array {
-        self.cpu.display.get_buffer_array()
+        js_sys::Uint8Array::from(self.cpu.display.get_buffer())
     }
 
     /// Get display width.
@@ -72,26 +534,676 @@ impl Chip8 {
         self.cpu.display.height
     }
 
+    /// Get a static analysis of the current ROM as JSON: its control-flow
+    /// graph, subroutines, and which bytes are code or sprites.
+    pub fn get_rom_analysis(&self) -> String {
+        analyze_rom(&self.rom).to_json()
+    }
+
+    /// Get the current ROM's control-flow graph in Graphviz DOT format.
+    pub fn get_rom_graph(&self) -> String {
+        analyze_rom(&self.rom).to_dot()
+    }
+
+    /// Get the current ROM's sprites, as found by the analysis, laid out on a
+    /// sheet, as a "png" or "pbm" image.
+    /// Throws a JavaScript error when the format is invalid.
+    pub fn get_sprite_sheet(&self, format: &str) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        // Found sprites are always inside the ROM.
+        let sheet = SpriteSheet::find(&self.rom)
+            .render(&self.rom)
+            .expect("Rendering found sprites");
+        let image = match format {
+            "png" => encode_png(&sheet, &MONOCHROME),
+            "pbm" => encode_pbm(&sheet),
+            _ => return Err(js_sys::Error::new("Invalid format provided")),
+        };
+
+        Ok(js_sys::Uint8Array::from(image.as_slice()))
+    }
+
+    /// Get the screen as a "png", "pbm", "pgm" or "svg" image, each pixel
+    /// `scale` pixels across. `palette` is the unlit then lit colour as RGB
+    /// bytes, or empty for black on white.
+    /// Throws a JavaScript error when the format, scale or palette is invalid.
+    pub fn screenshot(
+        &self,
+        format: &str,
+        scale: u32,
+        palette: &[u8],
+    ) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        let format = ImageFormat::from_name(format)
+            .ok_or_else(|| js_sys::Error::new("Invalid format provided"))?;
+        let image = screenshot(&self.cpu.display, format, scale, &parse_palette(palette)?)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid screenshot: {:?}", error)))?;
+
+        Ok(js_sys::Uint8Array::from(image.as_slice()))
+    }
+
+    /// Get the current ROM with its sprites replaced by those on an edited
+    /// copy of the sheet from `get_sprite_sheet`, as a PBM, to add as a game.
+    /// Throws a JavaScript error when the sheet is invalid or doesn't match.
+    pub fn replace_sprites(&self, sheet: &[u8]) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        let sheet = decode_pbm(sheet)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid sheet: {:?}", error)))?;
+        let mut rom = self.rom.clone();
+        SpriteSheet::find(&self.rom)
+            .apply(&mut rom, &sheet)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid sheet: {:?}", error)))?;
+
+        Ok(js_sys::Uint8Array::from(rom.as_slice()))
+    }
+
+    /// Start counting how often each byte of memory is fetched, read and
+    /// written, from zero. Stays enabled when a game is loaded or restarted.
+    pub fn enable_coverage(&mut self) {
+        self.cpu.enable_coverage();
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.cpu.disable_coverage();
+    }
+
+    /// Get the coverage counts for every byte of memory, for `access` being
+    /// "fetch", "read" or "write".
+    /// Throws a JavaScript error when coverage is disabled or `access` is unknown.
+    pub fn get_coverage_heatmap(&self, access: &str) -> Result<js_sys::Uint32Array, js_sys::Error> {
+        let access = match access {
+            "fetch" => MemoryAccess::Fetch,
+            "read" => MemoryAccess::Read,
+            "write" => MemoryAccess::Write,
+            _ => {
+                return Err(js_sys::Error::new(&format!(
+                    "Unknown memory access: {}",
+                    access
+                )))
+            }
+        };
+        match self.cpu.get_coverage() {
+            None => Err(js_sys::Error::new("Coverage is not enabled")),
+            Some(coverage) => Ok(js_sys::Uint32Array::from(coverage.get_counts(access))),
+        }
+    }
+
+    /// Start profiling execution from zero. Stays enabled when a game is
+    /// loaded or restarted.
+    pub fn enable_profiler(&mut self) {
+        self.cpu.enable_profiler();
+    }
+
+    pub fn disable_profiler(&mut self) {
+        self.cpu.disable_profiler();
+    }
+
+    /// Get the flat profile: executions per opcode class and address, time
+    /// spent in each subroutine and instructions per frame.
+    /// Throws a JavaScript error when profiling is disabled.
+    pub fn get_profile_report(&self) -> Result<String, js_sys::Error> {
+        match self.cpu.get_profiler() {
+            None => Err(js_sys::Error::new("Profiling is not enabled")),
+            Some(profiler) => Ok(profiler.flat_profile()),
+        }
+    }
+
+    /// Get the call tree in the folded stack format read by flame graph tools.
+    /// Throws a JavaScript error when profiling is disabled.
+    pub fn get_profile_folded_stacks(&self) -> Result<String, js_sys::Error> {
+        match self.cpu.get_profiler() {
+            None => Err(js_sys::Error::new("Profiling is not enabled")),
+            Some(profiler) => Ok(profiler.folded_stacks()),
+        }
+    }
+
+    /// Start tracing executed instructions, keeping the latest `capacity`
+    /// lines. Stays enabled when a game is loaded or restarted.
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.cpu.enable_trace(capacity);
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.cpu.disable_trace();
+    }
+
+    /// Call `callback` with every trace line, after each cycle or frame,
+    /// instead of keeping them. Pass `undefined` to keep them again.
+    pub fn set_trace_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.trace_callback = callback;
+    }
+
+    /// Call `callback` with an object for everything the program does that a
+    /// frontend might react to, after the `cycle` or `run_frame` it happened
+    /// in, instead of polling for it. Each has a `type`:
+    /// - "screenCleared"
+    /// - "spriteDrawn", with `x`, `y`, `height`, `address` and `collision`
+    /// - "soundStarted" and "soundStopped"
+    /// - "keyWaitStarted", with `register`, when FX0A waits for a key
+    /// - "keyWaitEnded", with `register` and `key`
+    /// - "subroutineCalled", with `address` and `returnAddress`
+    /// - "subroutineReturned", with `address`
+    /// - "halted", with `address` and `opcode`, when the game stops at an
+    ///   opcode the interpreter doesn't know, see [`Chip8::is_halted`]
+    ///
+    /// Pass `undefined` to stop.
+    pub fn set_event_callback(&mut self, callback: Option<js_sys::Function>) {
+        let platform = self.cpu.platform_mut();
+        platform.events.clear();
+        platform.event_callback = callback;
+    }
+
+    /// Get the kept trace lines, oldest first.
+    /// Throws a JavaScript error when tracing is disabled.
+    pub fn get_trace(&self) -> Result<String, js_sys::Error> {
+        match self.cpu.get_trace() {
+            None => Err(js_sys::Error::new("Tracing is not enabled")),
+            Some(trace) => Ok(trace.to_lines()),
+        }
+    }
+
+    /// Compare two traces, returning where they first differ, or `undefined`
+    /// when they're the same.
+    pub fn diff_traces(left: &str, right: &str) -> Option<String> {
+        first_divergence(left, right).map(|divergence| divergence.to_string())
+    }
+
+    /// Get a counter that moves whenever the machine state changes, so the
+    /// debugger only refreshes when it has to.
+    pub fn get_generation(&self) -> f64 {
+        self.cpu.get_generation() as f64
+    }
+
+    /// Get the machine state as a plain object: `registers`, `indexRegister`,
+    /// `programCounter`, the live `stack` frames, `stackPointer`, `delayTimer`,
+    /// `soundTimer`, `pressedKeys`, `quirks`, `mode` ("live", "recording" or
+    /// "playback"), `frameCount`, `cycleCount` and `generation`.
+    pub fn get_state(&self) -> js_sys::Object {
+        StateSnapshot::capture(&self.cpu, self.get_mode()).to_object(|_| true)
+    }
+
+    /// Get the same object as [`Chip8::get_state`], with only the properties
+    /// that changed after `since_generation`. Properties may be included
+    /// that changed back and forth since.
+    pub fn get_state_diff(&mut self, since_generation: f64) -> js_sys::Object {
+        let snapshot = StateSnapshot::capture(&self.cpu, self.get_mode());
+        self.state_history
+            .record(snapshot, self.cpu.get_generation(), since_generation as u64)
+    }
+
+    /// Start a RAM search with every address of memory as a candidate.
+    pub fn start_ram_search(&mut self) {
+        self.ram_search = Some(RamSearch::new(self.cpu.get_memory()));
+    }
+
+    /// Keep the candidates whose value is "equal" to `value`, or "changed",
+    /// "unchanged", "increased" or "decreased" since the last search, and
+    /// get how many are left.
+    /// Throws a JavaScript error when no search was started or the relation is unknown.
+    pub fn filter_ram_search(
+        &mut self,
+        relation: &str,
+        value: Option<u8>,
+    ) -> Result<usize, js_sys::Error> {
+        let relation = match (relation, value) {
+            ("equal", Some(value)) => SearchRelation::EqualTo(value),
+            ("changed", _) => SearchRelation::Changed,
+            ("unchanged", _) => SearchRelation::Unchanged,
+            ("increased", _) => SearchRelation::Increased,
+            ("decreased", _) => SearchRelation::Decreased,
+            _ => {
+                return Err(js_sys::Error::new(&format!(
+                    "Invalid search relation: {}",
+                    relation
+                )))
+            }
+        };
+        match self.ram_search.as_mut() {
+            None => Err(js_sys::Error::new("No RAM search started")),
+            Some(search) => Ok(search.filter(self.cpu.get_memory(), relation)),
+        }
+    }
+
+    /// Get the addresses still matching the RAM search.
+    /// Throws a JavaScript error when no search was started.
+    pub fn get_ram_search_candidates(&self) -> Result<js_sys::Uint16Array, js_sys::Error> {
+        match &self.ram_search {
+            None => Err(js_sys::Error::new("No RAM search started")),
+            Some(search) => Ok(js_sys::Uint16Array::from(search.get_candidates())),
+        }
+    }
+
+    /// Add a "freeze" or "set" cheat for the current ROM.
+    /// Throws a JavaScript error when the kind is unknown or the address is out of range.
+    pub fn add_cheat(
+        &mut self,
+        kind: &str,
+        address: u16,
+        value: u8,
+        description: &str,
+    ) -> Result<(), js_sys::Error> {
+        let kind = match kind {
+            "freeze" => CheatKind::Freeze,
+            "set" => CheatKind::Set,
+            _ => return Err(js_sys::Error::new(&format!("Invalid cheat kind: {}", kind))),
+        };
+        self.cheats
+            .add(Cheat::new(kind, address, value, description))
+            .map_err(|error| js_sys::Error::new(&format!("Invalid cheat: {:?}", error)))
+    }
+
+    /// Throws a JavaScript error when there's no cheat at `index`.
+    pub fn remove_cheat(&mut self, index: usize) -> Result<(), js_sys::Error> {
+        if index >= self.cheats.cheats.len() {
+            return Err(js_sys::Error::new("Invalid cheat index"));
+        }
+        self.cheats.cheats.remove(index);
+
+        Ok(())
+    }
+
+    /// Throws a JavaScript error when there's no cheat at `index`.
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> Result<(), js_sys::Error> {
+        match self.cheats.cheats.get_mut(index) {
+            None => Err(js_sys::Error::new("Invalid cheat index")),
+            Some(cheat) => {
+                cheat.set_enabled(enabled);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the current ROM's cheats as a cheat file.
+    pub fn get_cheats(&self) -> String {
+        self.cheats.to_text()
+    }
+
+    /// Replace the current ROM's cheats with a cheat file's.
+    /// Throws a JavaScript error when the file is invalid or for another ROM.
+    pub fn load_cheats(&mut self, text: &str) -> Result<(), js_sys::Error> {
+        let cheats = CheatList::parse(text)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid cheats: {:?}", error)))?;
+        if cheats.rom_crc32 != crc32(&self.rom) {
+            return Err(js_sys::Error::new("The cheats are for another game"));
+        }
+        self.cheats = cheats;
+
+        Ok(())
+    }
+
+    /// Read a byte of memory.
+    /// Throws a JavaScript error when the address is out of range.
+    pub fn peek(&self, address: usize) -> Result<u8, js_sys::Error> {
+        self.cpu.peek(address).map_err(access_error)
+    }
+
+    /// Write a byte of memory.
+    /// Throws a JavaScript error when the address is out of range.
+    pub fn poke(&mut self, address: usize, value: u8) -> Result<(), js_sys::Error> {
+        self.cpu.poke(address, value).map_err(access_error)
+    }
+
+    /// Read `length` bytes of memory from `start`.
+    /// Throws a JavaScript error when the range doesn't fit in memory.
+    pub fn read_range(
+        &self,
+        start: usize,
+        length: usize,
+    ) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        self.cpu
+            .read_range(start, length)
+            .map(js_sys::Uint8Array::from)
+            .map_err(access_error)
+    }
+
+    /// Write `bytes` to memory from `start`.
+    /// Throws a JavaScript error, writing nothing, when they don't fit in memory.
+    pub fn write_range(&mut self, start: usize, bytes: &[u8]) -> Result<(), js_sys::Error> {
+        self.cpu.write_range(start, bytes).map_err(access_error)
+    }
+
+    /// Get V0 to VF.
+    pub fn get_registers(&self) -> js_sys::Uint8Array {
+        js_sys::Uint8Array::from(&self.cpu.get_registers()[..])
+    }
+
+    /// Set VX, for `index` 0x0-0xF.
+    /// Throws a JavaScript error when the register doesn't exist.
+    pub fn set_register(&mut self, index: usize, value: u8) -> Result<(), js_sys::Error> {
+        self.cpu.set_register(index, value).map_err(access_error)
+    }
+
+    pub fn get_index_register(&self) -> u16 {
+        self.cpu.get_index_register()
+    }
+
+    /// Throws a JavaScript error when the address is out of range.
+    pub fn set_index_register(&mut self, address: u16) -> Result<(), js_sys::Error> {
+        self.cpu.set_index_register(address).map_err(access_error)
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.cpu.get_program_counter()
+    }
+
+    /// Throws a JavaScript error when the address is out of range.
+    pub fn set_program_counter(&mut self, address: u16) -> Result<(), js_sys::Error> {
+        self.cpu.set_program_counter(address).map_err(access_error)
+    }
+
+    /// Get all 16 stack entries, including those above the stack pointer.
+    pub fn get_stack(&self) -> js_sys::Uint16Array {
+        js_sys::Uint16Array::from(&self.cpu.get_stack()[..])
+    }
+
+    /// Throws a JavaScript error when the entry or address is out of range.
+    pub fn set_stack_entry(&mut self, index: usize, address: u16) -> Result<(), js_sys::Error> {
+        self.cpu
+            .set_stack_entry(index, address)
+            .map_err(access_error)
+    }
+
+    pub fn get_stack_pointer(&self) -> u8 {
+        self.cpu.get_stack_pointer()
+    }
+
+    /// Throws a JavaScript error when the stack pointer is past the stack.
+    pub fn set_stack_pointer(&mut self, stack_pointer: u8) -> Result<(), js_sys::Error> {
+        self.cpu
+            .set_stack_pointer(stack_pointer)
+            .map_err(access_error)
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.cpu.get_delay_timer()
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.cpu.set_delay_timer(value);
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.cpu.get_sound_timer()
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.cpu.set_sound_timer(value);
+    }
+
     pub fn get_draw_flag(&self) -> bool {
         self.cpu.draw_flag
     }
 
+    /// Whether the game is stopped at an FX0A waiting for a key, to show a
+    /// prompt or stop running frames until one is pressed.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.cpu.is_waiting_for_key()
+    }
+
+    /// Whether the game stopped at an opcode the interpreter doesn't know.
+    /// Loading a game or a state, or moving the program counter, starts it
+    /// again.
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
     pub fn end_cycle(&mut self) {
         self.cpu.draw_flag = false;
     }
 }
 
+#[cfg(feature = "recompiler")]
+#[wasm_bindgen]
+impl Chip8 {
+    /// Compile the current ROM to a WebAssembly module, see `www/compiled.js`
+    /// for how to run it.
+    /// Throws a JavaScript error when the ROM can't be compiled.
+    pub fn compile_rom(&self) -> Result<js_sys::Uint8Array, js_sys::Error> {
+        let compiled = recompiler::compile_rom(&self.rom, self.cpu.get_quirks())
+            .map_err(|error| js_sys::Error::new(&format!("Invalid ROM: {:?}", error)))?;
+
+        Ok(js_sys::Uint8Array::from(compiled.wasm.as_slice()))
+    }
+
+    pub fn get_state_size() -> usize {
+        STATE_SIZE
+    }
+
+    /// Get the machine state, laid out like the start of a compiled module's memory.
+    pub fn save_state(&self) -> js_sys::Uint8Array {
+        let mut state = vec![0; STATE_SIZE];
+        self.cpu
+            .save_state(&mut state)
+            .expect("The buffer fits a state");
+
+        js_sys::Uint8Array::from(state.as_slice())
+    }
+
+    /// Throws a JavaScript error when the state is invalid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), js_sys::Error> {
+        self.cpu
+            .load_state(state)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid state: {:?}", error)))
+    }
+
+    /// Run one instruction of a compiled module's state on the interpreter, for
+    /// when the module stops short. Returns the start and length of the memory
+    /// the instruction wrote, which must be passed on to the module's
+    /// `invalidate`, or an empty array.
+    /// The instruction runs on a spare CPU with the current quirks, so the
+    /// interpreter, its callbacks, trace and random numbers are left alone; load
+    /// the compiled state with [`Chip8::load_state`] to carry on from it.
+    /// Throws a JavaScript error when the state is invalid.
+    pub fn step_compiled_state(&self, state: &mut [u8]) -> Result<Vec<u32>, js_sys::Error> {
+        let mut cpu = Chip8CPU::with_platform(DefaultPlatform::with_seed(self.rng_seed));
+        cpu.set_quirks(self.cpu.get_quirks());
+        cpu.load_state(state)
+            .map_err(|error| js_sys::Error::new(&format!("Invalid state: {:?}", error)))?;
+        let written = recompiler::step(&mut cpu);
+        cpu.save_state(state)
+            .expect("The state was loaded from this buffer");
+
+        Ok(match written {
+            None => Vec::new(),
+            Some((start, length)) => vec![start as u32, length as u32],
+        })
+    }
+}
+
 impl Chip8 {
-    fn make_games() -> HashMap<String, Vec<u8>> {
-        let mut games = HashMap::new();
-        games.insert("PONG".to_string(), include_bytes!("games/PONG").to_vec());
-        games.insert("PONG2".to_string(), include_bytes!("games/PONG2").to_vec());
-        games.insert("TANK".to_string(), include_bytes!("games/TANK").to_vec());
+    fn make_games() -> GameLibrary {
+        let metadata = |description: &str, tags: &[&str]| GameMetadata {
+            description: description.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..GameMetadata::default()
+        };
+
+        let mut games = GameLibrary::new();
+        let builtin = [
+            (
+                "PONG",
+                include_bytes!("games/PONG").to_vec(),
+                metadata("Two player pong.", &["sports", "two-player"]),
+            ),
+            (
+                "PONG2",
+                include_bytes!("games/PONG2").to_vec(),
+                metadata("Two player pong, with a net.", &["sports", "two-player"]),
+            ),
+            (
+                "TANK",
+                include_bytes!("games/TANK").to_vec(),
+                metadata("Drive a tank and shoot the target.", &["action"]),
+            ),
+        ];
+        for (name, rom, metadata) in builtin.iter().cloned() {
+            games
+                .add(name, rom, metadata)
+                .expect("Built in games have distinct names");
+        }
 
         games
     }
 
-    fn get_game_with_name(&self, game_name: String) -> Option<&Vec<u8>> {
-        self.games.get(&game_name)
+    fn restart(&mut self, game_data: Vec<u8>, rng_seed: u64) {
+        let quirks = self.cpu.get_quirks();
+        let mut new_cpu = Chip8CPU::with_platform(BrowserPlatform::with_seed(rng_seed));
+        new_cpu.set_quirks(quirks);
+        self.carry_over_debugging(&mut new_cpu);
+        new_cpu
+            .load_rom(&game_data)
+            .expect("Games in the library fit in memory");
+        self.cpu = new_cpu;
+        self.set_rom(game_data);
+        self.rng_seed = rng_seed;
+    }
+
+    /// Switches the ROM, keeping the cheats only if it's the same one.
+    fn set_rom(&mut self, game_data: Vec<u8>) {
+        let rom_crc32 = crc32(&game_data);
+        if rom_crc32 != self.cheats.rom_crc32 {
+            self.cheats = CheatList::new(rom_crc32);
+        }
+        self.ram_search = None;
+        self.rom = game_data;
+    }
+
+    /// Enables the same coverage, profiling and tracing on a CPU replacing the
+    /// current one, starting from zero, and carries on its generation count
+    /// and event callback so the debugger sees the change.
+    fn carry_over_debugging(&self, new_cpu: &mut Chip8CPU<BrowserPlatform>) {
+        new_cpu.set_generation(self.cpu.get_generation() + 1);
+        new_cpu.platform_mut().event_callback = self.cpu.platform().event_callback.clone();
+        if self.cpu.get_coverage().is_some() {
+            new_cpu.enable_coverage();
+        }
+        if self.cpu.get_profiler().is_some() {
+            new_cpu.enable_profiler();
+        }
+        if let Some(trace) = self.cpu.get_trace() {
+            new_cpu.enable_trace(trace.get_capacity());
+        }
+    }
+
+    /// Hands the buffered trace lines to the trace callback, if there is one.
+    fn flush_trace(&mut self) {
+        let (callback, trace) = match (&self.trace_callback, self.cpu.get_trace_mut()) {
+            (Some(callback), Some(trace)) => (callback, trace),
+            _ => return,
+        };
+        trace.drain().for_each(|entry| {
+            let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(&entry.to_string()));
+        });
+    }
+
+    /// Run one 60 Hz frame. Cheats apply after it, unless a movie is being
+    /// recorded or played, since they'd make it impossible to replay.
+    fn emulate_frame(&mut self) {
+        if let Some(player) = self.player.as_mut() {
+            if !player.run_frame(&mut self.cpu) {
+                self.player = None;
+            }
+        } else {
+            match self.recorder.as_mut() {
+                None => {
+                    self.cpu.run_frame(self.instructions_per_frame);
+                    self.cheats.apply(&mut self.cpu);
+                }
+                Some(recorder) => recorder.run_frame(&mut self.cpu),
+            }
+        }
+        if let Some(gif_recorder) = self.gif_recorder.as_mut() {
+            gif_recorder.add_frame(&Bitmap::from_display(&self.cpu.display));
+        }
+
+        self.flush_trace();
+        self.cpu.platform_mut().flush_events();
+    }
+
+    fn get_mode(&self) -> &'static str {
+        if self.player.is_some() {
+            "playback"
+        } else if self.recorder.is_some() {
+            "recording"
+        } else {
+            "live"
+        }
+    }
+}
+
+fn access_error(error: AccessError) -> js_sys::Error {
+    js_sys::Error::new(&format!("Invalid access: {:?}", error))
+}
+
+fn speed_error(error: SpeedError) -> js_sys::Error {
+    js_sys::Error::new(&format!("Invalid speed: {:?}", error))
+}
+
+fn library_error(error: LibraryError) -> js_sys::Error {
+    js_sys::Error::new(&format!("Invalid game: {:?}", error))
+}
+
+fn event_to_js(event: CpuEvent) -> JsValue {
+    let object = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        js_sys::Reflect::set(&object, &JsValue::from_str(key), &value)
+            .expect("Setting on a plain object");
+    };
+    let kind = match event {
+        CpuEvent::ScreenCleared => "screenCleared",
+        CpuEvent::SpriteDrawn {
+            x,
+            y,
+            height,
+            address,
+            collision,
+        } => {
+            set("x", x.into());
+            set("y", y.into());
+            set("height", height.into());
+            set("address", address.into());
+            set("collision", collision.into());
+            "spriteDrawn"
+        }
+        CpuEvent::SoundStarted => "soundStarted",
+        CpuEvent::SoundStopped => "soundStopped",
+        CpuEvent::KeyWaitStarted { register } => {
+            set("register", register.into());
+            "keyWaitStarted"
+        }
+        CpuEvent::KeyWaitEnded { register, key } => {
+            set("register", register.into());
+            set("key", key.into());
+            "keyWaitEnded"
+        }
+        CpuEvent::SubroutineCalled {
+            address,
+            return_address,
+        } => {
+            set("address", address.into());
+            set("returnAddress", return_address.into());
+            "subroutineCalled"
+        }
+        CpuEvent::SubroutineReturned { address } => {
+            set("address", address.into());
+            "subroutineReturned"
+        }
+        CpuEvent::Halted { address, opcode } => {
+            set("address", address.into());
+            set("opcode", opcode.into());
+            "halted"
+        }
+    };
+    set("type", JsValue::from_str(kind));
+
+    object.into()
+}
+
+/// Colours given as RGB bytes, one after the other.
+fn parse_palette(palette: &[u8]) -> Result<Vec<Rgb>, js_sys::Error> {
+    if !palette.len().is_multiple_of(3) {
+        return Err(js_sys::Error::new("Invalid palette provided"));
     }
+
+    Ok(palette
+        .chunks(3)
+        .map(|colour| [colour[0], colour[1], colour[2]])
+        .collect())
 }
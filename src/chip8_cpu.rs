@@ -1,15 +1,48 @@
-#[path = "./traits.rs"]
-mod traits;
+use crate::coverage::MemoryAccess;
+use crate::display::{self, Display};
+use crate::instruction::Instruction;
+use crate::platform::DefaultPlatform;
+use crate::quirks::Quirks;
+use crate::traits::{CpuEvent, Platform};
 
-use traits::FillableVector;
+#[cfg(feature = "alloc")]
+use crate::coverage::CoverageMap;
+#[cfg(feature = "alloc")]
+use crate::profiler::Profiler;
+#[cfg(feature = "alloc")]
+use crate::trace::{TraceBuffer, TraceEntry};
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
 
-#[path = "./display.rs"]
-mod display;
-use display::Display;
+pub const MEMORY_SIZE: usize = 4096;
+pub const REGISTER_COUNT: usize = 16;
+pub const STACK_SIZE: usize = 16;
+pub const KEY_COUNT: usize = 16;
+/// Where ROMs are loaded, and where they start running.
+pub const PROGRAM_START: usize = 0x200;
+/// The most a ROM can have and still fit in memory after [`PROGRAM_START`].
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
 
-use rand::Rng;
-
-use wasm_bindgen::prelude::wasm_bindgen;
+const STATE_MAGIC: [u8; 4] = *b"C8ST";
+const STATE_VERSION: u8 = 3;
+/// Offsets of each field in a serialized machine state, see
+/// [`Chip8CPU::save_state`].
+pub const STATE_MEMORY_OFFSET: usize = STATE_MAGIC.len() + 1;
+pub const STATE_REGISTERS_OFFSET: usize = STATE_MEMORY_OFFSET + MEMORY_SIZE;
+pub const STATE_STACK_OFFSET: usize = STATE_REGISTERS_OFFSET + REGISTER_COUNT;
+pub const STATE_STACK_POINTER_OFFSET: usize = STATE_STACK_OFFSET + STACK_SIZE * 2;
+pub const STATE_PROGRAM_COUNTER_OFFSET: usize = STATE_STACK_POINTER_OFFSET + 1;
+pub const STATE_INDEX_REGISTER_OFFSET: usize = STATE_PROGRAM_COUNTER_OFFSET + 2;
+pub const STATE_DELAY_TIMER_OFFSET: usize = STATE_INDEX_REGISTER_OFFSET + 2;
+pub const STATE_SOUND_TIMER_OFFSET: usize = STATE_DELAY_TIMER_OFFSET + 1;
+pub const STATE_KEYS_OFFSET: usize = STATE_SOUND_TIMER_OFFSET + 1;
+pub const STATE_DISPLAY_OFFSET: usize = STATE_KEYS_OFFSET + KEY_COUNT;
+pub const STATE_DRAW_FLAG_OFFSET: usize = STATE_DISPLAY_OFFSET + display::BUFFER_SIZE;
+pub const STATE_BUZZER_OFFSET: usize = STATE_DRAW_FLAG_OFFSET + 1;
+pub const STATE_FRAME_COUNT_OFFSET: usize = STATE_BUZZER_OFFSET + 1;
+pub const STATE_KEY_WAIT_OFFSET: usize = STATE_FRAME_COUNT_OFFSET + 8;
+/// Size in bytes of a serialized machine state.
+pub const STATE_SIZE: usize = STATE_KEY_WAIT_OFFSET + 1;
 
 const FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -30,33 +63,84 @@ const FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The buffer is shorter than [`STATE_SIZE`].
+    BufferTooSmall,
+    /// The bytes don't start with a machine state header.
+    InvalidHeader,
+    /// The state was written by an incompatible version of the emulator.
+    UnsupportedVersion(u8),
+}
+
+/// Why a ROM couldn't be loaded, see [`Chip8CPU::load_rom`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RomError {
+    /// The ROM has this many bytes, more than [`MAX_ROM_SIZE`].
+    TooLarge(usize),
 }
 
-// To print in to the browser console.
-macro_rules! console_log {
-    ($($t:tt)*) => (log(&format_args!($($t)*).to_string()))
+/// How far FX0A has got waiting for a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyWait {
+    /// FX0A isn't waiting.
+    Idle,
+    /// Waiting for a key to go down.
+    Press,
+    /// The key went down, and has to come back up, see
+    /// [`Quirks::key_wait_release`].
+    Release(u8),
 }
 
-pub struct Chip8CPU {
+impl KeyWait {
+    /// 0 when idle, 1 waiting for a press, and 0x10 plus the key waiting for
+    /// its release.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            KeyWait::Idle => 0,
+            KeyWait::Press => 1,
+            KeyWait::Release(key) => 0x10 | key,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> KeyWait {
+        match byte {
+            0x10..=0x1F => KeyWait::Release(byte & 0xF),
+            1 => KeyWait::Press,
+            _ => KeyWait::Idle,
+        }
+    }
+}
+
+/// Why an inspector or editor access was refused, see [`Chip8CPU::peek`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessError {
+    /// The address, or the end of a range, is past the end of memory. For the
+    /// program counter the whole opcode has to fit.
+    AddressOutOfRange(usize),
+    /// Registers go from V0 to VF.
+    RegisterOutOfRange(usize),
+    /// The stack has [`STACK_SIZE`] entries, and the stack pointer can point at
+    /// most one past the last.
+    StackOutOfRange(usize),
+}
+
+pub struct Chip8CPU<P: Platform = DefaultPlatform> {
     /// For the CHIP8 virtual machine, the input comes from a 16-button keyboard
     /// (pretty convenient that the number of keys falls within a nibble). The
     /// machine is also fed with the programs it is supposed to run.
-    key_inputs: Vec<u8>,
+    key_inputs: [u8; KEY_COUNT],
     /// Display reference.
     pub display: Display,
     /// CHIP8 has memory that can hold up to 4096 bytes. This includes the
     /// interpreter itself, the fonts (more on this later), and where it loads the
     /// program it is supposed to run (from input).
-    memory: Vec<u8>,
+    memory: [u8; MEMORY_SIZE],
     /// The CHIP8 has 16 8-bit registers (usually referred to as Vx where x is the
     /// register number in Cogwood's reference). These are generally used to store
     /// values for operations. The last register, Vf, is mostly used for flags and
     /// should be avoided for use in programs.
-    gpio: Vec<u8>,
+    gpio: [u8; REGISTER_COUNT],
     /// 8-bit sound timer
     sound_timer: u8,
     /// 8-bit delay timer
@@ -66,380 +150,891 @@ pub struct Chip8CPU {
     /// 16-bit program counter
     program_counter: u16,
     /// A stack of at most 16 16-bit values, used for subroutine calls.
-    stack: Vec<u16>,
+    stack: [u16; STACK_SIZE],
     /// Current stack pointer index.
     stack_pointer: u8,
     /// Whether or not to draw.
     pub draw_flag: bool,
+    /// Interpreter behaviours the loaded ROM expects.
+    quirks: Quirks,
+    /// Whether the buzzer is currently sounding.
+    buzzer_on: bool,
+    /// Where FX0A is in waiting for a key.
+    key_wait: KeyWait,
+    /// Set by an unknown opcode, after which cycles do nothing.
+    halted: bool,
+    /// Number of frames run through [`Chip8CPU::run_frame`].
+    frame_count: u64,
+    /// Number of instructions executed.
+    cycle_count: u64,
+    /// Bumped whenever the machine state changes, see
+    /// [`Chip8CPU::get_generation`].
+    generation: u64,
+    /// Host services: random numbers, logging and audio.
+    platform: P,
+    /// Memory access counts, while coverage is enabled.
+    #[cfg(feature = "alloc")]
+    coverage: Option<Box<CoverageMap>>,
+    /// Execution counts, while profiling is enabled.
+    #[cfg(feature = "alloc")]
+    profiler: Option<Box<Profiler>>,
+    /// The latest executed instructions, while tracing is enabled.
+    #[cfg(feature = "alloc")]
+    trace: Option<Box<TraceBuffer>>,
 }
 
 impl Chip8CPU {
     pub fn new() -> Chip8CPU {
+        Chip8CPU::with_platform(DefaultPlatform::default())
+    }
+}
+
+impl Default for Chip8CPU {
+    fn default() -> Chip8CPU {
+        Chip8CPU::new()
+    }
+}
+
+impl<P: Platform> Chip8CPU<P> {
+    pub fn with_platform(platform: P) -> Chip8CPU<P> {
         Chip8CPU {
-            key_inputs: Vec::with_filled_capacity(16, 0),
+            key_inputs: [0; KEY_COUNT],
             display: Display::new(),
-            memory: load_fontset(Vec::with_filled_capacity(4096, 0)),
-            gpio: Vec::with_filled_capacity(16, 0),
+            memory: load_fontset([0; MEMORY_SIZE]),
+            gpio: [0; REGISTER_COUNT],
             sound_timer: 0,
             delay_timer: 0,
             index_register: 0,
-            program_counter: 0,
-            stack: Vec::with_filled_capacity(16, 0),
+            program_counter: PROGRAM_START as u16,
+            stack: [0; STACK_SIZE],
             stack_pointer: 0,
             draw_flag: false,
+            quirks: Quirks::new(),
+            buzzer_on: false,
+            key_wait: KeyWait::Idle,
+            halted: false,
+            frame_count: 0,
+            cycle_count: 0,
+            generation: 0,
+            platform,
+            #[cfg(feature = "alloc")]
+            coverage: None,
+            #[cfg(feature = "alloc")]
+            profiler: None,
+            #[cfg(feature = "alloc")]
+            trace: None,
         }
     }
 
-    pub fn load_rom(&mut self, game_data: &Vec<u8>) {
-        console_log!("Loaded game with {:?} bytes", game_data.len());
-        game_data
-            .iter()
-            .cloned()
-            .enumerate()
-            .for_each(|(index, binary)| self.memory[index + 0x200] = binary);
+    /// Copies the ROM into memory at [`PROGRAM_START`]. Nothing is changed
+    /// when it's too large to fit.
+    pub fn load_rom(&mut self, game_data: &[u8]) -> Result<(), RomError> {
+        if game_data.len() > MAX_ROM_SIZE {
+            return Err(RomError::TooLarge(game_data.len()));
+        }
+        self.platform
+            .log(format_args!("Loaded game with {:?} bytes", game_data.len()));
+        self.memory[PROGRAM_START..PROGRAM_START + game_data.len()].copy_from_slice(game_data);
+        self.generation += 1;
+
+        Ok(())
     }
 
+    /// Runs one instruction and counts the timers down, unless the CPU is
+    /// halted.
     pub fn cycle(&mut self) {
+        if self.halted {
+            return;
+        }
         Processor::process_opcode(self);
-        Processor::update_timers(self);
+        if !self.halted {
+            Processor::update_timers(self);
+        }
+    }
+
+    /// Runs one 60 Hz frame worth of instructions. Keys only change between
+    /// frames, which is what makes a run reproducible from its inputs.
+    pub fn run_frame(&mut self, instructions_per_frame: u32) {
+        (0..instructions_per_frame).for_each(|_| self.cycle());
+        self.end_frame();
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        #[cfg(feature = "alloc")]
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame();
+        }
+        self.frame_count += 1;
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Instructions executed by this CPU. Unlike the frame count it isn't
+    /// part of the saved state.
+    pub fn get_cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+
+    /// Serializes the machine state into `buffer`, returning the number of bytes
+    /// written. Quirks and the platform are configuration, not state, so they
+    /// aren't included.
+    ///
+    /// Layout, multi-byte values are little endian: `C8ST` magic, version,
+    /// memory, V0-VF, stack, stack pointer, program counter, I, delay timer,
+    /// sound timer, keys, display buffer, draw flag, buzzer, frame count, key
+    /// wait, see [`KeyWait::to_byte`].
+    pub fn save_state(&self, buffer: &mut [u8]) -> Result<usize, StateError> {
+        if buffer.len() < STATE_SIZE {
+            return Err(StateError::BufferTooSmall);
+        }
+
+        let mut writer = StateWriter { buffer, offset: 0 };
+        writer.write(&STATE_MAGIC);
+        writer.write(&[STATE_VERSION]);
+        writer.write(&self.memory);
+        writer.write(&self.gpio);
+        self.stack
+            .iter()
+            .for_each(|address| writer.write(&address.to_le_bytes()));
+        writer.write(&[self.stack_pointer]);
+        writer.write(&self.program_counter.to_le_bytes());
+        writer.write(&self.index_register.to_le_bytes());
+        writer.write(&[self.delay_timer, self.sound_timer]);
+        writer.write(&self.key_inputs);
+        writer.write(self.display.get_buffer());
+        writer.write(&[self.draw_flag as u8, self.buzzer_on as u8]);
+        writer.write(&self.frame_count.to_le_bytes());
+        writer.write(&[self.key_wait.to_byte()]);
+
+        Ok(writer.offset)
+    }
+
+    /// Restores a machine state written by [`Chip8CPU::save_state`]. Nothing is
+    /// changed when an error is returned.
+    pub fn load_state(&mut self, buffer: &[u8]) -> Result<(), StateError> {
+        if buffer.len() < STATE_SIZE {
+            return Err(StateError::BufferTooSmall);
+        }
+
+        let mut reader = StateReader { buffer, offset: 0 };
+        if reader.read(STATE_MAGIC.len()) != STATE_MAGIC {
+            return Err(StateError::InvalidHeader);
+        }
+        let version = reader.read_u8();
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        self.memory.copy_from_slice(reader.read(MEMORY_SIZE));
+        self.gpio.copy_from_slice(reader.read(REGISTER_COUNT));
+        (0..STACK_SIZE).for_each(|index| self.stack[index] = reader.read_u16());
+        self.stack_pointer = reader.read_u8();
+        self.program_counter = reader.read_u16();
+        self.index_register = reader.read_u16();
+        self.delay_timer = reader.read_u8();
+        self.sound_timer = reader.read_u8();
+        self.key_inputs.copy_from_slice(reader.read(KEY_COUNT));
+        self.display
+            .get_buffer_mut()
+            .copy_from_slice(reader.read(display::BUFFER_SIZE));
+        self.draw_flag = reader.read_u8() != 0;
+        self.buzzer_on = reader.read_u8() != 0;
+        self.frame_count = reader.read_u64();
+        self.key_wait = KeyWait::from_byte(reader.read_u8());
+        // A state saved while halted halts again on its next cycle, since its
+        // program counter is still at the unknown opcode.
+        self.halted = false;
+        self.platform.set_buzzer(self.buzzer_on);
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    /// Press or release one of the 16 keys (0x0-0xF).
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.key_inputs[key] = pressed as u8;
+        self.generation += 1;
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Whether execution is stopped at an FX0A until a key is pressed, or
+    /// released. Timers keep counting down meanwhile.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
+    pub fn get_key_wait(&self) -> KeyWait {
+        self.key_wait
+    }
+
+    /// Whether the CPU stopped at an unknown opcode, which the program counter
+    /// still points at. Only moving the program counter or loading a state
+    /// gets it running again.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.generation += 1;
+    }
+
+    pub fn platform(&self) -> &P {
+        &self.platform
+    }
+
+    pub fn platform_mut(&mut self) -> &mut P {
+        &mut self.platform
+    }
+
+    /// Starts counting memory accesses from zero, see [`CoverageMap`].
+    #[cfg(feature = "alloc")]
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Box::new(CoverageMap::new()));
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn get_coverage(&self) -> Option<&CoverageMap> {
+        self.coverage.as_deref()
+    }
+
+    /// Starts profiling from zero, see [`Profiler`].
+    #[cfg(feature = "alloc")]
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Box::new(Profiler::new()));
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn disable_profiler(&mut self) {
+        self.profiler = None;
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn get_profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    /// Starts tracing, keeping up to `capacity` of the latest instructions,
+    /// see [`TraceBuffer`].
+    #[cfg(feature = "alloc")]
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(Box::new(TraceBuffer::new(capacity)));
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn get_trace(&self) -> Option<&TraceBuffer> {
+        self.trace.as_deref()
+    }
+
+    /// For draining the trace.
+    #[cfg(feature = "alloc")]
+    pub fn get_trace_mut(&mut self) -> Option<&mut TraceBuffer> {
+        self.trace.as_deref_mut()
+    }
+
+    fn record_access(&mut self, access: MemoryAccess, address: usize) {
+        #[cfg(feature = "alloc")]
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(access, address);
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = (access, address);
+    }
+
+    pub fn get_memory(&self) -> &[u8; MEMORY_SIZE] {
+        &self.memory
+    }
+
+    /// For frontends handing memory to code that writes it directly. Unlike
+    /// [`Chip8CPU::write_range`] this doesn't bump the generation.
+    #[cfg(feature = "libretro")]
+    pub(crate) fn get_memory_mut(&mut self) -> &mut [u8; MEMORY_SIZE] {
+        &mut self.memory
+    }
+
+    pub fn get_registers(&self) -> &[u8; REGISTER_COUNT] {
+        &self.gpio
+    }
+
+    pub fn get_index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn get_stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Keys 0x0-0xF, non-zero while pressed.
+    pub fn get_keys(&self) -> &[u8; KEY_COUNT] {
+        &self.key_inputs
+    }
+
+    pub fn get_stack(&self) -> &[u16; STACK_SIZE] {
+        &self.stack
+    }
+
+    /// A counter bumped by every instruction run and every change made from
+    /// outside, so a UI only has to refresh when it moved. Not part of the
+    /// saved state.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub fn peek(&self, address: usize) -> Result<u8, AccessError> {
+        self.memory
+            .get(address)
+            .copied()
+            .ok_or(AccessError::AddressOutOfRange(address))
+    }
+
+    /// Writes a byte of memory. Code caches like
+    /// [`BlockEngine`](crate::block_cache::BlockEngine) aren't told, so
+    /// invalidate them after editing code.
+    pub fn poke(&mut self, address: usize, value: u8) -> Result<(), AccessError> {
+        self.write_range(address, &[value])
+    }
+
+    pub fn read_range(&self, start: usize, length: usize) -> Result<&[u8], AccessError> {
+        let end = Self::range_end(start, length)?;
+
+        Ok(&self.memory[start..end])
+    }
+
+    /// Writes `bytes` to memory from `start`, or nothing when they don't fit.
+    pub fn write_range(&mut self, start: usize, bytes: &[u8]) -> Result<(), AccessError> {
+        let end = Self::range_end(start, bytes.len())?;
+        self.memory[start..end].copy_from_slice(bytes);
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    fn range_end(start: usize, length: usize) -> Result<usize, AccessError> {
+        match start.checked_add(length) {
+            Some(end) if end <= MEMORY_SIZE => Ok(end),
+            _ => Err(AccessError::AddressOutOfRange(start.saturating_add(length))),
+        }
+    }
+
+    /// Sets VX, for `index` 0x0-0xF.
+    pub fn set_register(&mut self, index: usize, value: u8) -> Result<(), AccessError> {
+        let register = self
+            .gpio
+            .get_mut(index)
+            .ok_or(AccessError::RegisterOutOfRange(index))?;
+        *register = value;
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    pub fn set_index_register(&mut self, address: u16) -> Result<(), AccessError> {
+        if address as usize >= MEMORY_SIZE {
+            return Err(AccessError::AddressOutOfRange(address as usize));
+        }
+        self.index_register = address;
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    pub fn set_program_counter(&mut self, address: u16) -> Result<(), AccessError> {
+        if address as usize + 1 >= MEMORY_SIZE {
+            return Err(AccessError::AddressOutOfRange(address as usize));
+        }
+        self.program_counter = address;
+        // Whatever FX0A was waiting on, or halted the CPU, is left behind.
+        self.key_wait = KeyWait::Idle;
+        self.halted = false;
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    pub fn set_stack_entry(&mut self, index: usize, address: u16) -> Result<(), AccessError> {
+        if address as usize >= MEMORY_SIZE {
+            return Err(AccessError::AddressOutOfRange(address as usize));
+        }
+        let entry = self
+            .stack
+            .get_mut(index)
+            .ok_or(AccessError::StackOutOfRange(index))?;
+        *entry = address;
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    pub fn set_stack_pointer(&mut self, stack_pointer: u8) -> Result<(), AccessError> {
+        if stack_pointer as usize > STACK_SIZE {
+            return Err(AccessError::StackOutOfRange(stack_pointer as usize));
+        }
+        self.stack_pointer = stack_pointer;
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+        self.generation += 1;
+    }
+
+    /// Sets the sound timer, which sounds the buzzer from the next cycle.
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+        self.generation += 1;
     }
 }
 
 pub struct Processor {}
 
 impl Processor {
-    fn process_opcode(cpu: &mut Chip8CPU) {
+    fn process_opcode<P: Platform>(cpu: &mut Chip8CPU<P>) {
         let opcode = Self::fetch_opcode(cpu);
 
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let nn = (opcode & 0x00FF) as u8;
-
-        match opcode & 0xF000 {
-            0x0000 => match opcode & 0x000F {
-                0x0000 => {
-                    // 00E0: Clears the screen.
-                    cpu.display = Display::new();
-                    cpu.draw_flag = true;
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x000E => {
-                    // 00EE: Returns from a subroutine.
-                    let stack_pointer = cpu.stack_pointer - 1;
-                    cpu.stack_pointer = stack_pointer;
-                    cpu.program_counter = cpu.stack[stack_pointer as usize] + 2;
-                    return;
-                }
-                _ => {
-                    panic!("Unknown opcode in default 0x0000 {:X}", opcode);
-                }
-            },
-            0x1000 => {
-                // 1NNN: Jumps to address NNN.
+        Self::execute(cpu, Instruction::decode(opcode));
+    }
+
+    pub(crate) fn execute<P: Platform>(cpu: &mut Chip8CPU<P>, instruction: Instruction) {
+        cpu.cycle_count += 1;
+        cpu.generation += 1;
+        let program_counter = cpu.program_counter as usize;
+        cpu.record_access(MemoryAccess::Fetch, program_counter);
+        cpu.record_access(MemoryAccess::Fetch, program_counter + 1);
+        #[cfg(feature = "alloc")]
+        if let Some(profiler) = cpu.profiler.as_mut() {
+            profiler.record(cpu.program_counter, instruction);
+        }
+        #[cfg(feature = "alloc")]
+        if cpu.trace.is_some() {
+            let entry = TraceEntry {
+                cycle: cpu.cycle_count - 1,
+                program_counter: cpu.program_counter,
+                opcode: Self::fetch_opcode_at(cpu, cpu.program_counter),
+                registers: cpu.gpio,
+                index_register: cpu.index_register,
+                stack_pointer: cpu.stack_pointer,
+                delay_timer: cpu.delay_timer,
+                sound_timer: cpu.sound_timer,
+            };
+            if let Some(trace) = cpu.trace.as_mut() {
+                trace.push(entry);
+            }
+        }
+
+        match instruction {
+            Instruction::ClearScreen => {
+                // 00E0: Clears the screen.
+                cpu.display = Display::new();
+                cpu.draw_flag = true;
                 cpu.program_counter += 2;
-                return;
+                cpu.platform.on_event(CpuEvent::ScreenCleared);
+            }
+            Instruction::Return => {
+                // 00EE: Returns from a subroutine. The stack wraps around rather than underflowing.
+                cpu.stack_pointer = cpu.stack_pointer.wrapping_sub(1);
+                cpu.program_counter =
+                    cpu.stack[cpu.stack_pointer as usize % STACK_SIZE].wrapping_add(2);
+                cpu.platform.on_event(CpuEvent::SubroutineReturned {
+                    address: cpu.program_counter,
+                });
             }
-            0x2000 => {
-                // 2NNN: Calls subroutine at NNN.
-                cpu.stack[cpu.stack_pointer as usize] = cpu.program_counter;
-                cpu.stack_pointer += 1;
-                cpu.program_counter = opcode & 0x0FFF;
-                return;
+            Instruction::Jump { address } => {
+                // 1NNN: Jumps to address NNN.
+                cpu.program_counter = address;
+            }
+            Instruction::Call { address } => {
+                // 2NNN: Calls subroutine at NNN. The stack wraps around rather than overflowing.
+                cpu.stack[cpu.stack_pointer as usize % STACK_SIZE] = cpu.program_counter;
+                cpu.stack_pointer = cpu.stack_pointer.wrapping_add(1);
+                cpu.platform.on_event(CpuEvent::SubroutineCalled {
+                    address,
+                    return_address: cpu.program_counter + 2,
+                });
+                cpu.program_counter = address;
             }
-            0x3000 => {
+            Instruction::SkipIfEqual { x, value } => {
                 // 3XNN: Skips the next instruction if VX equals NN.
-                cpu.program_counter += if cpu.gpio[x] == nn { 4 } else { 2 };
-                return;
+                cpu.program_counter += if cpu.gpio[x] == value { 4 } else { 2 };
             }
-            0x4000 => {
+            Instruction::SkipIfNotEqual { x, value } => {
                 // 4XNN: Skips the next instruction if VX doesn't equal NN.
-                cpu.program_counter += if cpu.gpio[x] != nn { 4 } else { 2 };
-                return;
+                cpu.program_counter += if cpu.gpio[x] != value { 4 } else { 2 };
             }
-            0x5000 => {
+            Instruction::SkipIfRegistersEqual { x, y } => {
                 // 5XY0: Skips the next instruction if VX equals VY.
                 cpu.program_counter += if cpu.gpio[x] == cpu.gpio[y] { 4 } else { 2 };
-                return;
             }
-            0x6000 => {
+            Instruction::SetRegister { x, value } => {
                 // 6XNN: Sets VX to NN.
-                cpu.gpio[x] = nn;
+                cpu.gpio[x] = value;
                 cpu.program_counter += 2;
-                return;
             }
-            0x7000 => {
+            Instruction::AddToRegister { x, value } => {
                 // 7XNN: Adds NN to VX.
-                (cpu.gpio[x], _) = cpu.gpio[x].overflowing_add(nn);
+                (cpu.gpio[x], _) = cpu.gpio[x].overflowing_add(value);
                 cpu.program_counter += 2;
-                return;
-            }
-            0x8000 => match opcode & 0x000F {
-                0x0000 => {
-                    // 8XY0: Sets VX to the value of VY.
-                    cpu.gpio[x] = cpu.gpio[y];
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0001 => {
-                    // 8XY1: Sets VX to VX or VY.
-                    cpu.gpio[x] |= cpu.gpio[y];
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0002 => {
-                    // 8XY2: Sets VX to VX and VY.
-                    cpu.gpio[x] &= cpu.gpio[y];
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0003 => {
-                    // 8XY3: Sets VX to VX xor VY.
-                    cpu.gpio[x] ^= cpu.gpio[y];
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0004 => {
-                    // 8XY4: Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-                    cpu.gpio[0xF] = if cpu.gpio[y] > (0xFF - cpu.gpio[x]) {
-                        1
-                    } else {
-                        0
-                    };
-                    (cpu.gpio[x], _) = cpu.gpio[x].overflowing_add(cpu.gpio[y]);
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0005 => {
-                    // 8XY5: VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                    cpu.gpio[0xF] = if cpu.gpio[y] > cpu.gpio[x] { 0 } else { 1 };
-                    (cpu.gpio[x], _) = cpu.gpio[x].overflowing_sub(cpu.gpio[y]);
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0006 => {
-                    // 8XY6: Shifts VX right by one. VF is set to the value of the least significant bit of VX before the shift.
-                    cpu.gpio[0xF] = cpu.gpio[x] & 0x1;
-                    cpu.gpio[x] >>= 1;
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0007 => {
-                    // 8XY7: Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-                    cpu.gpio[0xF] = if cpu.gpio[x] > cpu.gpio[y] { 0 } else { 1 };
-                    (cpu.gpio[x], _) = cpu.gpio[y].overflowing_sub(cpu.gpio[x]);
-                    cpu.program_counter += 2;
-                    return;
+            }
+            Instruction::CopyRegister { x, y } => {
+                // 8XY0: Sets VX to the value of VY.
+                cpu.gpio[x] = cpu.gpio[y];
+                cpu.program_counter += 2;
+            }
+            Instruction::Or { x, y } => {
+                // 8XY1: Sets VX to VX or VY.
+                cpu.gpio[x] |= cpu.gpio[y];
+                if cpu.quirks.vf_reset {
+                    cpu.gpio[0xF] = 0;
                 }
-                // 61584
-                0x000E => {
-                    // 8XYE: Shifts VX left by one. VF is set to the value of the most significant bit of VX before the shift.
-                    cpu.gpio[0xF] = cpu.gpio[x] >> 7;
-                    cpu.gpio[x] <<= 1;
-                    cpu.program_counter += 2;
-                    return;
+                cpu.program_counter += 2;
+            }
+            Instruction::And { x, y } => {
+                // 8XY2: Sets VX to VX and VY.
+                cpu.gpio[x] &= cpu.gpio[y];
+                if cpu.quirks.vf_reset {
+                    cpu.gpio[0xF] = 0;
                 }
-                _ => {
-                    panic!("Unknown opcode in default 0x8000 {:X}", opcode);
+                cpu.program_counter += 2;
+            }
+            Instruction::Xor { x, y } => {
+                // 8XY3: Sets VX to VX xor VY.
+                cpu.gpio[x] ^= cpu.gpio[y];
+                if cpu.quirks.vf_reset {
+                    cpu.gpio[0xF] = 0;
                 }
-            },
-            0x9000 => {
+                cpu.program_counter += 2;
+            }
+            Instruction::Add { x, y } => {
+                // 8XY4: Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
+                // VF is written last, so the flag wins when X is F.
+                let (result, carry) = cpu.gpio[x].overflowing_add(cpu.gpio[y]);
+                cpu.gpio[x] = result;
+                cpu.gpio[0xF] = carry as u8;
+                cpu.program_counter += 2;
+            }
+            Instruction::Subtract { x, y } => {
+                // 8XY5: VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+                let (result, borrow) = cpu.gpio[x].overflowing_sub(cpu.gpio[y]);
+                cpu.gpio[x] = result;
+                cpu.gpio[0xF] = !borrow as u8;
+                cpu.program_counter += 2;
+            }
+            Instruction::ShiftRight { x, y } => {
+                // 8XY6: Shifts VX right by one. VF is set to the value of the least significant bit of VX before the shift.
+                let source = cpu.gpio[if cpu.quirks.shift_uses_vy { y } else { x }];
+                cpu.gpio[x] = source >> 1;
+                cpu.gpio[0xF] = source & 0x1;
+                cpu.program_counter += 2;
+            }
+            Instruction::SubtractReversed { x, y } => {
+                // 8XY7: Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+                let (result, borrow) = cpu.gpio[y].overflowing_sub(cpu.gpio[x]);
+                cpu.gpio[x] = result;
+                cpu.gpio[0xF] = !borrow as u8;
+                cpu.program_counter += 2;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                // 8XYE: Shifts VX left by one. VF is set to the value of the most significant bit of VX before the shift.
+                let source = cpu.gpio[if cpu.quirks.shift_uses_vy { y } else { x }];
+                cpu.gpio[x] = source << 1;
+                cpu.gpio[0xF] = source >> 7;
+                cpu.program_counter += 2;
+            }
+            Instruction::SkipIfRegistersNotEqual { x, y } => {
                 // 9XY0: Skips the next instruction if VX doesn't equal VY.
                 cpu.program_counter += if cpu.gpio[x] != cpu.gpio[y] { 4 } else { 2 };
-                return;
             }
-            0xA000 => {
+            Instruction::SetIndex { address } => {
                 // ANNN: Sets I to the address NNN.
-                cpu.index_register = opcode & 0x0FFF;
+                cpu.index_register = address;
                 cpu.program_counter += 2;
-                return;
             }
-            0xB000 => {
+            Instruction::JumpWithOffset { address, x } => {
                 // BNNN: Jumps to the address NNN plus V0.
-                cpu.program_counter = (opcode & 0x0FFF) + (cpu.gpio[0] as u16);
-                return;
+                let offset_register = if cpu.quirks.jump_uses_vx { x } else { 0 };
+                cpu.program_counter = address + (cpu.gpio[offset_register] as u16);
             }
-            0xC000 => {
+            Instruction::Random { x, mask } => {
                 // CXNN: Sets VX to the result of a bitwise and operation on a random number and NN.
-                cpu.gpio[x] = nn & (rand::thread_rng().gen::<u8>() % 0xFF);
-                return;
+                cpu.gpio[x] = mask & cpu.platform.random_byte();
+                cpu.program_counter += 2;
             }
-            0xD000 => {
+            Instruction::Draw { x, y, height } => {
                 // Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels. Each row of 8 pixels is read as bit-coded starting from memory location I; I value doesn’t change after the execution of this instruction. As described above, VF is set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn, and to 0 if that doesn’t happen
-                let height = opcode & 0x000F;
+                // The sprite starts wrapped onto the screen, and is clipped at its edges.
+                let screen_width = cpu.display.width as usize;
+                let screen_height = cpu.display.height as usize;
+                let left = cpu.gpio[x] as usize % screen_width;
+                let top = cpu.gpio[y] as usize % screen_height;
                 cpu.gpio[0xF] = 0;
-                (0..height).for_each(|y_line| {
-                    let pixel = cpu.memory[(cpu.index_register + y_line) as usize];
-                    (0..8).for_each(|x_line| {
-                        if (pixel & (0x80 & x_line)) == 0 {
-                            return;
-                        }
-
-                        let display_buffer_location =
-                            (x + (x_line as usize) + (y + (y_line as usize))) * 64;
-                        let buffer_size = (cpu.display.width * cpu.display.height) as usize;
-                        if display_buffer_location >= buffer_size {
+                (0..height as usize).for_each(|row| {
+                    let sprite_address = (cpu.index_register as usize + row) % MEMORY_SIZE;
+                    cpu.record_access(MemoryAccess::Read, sprite_address);
+                    let sprite = cpu.memory[sprite_address];
+                    (0..8).for_each(|column| {
+                        let (pixel_x, pixel_y) = (left + column, top + row);
+                        if sprite & (0x80 >> column) == 0
+                            || pixel_x >= screen_width
+                            || pixel_y >= screen_height
+                        {
                             return;
                         }
 
-                        let buffer_item = cpu.display.get_buffer_item(display_buffer_location);
+                        let buffer_location = pixel_y * screen_width + pixel_x;
+                        let buffer_item = cpu.display.get_buffer_item(buffer_location);
                         if buffer_item == 1 {
                             cpu.gpio[0xF] = 1;
                         }
                         cpu.display
-                            .set_buffer_item(display_buffer_location, buffer_item ^ 1);
+                            .set_buffer_item(buffer_location, buffer_item ^ 1);
                     })
                 });
-                cpu.draw_flag = false;
+                cpu.draw_flag = true;
                 cpu.program_counter += 2;
-                return;
-            }
-            0xE000 => match opcode & 0x000F {
-                0x000E => {
-                    // EX9E: Skips the next instruction if the key stored in VX is pressed.
-                    cpu.program_counter += if cpu.key_inputs[cpu.gpio[x] as usize] != 0 {
-                        4
-                    } else {
-                        2
-                    };
-                    return;
-                }
-                0x0001 => {
-                    // EXA1: Skips the next instruction if the key stored in VX isn't pressed.
-                    cpu.program_counter += if cpu.key_inputs[cpu.gpio[x] as usize] == 0 {
-                        4
-                    } else {
-                        2
-                    };
-                    return;
-                }
-                _ => {
-                    panic!("Unknown opcode in default 0xE000 {:X}", opcode);
-                }
-            },
-            0xF000 => match opcode & 0x00FF {
-                0x0007 => {
-                    // FX07: Sets VX to the value of the delay timer.
-                    cpu.gpio[x] = cpu.delay_timer;
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x000A => {
-                    // FX0A: A key press is awaited, and then stored in VX.
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let mut key_pressed = false;
-                    (0..cpu.key_inputs.len()).for_each(|index| {
-                        let key = cpu.key_inputs[index];
-                        if key == 0 {
-                            return;
-                        }
-
-                        cpu.gpio[x] = index as u8;
-                        key_pressed = true;
-                    });
+                cpu.platform.on_event(CpuEvent::SpriteDrawn {
+                    x: cpu.gpio[x],
+                    y: cpu.gpio[y],
+                    height: height as u8,
+                    address: cpu.index_register,
+                    collision: cpu.gpio[0xF] == 1,
+                });
+            }
+            Instruction::SkipIfKeyPressed { x } => {
+                // EX9E: Skips the next instruction if the key stored in VX is pressed.
+                cpu.program_counter += if cpu.key_inputs[cpu.gpio[x] as usize % KEY_COUNT] != 0 {
+                    4
+                } else {
+                    2
+                };
+            }
+            Instruction::SkipIfKeyNotPressed { x } => {
+                // EXA1: Skips the next instruction if the key stored in VX isn't pressed.
+                cpu.program_counter += if cpu.key_inputs[cpu.gpio[x] as usize % KEY_COUNT] == 0 {
+                    4
+                } else {
+                    2
+                };
+            }
+            Instruction::GetDelayTimer { x } => {
+                // FX07: Sets VX to the value of the delay timer.
+                cpu.gpio[x] = cpu.delay_timer;
+                cpu.program_counter += 2;
+            }
+            Instruction::WaitForKey { x } => {
+                // FX0A: A key press is awaited, and then stored in VX. The program counter stays put until then, so this runs again every cycle.
+                let was_waiting = cpu.key_wait != KeyWait::Idle;
+                let pressed = cpu.key_inputs.iter().position(|key| *key != 0);
+                let key = match (cpu.key_wait, pressed) {
+                    (KeyWait::Release(key), _) => {
+                        Some(key).filter(|key| cpu.key_inputs[*key as usize] == 0)
+                    }
+                    (_, None) => {
+                        cpu.key_wait = KeyWait::Press;
+                        None
+                    }
+                    (_, Some(key)) if cpu.quirks.key_wait_release => {
+                        cpu.key_wait = KeyWait::Release(key as u8);
+                        None
+                    }
+                    (_, Some(key)) => Some(key as u8),
+                };
 
-                    if !key_pressed {
+                let key = match key {
+                    Some(key) => key,
+                    None => {
+                        if !was_waiting {
+                            cpu.platform
+                                .on_event(CpuEvent::KeyWaitStarted { register: x as u8 });
+                        }
                         return;
                     }
-
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0015 => {
-                    // FX15: Sets the delay timer to VX.
-                    cpu.delay_timer = cpu.gpio[x];
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0018 => {
-                    // FX18: Sets the sound timer to VX.
-                    cpu.sound_timer = cpu.gpio[x];
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x001E => {
-                    // FX1E: Adds VX to I.
-                    // VF is set to 1 when range overflow (I+VX>0xFFF), and 0 when there isn't.
-                    cpu.gpio[0xF] = if (cpu.index_register + (cpu.gpio[x] as u16)) > 0xFFF {
-                        1
-                    } else {
-                        0
-                    };
-                    cpu.index_register = cpu.gpio[x] as u16;
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0029 => {
-                    // FX29: Sets I to the location of the sprite for the character in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font.
-                    cpu.index_register = (cpu.gpio[x] as u16) * 0x5;
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0033 => {
-                    // FX33: Stores the binary-coded decimal representation of VX, with the most significant of three digits at the address in I, the middle digit at I plus 1, and the least significant digit at I plus 2.
-                    let memory_index = cpu.index_register as usize;
-                    let x_register = cpu.gpio[x];
-                    cpu.memory[memory_index] = x_register;
-                    cpu.memory[memory_index + 1] = (x_register / 10) % 10;
-                    cpu.memory[memory_index + 2] = (x_register / 100) % 10;
-                    cpu.program_counter += 2;
-                    return;
-                }
-                0x0055 => {
-                    // FX55: Stores V0 to VX (including VX) in memory starting at address I.
-                    (0..(x + 1)).for_each(|index| {
-                        let memory_index = (cpu.index_register as usize) + index;
-                        cpu.memory[memory_index] = cpu.gpio[index];
+                };
+                cpu.key_wait = KeyWait::Idle;
+                cpu.gpio[x] = key;
+                if was_waiting {
+                    cpu.platform.on_event(CpuEvent::KeyWaitEnded {
+                        register: x as u8,
+                        key,
                     });
-                    cpu.index_register += ((opcode & 0x0F00) >> 8) + 1;
-                    cpu.program_counter += 2;
-                    return;
                 }
-                0x0065 => {
-                    // FX65: Fills V0 to VX (including VX) with values from memory starting at address I.
-                    (0..(x + 1)).for_each(|index| {
-                        let memory_index = (cpu.index_register as usize) + index;
-                        cpu.memory[index] = cpu.gpio[memory_index];
-                    });
-                    cpu.index_register += ((opcode & 0x0F00) >> 8) + 1;
-                    cpu.program_counter += 2;
-                    return;
+                cpu.program_counter += 2;
+            }
+            Instruction::SetDelayTimer { x } => {
+                // FX15: Sets the delay timer to VX.
+                cpu.delay_timer = cpu.gpio[x];
+                cpu.program_counter += 2;
+            }
+            Instruction::SetSoundTimer { x } => {
+                // FX18: Sets the sound timer to VX.
+                cpu.sound_timer = cpu.gpio[x];
+                cpu.program_counter += 2;
+            }
+            Instruction::AddToIndex { x } => {
+                // FX1E: Adds VX to I. VF is left alone.
+                cpu.index_register = cpu.index_register.wrapping_add(cpu.gpio[x] as u16);
+                cpu.program_counter += 2;
+            }
+            Instruction::SetIndexToFont { x } => {
+                // FX29: Sets I to the location of the sprite for the character in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font.
+                cpu.index_register = ((cpu.gpio[x] & 0xF) as u16) * 0x5;
+                cpu.program_counter += 2;
+            }
+            Instruction::StoreBcd { x } => {
+                // FX33: Stores the binary-coded decimal representation of VX, with the most significant of three digits at the address in I, the middle digit at I plus 1, and the least significant digit at I plus 2.
+                let x_register = cpu.gpio[x];
+                let digits = [x_register / 100, (x_register / 10) % 10, x_register % 10];
+                digits.iter().enumerate().for_each(|(offset, digit)| {
+                    let memory_index = (cpu.index_register as usize + offset) % MEMORY_SIZE;
+                    cpu.memory[memory_index] = *digit;
+                    cpu.record_access(MemoryAccess::Write, memory_index);
+                });
+                cpu.program_counter += 2;
+            }
+            Instruction::StoreRegisters { x } => {
+                // FX55: Stores V0 to VX (including VX) in memory starting at address I.
+                (0..(x + 1)).for_each(|index| {
+                    let memory_index = (cpu.index_register as usize + index) % MEMORY_SIZE;
+                    cpu.memory[memory_index] = cpu.gpio[index];
+                    cpu.record_access(MemoryAccess::Write, memory_index);
+                });
+                if cpu.quirks.load_store_increments_i {
+                    cpu.index_register += (x as u16) + 1;
                 }
-                _ => {
-                    panic!("Unknown opcode in default 0xF000 {:X}", opcode);
+                cpu.program_counter += 2;
+            }
+            Instruction::LoadRegisters { x } => {
+                // FX65: Fills V0 to VX (including VX) with values from memory starting at address I.
+                (0..(x + 1)).for_each(|index| {
+                    let memory_index = (cpu.index_register as usize + index) % MEMORY_SIZE;
+                    cpu.gpio[index] = cpu.memory[memory_index];
+                    cpu.record_access(MemoryAccess::Read, memory_index);
+                });
+                if cpu.quirks.load_store_increments_i {
+                    cpu.index_register += (x as u16) + 1;
                 }
-            },
-            _ => {
-                panic!("Unknown opcode in all default {:X}", opcode);
+                cpu.program_counter += 2;
+            }
+            Instruction::Unknown { opcode } => {
+                // There's nothing to follow, so stop where it is.
+                cpu.halted = true;
+                cpu.platform
+                    .log(format_args!("Halted at unknown opcode {:04X}", opcode));
+                cpu.platform.on_event(CpuEvent::Halted {
+                    address: cpu.program_counter,
+                    opcode,
+                });
             }
         }
     }
 
-    fn fetch_opcode(cpu: &Chip8CPU) -> u16 {
-        let counter = cpu.program_counter as usize;
+    pub(crate) fn fetch_opcode<P: Platform>(cpu: &Chip8CPU<P>) -> u16 {
+        Self::fetch_opcode_at(cpu, cpu.program_counter)
+    }
+
+    pub(crate) fn fetch_opcode_at<P: Platform>(cpu: &Chip8CPU<P>, address: u16) -> u16 {
+        let counter = address as usize;
 
         ((cpu.memory[counter] as u16) << 8) | (cpu.memory[counter + 1] as u16)
     }
 
-    fn update_timers(cpu: &mut Chip8CPU) {
+    pub(crate) fn update_timers<P: Platform>(cpu: &mut Chip8CPU<P>) {
         if cpu.delay_timer > 0 {
             cpu.delay_timer -= 1;
         }
         if cpu.sound_timer > 0 {
             cpu.sound_timer -= 1
         }
-        if cpu.sound_timer == 0 {
-            // TODO: Play sound! 🔊
+        let buzzer_on = cpu.sound_timer > 0;
+        if buzzer_on != cpu.buzzer_on {
+            cpu.buzzer_on = buzzer_on;
+            cpu.platform.set_buzzer(buzzer_on);
+            cpu.platform.on_event(if buzzer_on {
+                CpuEvent::SoundStarted
+            } else {
+                CpuEvent::SoundStopped
+            });
         }
     }
 }
 
-fn load_fontset(memory: Vec<u8>) -> Vec<u8> {
-    let mut memory_clone = memory.clone();
-    FONTSET
-        .iter()
-        .enumerate()
-        .for_each(|(index, font)| memory_clone[index] = font.clone());
+struct StateWriter<'a> {
+    buffer: &'a mut [u8],
+    offset: usize,
+}
+
+impl StateWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.buffer[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+}
+
+struct StateReader<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn read(&mut self, length: usize) -> &'a [u8] {
+        let bytes = &self.buffer[self.offset..self.offset + length];
+        self.offset += length;
+
+        bytes
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        self.read(1)[0]
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let bytes = self.read(2);
+
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+
+    fn read_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read(8));
+
+        u64::from_le_bytes(bytes)
+    }
+}
+
+fn load_fontset(mut memory: [u8; MEMORY_SIZE]) -> [u8; MEMORY_SIZE] {
+    memory[..FONTSET.len()].copy_from_slice(&FONTSET);
 
-    memory_clone
+    memory
 }
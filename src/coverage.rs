@@ -0,0 +1,129 @@
+//! Runtime coverage: how often each byte of memory was fetched as part of an
+//! opcode, read as data or written, while coverage is enabled on a CPU (see
+//! [`Chip8CPU::enable_coverage`](crate::chip8_cpu::Chip8CPU::enable_coverage)).
+
+use crate::chip8_cpu::MEMORY_SIZE;
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use core::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryAccess {
+    /// Part of an opcode that was executed.
+    Fetch,
+    /// Read as data, by DXYN and FX65.
+    Read,
+    /// Written, by FX33 and FX55.
+    Write,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageMap {
+    fetches: [u32; MEMORY_SIZE],
+    reads: [u32; MEMORY_SIZE],
+    writes: [u32; MEMORY_SIZE],
+}
+
+impl CoverageMap {
+    pub fn new() -> CoverageMap {
+        CoverageMap {
+            fetches: [0; MEMORY_SIZE],
+            reads: [0; MEMORY_SIZE],
+            writes: [0; MEMORY_SIZE],
+        }
+    }
+
+    pub fn record(&mut self, access: MemoryAccess, address: usize) {
+        if let Some(count) = self.counts_mut(access).get_mut(address) {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    /// Counts for every address of memory.
+    pub fn get_counts(&self, access: MemoryAccess) -> &[u32] {
+        match access {
+            MemoryAccess::Fetch => &self.fetches,
+            MemoryAccess::Read => &self.reads,
+            MemoryAccess::Write => &self.writes,
+        }
+    }
+
+    pub fn get_count(&self, access: MemoryAccess, address: usize) -> u32 {
+        self.get_counts(access).get(address).copied().unwrap_or(0)
+    }
+
+    pub fn clear(&mut self) {
+        self.fetches.fill(0);
+        self.reads.fill(0);
+        self.writes.fill(0);
+    }
+
+    fn counts_mut(&mut self, access: MemoryAccess) -> &mut [u32] {
+        match access {
+            MemoryAccess::Fetch => &mut self.fetches,
+            MemoryAccess::Read => &mut self.reads,
+            MemoryAccess::Write => &mut self.writes,
+        }
+    }
+
+    /// Lists `rom` as loaded at 0x200, two bytes per line, next to how often
+    /// each line was fetched, read and written. Each count is the higher of
+    /// the line's two bytes.
+    #[cfg(feature = "alloc")]
+    pub fn annotated_dump(&self, rom: &[u8]) -> String {
+        let start = 0x200;
+        let end = (start + rom.len()).min(MEMORY_SIZE);
+        let touched = |counts: &[u32]| {
+            counts[start..end]
+                .iter()
+                .filter(|count| **count > 0)
+                .count()
+        };
+
+        let mut dump = String::new();
+        writeln!(
+            dump,
+            "; {:03X}-{:03X}: {} bytes fetched, {} read, {} written of {}",
+            start,
+            end.saturating_sub(1),
+            touched(&self.fetches),
+            touched(&self.reads),
+            touched(&self.writes),
+            end - start
+        )
+        .unwrap();
+        writeln!(dump, "ADDR  BYTES   FETCHED      READ   WRITTEN").unwrap();
+
+        (start..end).step_by(2).for_each(|address| {
+            let line_end = (address + 2).min(end);
+            let count =
+                |counts: &[u32]| counts[address..line_end].iter().copied().max().unwrap_or(0);
+
+            write!(dump, "{:03X}   ", address).unwrap();
+            rom[address - start..line_end - start]
+                .iter()
+                .for_each(|byte| write!(dump, "{:02X}", byte).unwrap());
+            if line_end - address == 1 {
+                dump.push_str("  ");
+            }
+            writeln!(
+                dump,
+                "  {:>9} {:>9} {:>9}",
+                count(&self.fetches),
+                count(&self.reads),
+                count(&self.writes)
+            )
+            .unwrap();
+        });
+
+        dump
+    }
+}
+
+impl Default for CoverageMap {
+    fn default() -> CoverageMap {
+        CoverageMap::new()
+    }
+}
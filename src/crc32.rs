@@ -0,0 +1,18 @@
+/// CRC-32 (IEEE 802.3, as used by zip and PNG), which identifies ROMs.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    update_crc32(0, bytes)
+}
+
+/// Continues a CRC-32 over another chunk of bytes, starting from the value
+/// returned for the previous chunks.
+pub fn update_crc32(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, byte| {
+        (0..8).fold(crc ^ (*byte as u32), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
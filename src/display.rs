@@ -1,17 +1,16 @@
-#[path = "./traits.rs"]
-mod traits;
-use traits::FillableVector;
+pub const DISPLAY_WIDTH: u32 = 64;
+pub const DISPLAY_HEIGHT: u32 = 32;
 
-const DISPLAY_WIDTH: u32 = 64;
-const DISPLAY_HEIGHT: u32 = 32;
+pub const BUFFER_SIZE: usize = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize;
 
 /// For output, the machine uses a 64x32 display, and a simple sound buzzer.
 /// The display is basically just an array of pixels that are either in the
 /// on or off state.
+#[derive(Clone)]
 pub struct Display {
     pub width: u32,
     pub height: u32,
-    buffer: Vec<u8>,
+    buffer: [u8; BUFFER_SIZE],
 }
 
 impl Display {
@@ -19,12 +18,16 @@ impl Display {
         Display {
             width: DISPLAY_WIDTH,
             height: DISPLAY_HEIGHT,
-            buffer: Vec::with_filled_capacity((DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize, 0),
+            buffer: [0; BUFFER_SIZE],
         }
     }
 
-    pub fn get_buffer_array(&self) -> js_sys::Uint8Array {
-        js_sys::Uint8Array::from(self.buffer.as_slice())
+    pub fn get_buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub(crate) fn get_buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
     }
 
     pub fn get_buffer_item(&self, index: usize) -> u8 {
@@ -35,3 +38,9 @@ impl Display {
         self.buffer[index] = value;
     }
 }
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
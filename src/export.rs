@@ -0,0 +1,226 @@
+//! Lossless recordings of movies, to edit into videos: the screen as a Y4M
+//! stream and the buzzer as a WAV, both at exactly 60 frames a second so
+//! they stay in step.
+//!
+//! A movie is played without anyone watching, so the same ROM and movie always
+//! make the same files. Frames are in 4:4:4 full range YCbCr, so no colour is
+//! lost, and the sound is 16 bit mono PCM, each frame getting its share of
+//! samples with the remainders carried over. The buzzer is sampled once per
+//! frame, after the frame runs, the way the libretro core plays it.
+//!
+//! The sound is only the CHIP-8 buzzer, a 440 Hz square wave while the sound
+//! timer runs. XO-CHIP's sample playback and pitch register aren't exported,
+//! since the interpreter doesn't implement XO-CHIP.
+
+use crate::chip8_cpu::Chip8CPU;
+use crate::image::{palette_or_default, Bitmap, Rgb, MAX_SCALE};
+use crate::movie::{Movie, MovieError, MoviePlayer};
+use crate::platform::XorShiftRng;
+use crate::traits::{Audio, Logger, Observer, RandomSource};
+
+use alloc::format;
+use alloc::vec::Vec;
+
+pub const FRAME_RATE: u64 = 60;
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const BUZZER_FREQUENCY: u32 = 440;
+const BUZZER_VOLUME: i16 = 4000;
+const BYTES_PER_SAMPLE: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportError {
+    InvalidMovie(MovieError),
+    /// A scale of 0 or over [`MAX_SCALE`].
+    InvalidScale,
+    /// Under 1 kHz, or over 192 kHz.
+    InvalidSampleRate,
+    /// More sound than a WAV file holds.
+    TooLong,
+}
+
+/// Platform for exporting: the movie's random numbers and a buzzer to sample.
+struct ExportPlatform {
+    rng: XorShiftRng,
+    buzzer_on: bool,
+}
+
+impl RandomSource for ExportPlatform {
+    fn random_byte(&mut self) -> u8 {
+        self.rng.random_byte()
+    }
+}
+
+impl Logger for ExportPlatform {}
+
+impl Audio for ExportPlatform {
+    fn set_buzzer(&mut self, on: bool) {
+        self.buzzer_on = on;
+    }
+}
+
+impl Observer for ExportPlatform {}
+
+/// Plays a movie a frame at a time, turning each into its part of the video
+/// and the sound. Write [`MovieExporter::video_header`] and
+/// [`MovieExporter::audio_header`], then what [`MovieExporter::next_frame`]
+/// gives until the movie ends.
+pub struct MovieExporter {
+    cpu: Chip8CPU<ExportPlatform>,
+    player: MoviePlayer,
+    scale: u32,
+    /// The palette in YCbCr.
+    colours: Vec<[u8; 3]>,
+    sample_rate: u32,
+    frame: u64,
+    /// Where the buzzer's square wave is, in 1/`sample_rate` of a cycle.
+    phase: u32,
+}
+
+impl MovieExporter {
+    /// Each pixel is `scale` pixels across, coloured from `palette` like
+    /// screenshots are.
+    pub fn new(
+        rom: &[u8],
+        movie: Movie,
+        scale: u32,
+        palette: &[Rgb],
+        sample_rate: u32,
+    ) -> Result<MovieExporter, ExportError> {
+        if !(1..=MAX_SCALE).contains(&scale) {
+            return Err(ExportError::InvalidScale);
+        }
+        if !(1000..=192_000).contains(&sample_rate) {
+            return Err(ExportError::InvalidSampleRate);
+        }
+        let sample_count = samples_before(movie.frame_count, sample_rate);
+        if sample_count * BYTES_PER_SAMPLE > u32::MAX as u64 - 36 {
+            return Err(ExportError::TooLong);
+        }
+
+        let player = MoviePlayer::new(movie);
+        let mut cpu = Chip8CPU::with_platform(ExportPlatform {
+            rng: XorShiftRng::new(player.get_movie().rng_seed),
+            buzzer_on: false,
+        });
+        player
+            .prepare(&mut cpu, rom)
+            .map_err(ExportError::InvalidMovie)?;
+
+        Ok(MovieExporter {
+            cpu,
+            player,
+            scale,
+            colours: palette_or_default(palette)
+                .iter()
+                .map(|colour| rgb_to_ycbcr(*colour))
+                .collect(),
+            sample_rate,
+            frame: 0,
+            phase: 0,
+        })
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.player.get_movie().frame_count
+    }
+
+    pub fn video_header(&self) -> Vec<u8> {
+        let display = &self.cpu.display;
+        format!(
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444 XCOLORRANGE=FULL\n",
+            display.width * self.scale,
+            display.height * self.scale,
+            FRAME_RATE
+        )
+        .into_bytes()
+    }
+
+    /// The header for the whole movie's sound, which is known up front.
+    pub fn audio_header(&self) -> Vec<u8> {
+        let data_size =
+            (samples_before(self.get_frame_count(), self.sample_rate) * BYTES_PER_SAMPLE) as u32;
+
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(36 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono.
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * BYTES_PER_SAMPLE as u32).to_le_bytes());
+        header.extend_from_slice(&(BYTES_PER_SAMPLE as u16).to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+
+        header
+    }
+
+    /// Runs the next frame of the movie, and gets its Y4M frame and WAV
+    /// samples, or `None` once the movie is over.
+    pub fn next_frame(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        if !self.player.run_frame(&mut self.cpu) {
+            return None;
+        }
+
+        let video = self.render_video();
+        let audio = self.render_audio();
+        self.frame += 1;
+
+        Some((video, audio))
+    }
+
+    fn render_video(&self) -> Vec<u8> {
+        let image = Bitmap::from_display(&self.cpu.display).scale(self.scale);
+        let mut video = Vec::with_capacity(6 + image.pixels.len() * 3);
+        video.extend_from_slice(b"FRAME\n");
+        for plane in 0..3 {
+            video.extend(
+                image.pixels.iter().map(|colour| {
+                    self.colours[(*colour as usize).min(self.colours.len() - 1)][plane]
+                }),
+            );
+        }
+
+        video
+    }
+
+    fn render_audio(&mut self) -> Vec<u8> {
+        let sample_count = samples_before(self.frame + 1, self.sample_rate)
+            - samples_before(self.frame, self.sample_rate);
+        let buzzer_on = self.cpu.platform().buzzer_on;
+
+        let mut audio = Vec::with_capacity((sample_count * BYTES_PER_SAMPLE) as usize);
+        for _ in 0..sample_count {
+            let sample = match (buzzer_on, self.phase < self.sample_rate / 2) {
+                (false, _) => 0,
+                (true, true) => BUZZER_VOLUME,
+                (true, false) => -BUZZER_VOLUME,
+            };
+            audio.extend_from_slice(&sample.to_le_bytes());
+            self.phase = (self.phase + BUZZER_FREQUENCY) % self.sample_rate;
+        }
+
+        audio
+    }
+}
+
+/// How many samples come before the start of `frame`.
+pub fn samples_before(frame: u64, sample_rate: u32) -> u64 {
+    frame * sample_rate as u64 / FRAME_RATE
+}
+
+/// Full range BT.601, as JPEG uses.
+fn rgb_to_ycbcr([red, green, blue]: Rgb) -> [u8; 3] {
+    let (red, green, blue) = (red as i32, green as i32, blue as i32);
+    let component =
+        |value: i32, offset: i32| (((value + 32768) >> 16) + offset).clamp(0, 255) as u8;
+
+    [
+        component(19595 * red + 38470 * green + 7471 * blue, 0),
+        component(-11059 * red - 21709 * green + 32768 * blue, 128),
+        component(32768 * red - 27439 * green - 5329 * blue, 128),
+    ]
+}
@@ -0,0 +1,280 @@
+//! Animated GIFs of the screen, encoded as they're recorded.
+//!
+//! Frames come in at 60 Hz, but browsers slow down GIFs with delays under
+//! 2/100 of a second, so frames are shown for whole multiples of that, and
+//! the odd frame that would be shown for no time at all is left out. A frame
+//! the same as the one before just makes that one stay up longer, and one
+//! that isn't only has the part that changed written.
+
+use crate::image::{palette_or_default, Bitmap, ImageError, Rgb, MAX_SCALE};
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::mem;
+
+const FRAMES_PER_SECOND: u64 = 60;
+/// The shortest delay browsers honour, in hundredths of a second.
+const MIN_DELAY: u64 = 2;
+const MAX_CODE_SIZE: u8 = 12;
+const MAX_SUB_BLOCK_SIZE: usize = 255;
+const TRAILER: u8 = 0x3B;
+
+/// Records frames into an animated GIF that loops forever. The file is
+/// written as it goes, so only the frame on screen and the one before it are
+/// kept, besides whatever output hasn't been taken yet.
+pub struct GifRecorder {
+    width: u32,
+    height: u32,
+    scale: u32,
+    /// Bits per pixel in the image data, at least 2 as GIF requires.
+    code_size: u8,
+    /// The screen as the frames written so far leave it.
+    shown: Option<Bitmap>,
+    /// The latest frame, not written yet since it may stay up longer.
+    pending: Option<Bitmap>,
+    frame_count: u64,
+    /// How long the frames written so far are shown for, in hundredths of a
+    /// second.
+    written_time: u64,
+    output: Vec<u8>,
+}
+
+impl GifRecorder {
+    /// Starts a GIF of `width` by `height` frames, every pixel `scale` pixels
+    /// across. Colours past the end of `palette` get its last colour, and an
+    /// empty one is black and white.
+    pub fn new(
+        width: u32,
+        height: u32,
+        scale: u32,
+        palette: &[Rgb],
+    ) -> Result<GifRecorder, ImageError> {
+        if !(1..=MAX_SCALE).contains(&scale) {
+            return Err(ImageError::InvalidScale);
+        }
+        let palette = palette_or_default(palette);
+        let palette = &palette[..palette.len().min(256)];
+        // The colour table holds a power of two colours, at least 2.
+        let table_bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(1) as u8;
+
+        let mut output = Vec::new();
+        output.extend_from_slice(b"GIF89a");
+        output.extend_from_slice(&((width * scale) as u16).to_le_bytes());
+        output.extend_from_slice(&((height * scale) as u16).to_le_bytes());
+        // A global colour table, with 8 bit colours.
+        output.push(0x80 | 0x70 | (table_bits - 1));
+        output.push(0);
+        output.push(0);
+        for index in 0..1 << table_bits {
+            output.extend_from_slice(&palette[index.min(palette.len() - 1)]);
+        }
+        // Loop forever.
+        output.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        output.extend_from_slice(b"NETSCAPE2.0");
+        output.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        Ok(GifRecorder {
+            width,
+            height,
+            scale,
+            code_size: table_bits.max(2),
+            shown: None,
+            pending: None,
+            frame_count: 0,
+            written_time: 0,
+            output,
+        })
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Adds the next 60 Hz frame. Frames of another size are cropped, or
+    /// padded with colour 0.
+    pub fn add_frame(&mut self, frame: &Bitmap) {
+        let frame = if frame.width == self.width && frame.height == self.height {
+            frame.clone()
+        } else {
+            frame.crop(0, 0, self.width, self.height)
+        };
+
+        if self.pending.as_ref() != Some(&frame) {
+            self.write_pending();
+            self.pending = Some(frame);
+        }
+        self.frame_count += 1;
+    }
+
+    /// Takes the part of the file written so far, to save it bit by bit
+    /// rather than all at the end.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::take(&mut self.output)
+    }
+
+    /// Writes the last frame, and gets the rest of the file.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.write_pending();
+        self.output.push(TRAILER);
+
+        self.output
+    }
+
+    /// Writes the pending frame, shown until the current frame.
+    fn write_pending(&mut self) {
+        let frame = match self.pending.take() {
+            None => return,
+            Some(frame) => frame,
+        };
+        // Rounded to the nearest multiple of the shortest delay.
+        let end_time = (self.frame_count * 100 + FRAMES_PER_SECOND * MIN_DELAY / 2)
+            / (FRAMES_PER_SECOND * MIN_DELAY)
+            * MIN_DELAY;
+        let mut delay = end_time - self.written_time;
+        if delay == 0 {
+            return;
+        }
+        self.written_time = end_time;
+
+        // Delays longer than the most a frame has are split over copies of it.
+        while delay > 0 {
+            let frame_delay = delay.min(u16::MAX as u64 - 1) as u16;
+            self.write_image(&frame, frame_delay);
+            self.shown = Some(frame.clone());
+            delay -= frame_delay as u64;
+        }
+    }
+
+    fn write_image(&mut self, frame: &Bitmap, delay: u16) {
+        let (x, y, width, height) = match &self.shown {
+            None => (0, 0, self.width, self.height),
+            Some(shown) => changed_area(shown, frame),
+        };
+        let image = frame.crop(x, y, width, height).scale(self.scale);
+
+        // Graphic control extension: left in place, with the delay.
+        self.output.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+        self.output.extend_from_slice(&delay.to_le_bytes());
+        self.output.extend_from_slice(&[0x00, 0x00]);
+
+        self.output.push(0x2C);
+        let position = [x * self.scale, y * self.scale, image.width, image.height];
+        for value in position.iter() {
+            self.output
+                .extend_from_slice(&(*value as u16).to_le_bytes());
+        }
+        self.output.push(0);
+
+        self.output.push(self.code_size);
+        let data = lzw_encode(self.code_size, &image.pixels);
+        for block in data.chunks(MAX_SUB_BLOCK_SIZE) {
+            self.output.push(block.len() as u8);
+            self.output.extend_from_slice(block);
+        }
+        self.output.push(0);
+    }
+}
+
+/// The smallest rectangle holding every pixel that differs, or the top left
+/// pixel when none do, since an image can't be empty.
+fn changed_area(before: &Bitmap, after: &Bitmap) -> (u32, u32, u32, u32) {
+    let (mut left, mut top, mut right, mut bottom) = (after.width, after.height, 0, 0);
+    for y in 0..after.height {
+        for x in 0..after.width {
+            if before.get(x, y) != after.get(x, y) {
+                left = left.min(x);
+                top = top.min(y);
+                right = right.max(x + 1);
+                bottom = bottom.max(y + 1);
+            }
+        }
+    }
+
+    if right == 0 {
+        (0, 0, 1, 1)
+    } else {
+        (left, top, right - left, bottom - top)
+    }
+}
+
+/// GIF flavoured LZW: variable length codes packed from the least significant
+/// bit, starting one bit wider than the pixels, with the table reset once
+/// it's full.
+fn lzw_encode(min_code_size: u8, pixels: &[u8]) -> Vec<u8> {
+    let clear_code = 1u16 << min_code_size;
+    let end_code = clear_code + 1;
+
+    let mut writer = BitWriter::default();
+    let mut codes: BTreeMap<(u16, u8), u16> = BTreeMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = min_code_size + 1;
+    writer.write(clear_code, code_size);
+
+    let mut prefix: Option<u16> = None;
+    for pixel in pixels.iter().copied() {
+        let current = match prefix {
+            None => {
+                prefix = Some(pixel as u16);
+                continue;
+            }
+            Some(current) => current,
+        };
+        if let Some(code) = codes.get(&(current, pixel)) {
+            prefix = Some(*code);
+            continue;
+        }
+
+        writer.write(current, code_size);
+        // The decoder adds codes a step behind, so widens codes a step later.
+        if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+            code_size += 1;
+        }
+        if next_code < 1 << MAX_CODE_SIZE {
+            codes.insert((current, pixel), next_code);
+            next_code += 1;
+        } else {
+            writer.write(clear_code, code_size);
+            codes.clear();
+            next_code = end_code + 1;
+            code_size = min_code_size + 1;
+        }
+        prefix = Some(pixel as u16);
+    }
+
+    if let Some(current) = prefix {
+        writer.write(current, code_size);
+        if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+            code_size += 1;
+        }
+    }
+    writer.write(end_code, code_size);
+
+    writer.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        self.bytes
+    }
+}
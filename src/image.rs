@@ -0,0 +1,409 @@
+//! Indexed colour bitmaps, and the image formats they're saved in.
+//!
+//! PNG files are written with uncompressed DEFLATE blocks, which keeps the
+//! encoder tiny, and is plenty for images the size of a CHIP-8 screen.
+
+use crate::crc32::{crc32, update_crc32};
+use crate::display::Display;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+pub type Rgb = [u8; 3];
+
+/// Black and white, for bitmaps of lit and unlit pixels.
+pub const MONOCHROME: [Rgb; 2] = [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// The most a stored DEFLATE block holds.
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+/// Enough for a poster of the screen.
+pub const MAX_SCALE: u32 = 64;
+
+/// One byte per pixel, a colour index, in rows from the top left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn new(width: u32, height: u32) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// The screen, in its current resolution, lit pixels being colour 1.
+    pub fn from_display(display: &Display) -> Bitmap {
+        let size = (display.width * display.height) as usize;
+        Bitmap {
+            width: display.width,
+            height: display.height,
+            pixels: display.get_buffer()[..size]
+                .iter()
+                .map(|pixel| (*pixel != 0) as u8)
+                .collect(),
+        }
+    }
+
+    /// Every pixel as a `factor` by `factor` square.
+    pub fn scale(&self, factor: u32) -> Bitmap {
+        let mut scaled = Bitmap::new(self.width * factor, self.height * factor);
+        for y in 0..scaled.height {
+            for x in 0..scaled.width {
+                let colour = self.pixels[(y / factor * self.width + x / factor) as usize];
+                scaled.set(x, y, colour);
+            }
+        }
+
+        scaled
+    }
+
+    /// `None` outside the bitmap.
+    pub fn get(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(self.pixels[(y * self.width + x) as usize])
+    }
+
+    /// Does nothing outside the bitmap.
+    pub fn set(&mut self, x: u32, y: u32, colour: u8) {
+        if x < self.width && y < self.height {
+            self.pixels[(y * self.width + x) as usize] = colour;
+        }
+    }
+
+    /// Copies `other` in with its top left corner at `x`, `y`, clipping
+    /// whatever falls outside.
+    pub fn blit(&mut self, other: &Bitmap, x: u32, y: u32) {
+        for row in 0..other.height {
+            for column in 0..other.width {
+                self.set(
+                    x + column,
+                    y + row,
+                    other.pixels[(row * other.width + column) as usize],
+                );
+            }
+        }
+    }
+
+    /// The part of the bitmap at `x`, `y`, with anything outside it unlit.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Bitmap {
+        let mut cropped = Bitmap::new(width, height);
+        for row in 0..height {
+            for column in 0..width {
+                let colour = self.get(x + column, y + row).unwrap_or(0);
+                cropped.set(column, row, colour);
+            }
+        }
+
+        cropped
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// Not a PBM file, or one that ends early.
+    InvalidPbm,
+    /// A scale of 0 or over [`MAX_SCALE`].
+    InvalidScale,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Black and white.
+    Pbm,
+    /// Greyscale, each colour of the palette by its brightness.
+    Pgm,
+    /// A square per lit pixel, for any size.
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Pbm => "pbm",
+            ImageFormat::Pgm => "pgm",
+            ImageFormat::Svg => "svg",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name {
+            "png" => Some(ImageFormat::Png),
+            "pbm" => Some(ImageFormat::Pbm),
+            "pgm" => Some(ImageFormat::Pgm),
+            "svg" => Some(ImageFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// The screen as an image, every pixel `scale` image pixels across. Colour 0
+/// of `palette` is unlit pixels and colour 1 lit ones, or black and white
+/// when it's empty.
+pub fn screenshot(
+    display: &Display,
+    format: ImageFormat,
+    scale: u32,
+    palette: &[Rgb],
+) -> Result<Vec<u8>, ImageError> {
+    if !(1..=MAX_SCALE).contains(&scale) {
+        return Err(ImageError::InvalidScale);
+    }
+    let bitmap = Bitmap::from_display(display);
+
+    Ok(match format {
+        ImageFormat::Png => encode_png(&bitmap.scale(scale), palette),
+        ImageFormat::Pbm => encode_pbm(&bitmap.scale(scale)),
+        ImageFormat::Pgm => encode_pgm(&bitmap.scale(scale), palette),
+        // Scaled by the viewer, so it stays small.
+        ImageFormat::Svg => encode_svg(&bitmap, scale, palette),
+    })
+}
+
+/// An indexed colour PNG. Colours past the end of `palette` get its last
+/// colour.
+pub fn encode_png(bitmap: &Bitmap, palette: &[Rgb]) -> Vec<u8> {
+    let palette = palette_or_default(palette);
+    let palette = &palette[..palette.len().min(256)];
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&bitmap.width.to_be_bytes());
+    header.extend_from_slice(&bitmap.height.to_be_bytes());
+    // 8 bits per pixel, indexed colour, then the only compression, filter and
+    // interlace methods.
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let mut rows = Vec::with_capacity(((bitmap.width + 1) * bitmap.height) as usize);
+    for row in bitmap.pixels.chunks(bitmap.width.max(1) as usize) {
+        // No filter.
+        rows.push(0);
+        rows.extend(
+            row.iter()
+                .map(|colour| (*colour as usize).min(palette.len() - 1) as u8),
+        );
+    }
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"PLTE", &palette.concat());
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&rows));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+/// A binary PBM, where unlit pixels are white and any other colour black.
+pub fn encode_pbm(bitmap: &Bitmap) -> Vec<u8> {
+    let mut pbm = format!("P4\n{} {}\n", bitmap.width, bitmap.height).into_bytes();
+    for row in bitmap.pixels.chunks(bitmap.width.max(1) as usize) {
+        // Rows are padded to whole bytes.
+        for pixels in row.chunks(8) {
+            let byte = pixels
+                .iter()
+                .enumerate()
+                .filter(|(_, colour)| **colour != 0)
+                .fold(0, |byte, (bit, _)| byte | 0x80 >> bit);
+            pbm.push(byte);
+        }
+    }
+
+    pbm
+}
+
+/// A binary PGM, each colour the brightness of its colour in `palette`.
+pub fn encode_pgm(bitmap: &Bitmap, palette: &[Rgb]) -> Vec<u8> {
+    let palette = palette_or_default(palette);
+    let mut pgm = format!("P5\n{} {}\n255\n", bitmap.width, bitmap.height).into_bytes();
+    pgm.extend(bitmap.pixels.iter().map(|colour| {
+        let [red, green, blue] = colour_at(palette, *colour);
+        // ITU-R BT.601 luma.
+        ((red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000) as u8
+    }));
+
+    pgm
+}
+
+/// An SVG `scale` times the size of the bitmap, with a square for each pixel
+/// that isn't colour 0, which is the background.
+pub fn encode_svg(bitmap: &Bitmap, scale: u32, palette: &[Rgb]) -> Vec<u8> {
+    let palette = palette_or_default(palette);
+    let hex = |[red, green, blue]: Rgb| format!("#{:02x}{:02x}{:02x}", red, green, blue);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">",
+        bitmap.width * scale,
+        bitmap.height * scale,
+        bitmap.width,
+        bitmap.height
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        bitmap.width,
+        bitmap.height,
+        hex(colour_at(palette, 0))
+    )
+    .unwrap();
+
+    // One group per colour, so each square only needs its position.
+    let mut colours = bitmap.pixels.clone();
+    colours.sort_unstable();
+    colours.dedup();
+    for colour in colours.into_iter().filter(|colour| *colour != 0) {
+        writeln!(svg, "<g fill=\"{}\">", hex(colour_at(palette, colour))).unwrap();
+        for (index, _) in bitmap
+            .pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| **pixel == colour)
+        {
+            let (x, y) = (index as u32 % bitmap.width, index as u32 / bitmap.width);
+            writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\"/>",
+                x, y
+            )
+            .unwrap();
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+
+    svg.into_bytes()
+}
+
+/// Reads a plain (P1) or binary (P4) PBM, black pixels becoming colour 1.
+pub fn decode_pbm(pbm: &[u8]) -> Result<Bitmap, ImageError> {
+    let binary = match pbm.get(..2) {
+        Some(b"P1") => false,
+        Some(b"P4") => true,
+        _ => return Err(ImageError::InvalidPbm),
+    };
+    let mut position = 2;
+    let width = read_pbm_number(pbm, &mut position)?;
+    let height = read_pbm_number(pbm, &mut position)?;
+    if width
+        .checked_mul(height)
+        .is_none_or(|size| size > 0x100_0000)
+    {
+        return Err(ImageError::InvalidPbm);
+    }
+    let mut bitmap = Bitmap::new(width, height);
+
+    if binary {
+        // A single whitespace character separates the header from the data.
+        position += 1;
+        let row_size = width.div_ceil(8) as usize;
+        let data = pbm
+            .get(position..position + row_size * height as usize)
+            .ok_or(ImageError::InvalidPbm)?;
+        for (y, row) in data.chunks(row_size).enumerate() {
+            for x in 0..width {
+                let bit = row[(x / 8) as usize] & (0x80 >> (x % 8));
+                bitmap.set(x, y as u32, (bit != 0) as u8);
+            }
+        }
+    } else {
+        let mut pixels = pbm[position..]
+            .iter()
+            .filter(|byte| !byte.is_ascii_whitespace());
+        for pixel in bitmap.pixels.iter_mut() {
+            *pixel = match pixels.next() {
+                Some(b'0') => 0,
+                Some(b'1') => 1,
+                _ => return Err(ImageError::InvalidPbm),
+            };
+        }
+    }
+
+    Ok(bitmap)
+}
+
+/// Skips whitespace and `#` comments, then reads a decimal number.
+fn read_pbm_number(pbm: &[u8], position: &mut usize) -> Result<u32, ImageError> {
+    loop {
+        match pbm.get(*position) {
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            Some(b'#') => {
+                while !matches!(pbm.get(*position), None | Some(b'\n')) {
+                    *position += 1;
+                }
+            }
+            _ => break,
+        }
+    }
+
+    let start = *position;
+    while let Some(b'0'..=b'9') = pbm.get(*position) {
+        *position += 1;
+    }
+    core::str::from_utf8(&pbm[start..*position])
+        .ok()
+        .and_then(|number| number.parse().ok())
+        .ok_or(ImageError::InvalidPbm)
+}
+
+pub(crate) fn palette_or_default(palette: &[Rgb]) -> &[Rgb] {
+    if palette.is_empty() {
+        &MONOCHROME
+    } else {
+        palette
+    }
+}
+
+/// Colours past the end of the palette get its last colour.
+fn colour_at(palette: &[Rgb], colour: u8) -> Rgb {
+    palette[(colour as usize).min(palette.len() - 1)]
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&update_crc32(crc32(kind), data).to_be_bytes());
+}
+
+/// `data` as a zlib stream of stored blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // DEFLATE with a 32 KiB window, no preset dictionary, no compression.
+    let mut zlib = vec![0x78, 0x01];
+    if data.is_empty() {
+        zlib.extend_from_slice(&[1, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    let mut blocks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+
+    zlib
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+
+    b << 16 | a
+}
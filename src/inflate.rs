@@ -0,0 +1,311 @@
+//! A decoder for DEFLATE (RFC 1951), the compression zip archives use.
+//!
+//! It favours being small over being fast, which is plenty for ROM-sized
+//! files: codes are decoded a bit at a time from the canonical code lengths,
+//! without lookup tables.
+
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InflateError {
+    /// The data ends in the middle of a block.
+    Truncated,
+    /// A block type of 3, which is reserved.
+    InvalidBlockType,
+    /// A stored block whose length and its complement don't match.
+    InvalidStoredLength,
+    /// Code lengths that don't make a usable Huffman code.
+    InvalidCodeLengths,
+    /// A bit pattern no symbol has, or a symbol no code should produce.
+    InvalidSymbol,
+    /// A back reference to before the start of the output.
+    InvalidDistance,
+    /// The output would be larger than the limit given.
+    TooLarge,
+}
+
+const MAX_BITS: usize = 15;
+
+/// Base lengths and extra bits of length symbols 257-285.
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances and extra bits of distance symbols 0-29.
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses raw DEFLATE data, failing rather than producing more than
+/// `max_size` bytes.
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>, InflateError> {
+    let mut reader = BitReader {
+        data,
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output, max_size)?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                compressed_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut reader)?;
+                compressed_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            _ => return Err(InflateError::InvalidBlockType),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn stored_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<(), InflateError> {
+    reader.align();
+    let length = reader.bits(16)?;
+    if reader.bits(16)? != !length & 0xFFFF {
+        return Err(InflateError::InvalidStoredLength);
+    }
+    if output.len() + length as usize > max_size {
+        return Err(InflateError::TooLarge);
+    }
+    output.extend_from_slice(reader.bytes(length as usize)?);
+
+    Ok(())
+}
+
+fn compressed_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        if symbol < 256 {
+            if output.len() == max_size {
+                return Err(InflateError::TooLarge);
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASES.len() {
+            return Err(InflateError::InvalidSymbol);
+        }
+        let length =
+            LENGTH_BASES[symbol] as usize + reader.bits(LENGTH_EXTRA_BITS[symbol])? as usize;
+        let symbol = distances.decode(reader)? as usize;
+        if symbol >= DISTANCE_BASES.len() {
+            return Err(InflateError::InvalidSymbol);
+        }
+        let distance =
+            DISTANCE_BASES[symbol] as usize + reader.bits(DISTANCE_EXTRA_BITS[symbol])? as usize;
+
+        if distance > output.len() {
+            return Err(InflateError::InvalidDistance);
+        }
+        if output.len() + length > max_size {
+            return Err(InflateError::TooLarge);
+        }
+        // One byte at a time, since a copy may overlap what it produces.
+        let start = output.len() - distance;
+        for index in 0..length {
+            let byte = output[start + index];
+            output.push(byte);
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    // The fixed codes are complete, so they can't fail to build.
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5; 30]).unwrap(),
+    )
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let length_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for position in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*position] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    // Literal/length and distance code lengths are one sequence, so a repeat
+    // may run from one into the other.
+    let mut lengths = [0; 288 + 32];
+    let total = length_count + distance_count;
+    let mut index = 0;
+    while index < total {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or(InflateError::InvalidCodeLengths)?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > total {
+            return Err(InflateError::InvalidCodeLengths);
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        // Without an end of block code the block could never end.
+        return Err(InflateError::InvalidCodeLengths);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..length_count])?,
+        Huffman::new(&lengths[length_count..total])?,
+    ))
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the
+/// symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, InflateError> {
+        let mut counts = [0; MAX_BITS + 1];
+        lengths
+            .iter()
+            .for_each(|length| counts[*length as usize] += 1);
+        counts[0] = 0;
+
+        // More codes of a length than there's room for can't be decoded.
+        // Fewer is allowed, DEFLATE uses incomplete codes for one symbol.
+        let mut left: i32 = 1;
+        for count in counts.iter().skip(1) {
+            left = left * 2 - *count as i32;
+            if left < 0 {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+        }
+
+        let mut offsets = [0; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = alloc::vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        // Codes of each length follow on from the last code of the length
+        // before, so a code is the index of its symbol past `first`.
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for count in self.counts.iter().skip(1) {
+            code |= reader.bits(1)? as i32;
+            let count = *count as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(InflateError::InvalidSymbol)
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    /// The next `count` bits, least significant first.
+    fn bits(&mut self, count: u8) -> Result<u32, InflateError> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(InflateError::Truncated)?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let bits = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+
+        Ok(bits)
+    }
+
+    /// Skips to the next byte boundary.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], InflateError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.saturating_add(length))
+            .ok_or(InflateError::Truncated)?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+}
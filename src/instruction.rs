@@ -0,0 +1,335 @@
+use core::fmt;
+
+/// A decoded opcode. `x` and `y` are register indices, addresses are 12-bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 1NNN
+    Jump {
+        address: u16,
+    },
+    /// 2NNN
+    Call {
+        address: u16,
+    },
+    /// 3XNN
+    SkipIfEqual {
+        x: usize,
+        value: u8,
+    },
+    /// 4XNN
+    SkipIfNotEqual {
+        x: usize,
+        value: u8,
+    },
+    /// 5XY0
+    SkipIfRegistersEqual {
+        x: usize,
+        y: usize,
+    },
+    /// 6XNN
+    SetRegister {
+        x: usize,
+        value: u8,
+    },
+    /// 7XNN
+    AddToRegister {
+        x: usize,
+        value: u8,
+    },
+    /// 8XY0
+    CopyRegister {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY1
+    Or {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY2
+    And {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY3
+    Xor {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY4
+    Add {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY5
+    Subtract {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY6
+    ShiftRight {
+        x: usize,
+        y: usize,
+    },
+    /// 8XY7
+    SubtractReversed {
+        x: usize,
+        y: usize,
+    },
+    /// 8XYE
+    ShiftLeft {
+        x: usize,
+        y: usize,
+    },
+    /// 9XY0
+    SkipIfRegistersNotEqual {
+        x: usize,
+        y: usize,
+    },
+    /// ANNN
+    SetIndex {
+        address: u16,
+    },
+    /// BNNN, `x` is only used with the `jump_uses_vx` quirk.
+    JumpWithOffset {
+        address: u16,
+        x: usize,
+    },
+    /// CXNN
+    Random {
+        x: usize,
+        mask: u8,
+    },
+    /// DXYN
+    Draw {
+        x: usize,
+        y: usize,
+        height: u16,
+    },
+    /// EX9E
+    SkipIfKeyPressed {
+        x: usize,
+    },
+    /// EXA1
+    SkipIfKeyNotPressed {
+        x: usize,
+    },
+    /// FX07
+    GetDelayTimer {
+        x: usize,
+    },
+    /// FX0A
+    WaitForKey {
+        x: usize,
+    },
+    /// FX15
+    SetDelayTimer {
+        x: usize,
+    },
+    /// FX18
+    SetSoundTimer {
+        x: usize,
+    },
+    /// FX1E
+    AddToIndex {
+        x: usize,
+    },
+    /// FX29
+    SetIndexToFont {
+        x: usize,
+    },
+    /// FX33
+    StoreBcd {
+        x: usize,
+    },
+    /// FX55
+    StoreRegisters {
+        x: usize,
+    },
+    /// FX65
+    LoadRegisters {
+        x: usize,
+    },
+    Unknown {
+        opcode: u16,
+    },
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Instruction {
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        match opcode & 0xF000 {
+            0x0000 => match opcode & 0x000F {
+                0x0000 => Instruction::ClearScreen,
+                0x000E => Instruction::Return,
+                _ => Instruction::Unknown { opcode },
+            },
+            0x1000 => Instruction::Jump { address: nnn },
+            0x2000 => Instruction::Call { address: nnn },
+            0x3000 => Instruction::SkipIfEqual { x, value: nn },
+            0x4000 => Instruction::SkipIfNotEqual { x, value: nn },
+            0x5000 => Instruction::SkipIfRegistersEqual { x, y },
+            0x6000 => Instruction::SetRegister { x, value: nn },
+            0x7000 => Instruction::AddToRegister { x, value: nn },
+            0x8000 => match opcode & 0x000F {
+                0x0000 => Instruction::CopyRegister { x, y },
+                0x0001 => Instruction::Or { x, y },
+                0x0002 => Instruction::And { x, y },
+                0x0003 => Instruction::Xor { x, y },
+                0x0004 => Instruction::Add { x, y },
+                0x0005 => Instruction::Subtract { x, y },
+                0x0006 => Instruction::ShiftRight { x, y },
+                0x0007 => Instruction::SubtractReversed { x, y },
+                0x000E => Instruction::ShiftLeft { x, y },
+                _ => Instruction::Unknown { opcode },
+            },
+            0x9000 => Instruction::SkipIfRegistersNotEqual { x, y },
+            0xA000 => Instruction::SetIndex { address: nnn },
+            0xB000 => Instruction::JumpWithOffset { address: nnn, x },
+            0xC000 => Instruction::Random { x, mask: nn },
+            0xD000 => Instruction::Draw {
+                x,
+                y,
+                height: opcode & 0x000F,
+            },
+            0xE000 => match opcode & 0x000F {
+                0x000E => Instruction::SkipIfKeyPressed { x },
+                0x0001 => Instruction::SkipIfKeyNotPressed { x },
+                _ => Instruction::Unknown { opcode },
+            },
+            0xF000 => match opcode & 0x00FF {
+                0x0007 => Instruction::GetDelayTimer { x },
+                0x000A => Instruction::WaitForKey { x },
+                0x0015 => Instruction::SetDelayTimer { x },
+                0x0018 => Instruction::SetSoundTimer { x },
+                0x001E => Instruction::AddToIndex { x },
+                0x0029 => Instruction::SetIndexToFont { x },
+                0x0033 => Instruction::StoreBcd { x },
+                0x0055 => Instruction::StoreRegisters { x },
+                0x0065 => Instruction::LoadRegisters { x },
+                _ => Instruction::Unknown { opcode },
+            },
+            _ => Instruction::Unknown { opcode },
+        }
+    }
+
+    /// The opcode pattern naming the instruction's class, such as `8XY4`.
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::ClearScreen => "00E0",
+            Instruction::Return => "00EE",
+            Instruction::Jump { .. } => "1NNN",
+            Instruction::Call { .. } => "2NNN",
+            Instruction::SkipIfEqual { .. } => "3XNN",
+            Instruction::SkipIfNotEqual { .. } => "4XNN",
+            Instruction::SkipIfRegistersEqual { .. } => "5XY0",
+            Instruction::SetRegister { .. } => "6XNN",
+            Instruction::AddToRegister { .. } => "7XNN",
+            Instruction::CopyRegister { .. } => "8XY0",
+            Instruction::Or { .. } => "8XY1",
+            Instruction::And { .. } => "8XY2",
+            Instruction::Xor { .. } => "8XY3",
+            Instruction::Add { .. } => "8XY4",
+            Instruction::Subtract { .. } => "8XY5",
+            Instruction::ShiftRight { .. } => "8XY6",
+            Instruction::SubtractReversed { .. } => "8XY7",
+            Instruction::ShiftLeft { .. } => "8XYE",
+            Instruction::SkipIfRegistersNotEqual { .. } => "9XY0",
+            Instruction::SetIndex { .. } => "ANNN",
+            Instruction::JumpWithOffset { .. } => "BNNN",
+            Instruction::Random { .. } => "CXNN",
+            Instruction::Draw { .. } => "DXYN",
+            Instruction::SkipIfKeyPressed { .. } => "EX9E",
+            Instruction::SkipIfKeyNotPressed { .. } => "EXA1",
+            Instruction::GetDelayTimer { .. } => "FX07",
+            Instruction::WaitForKey { .. } => "FX0A",
+            Instruction::SetDelayTimer { .. } => "FX15",
+            Instruction::SetSoundTimer { .. } => "FX18",
+            Instruction::AddToIndex { .. } => "FX1E",
+            Instruction::SetIndexToFont { .. } => "FX29",
+            Instruction::StoreBcd { .. } => "FX33",
+            Instruction::StoreRegisters { .. } => "FX55",
+            Instruction::LoadRegisters { .. } => "FX65",
+            Instruction::Unknown { .. } => "????",
+        }
+    }
+
+    /// Whether the instruction can move the program counter anywhere but the
+    /// next instruction, which ends a straight-line run of code.
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Return
+                | Instruction::Jump { .. }
+                | Instruction::Call { .. }
+                | Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. }
+                | Instruction::JumpWithOffset { .. }
+                | Instruction::SkipIfKeyPressed { .. }
+                | Instruction::SkipIfKeyNotPressed { .. }
+                | Instruction::WaitForKey { .. }
+                | Instruction::Unknown { .. }
+        )
+    }
+}
+
+/// Disassembles in the common `LD V0, 0x12` syntax, unknown opcodes as data.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Jump { address } => write!(f, "JP 0x{:03X}", address),
+            Instruction::Call { address } => write!(f, "CALL 0x{:03X}", address),
+            Instruction::SkipIfEqual { x, value } => write!(f, "SE V{:X}, 0x{:02X}", x, value),
+            Instruction::SkipIfNotEqual { x, value } => {
+                write!(f, "SNE V{:X}, 0x{:02X}", x, value)
+            }
+            Instruction::SkipIfRegistersEqual { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SetRegister { x, value } => write!(f, "LD V{:X}, 0x{:02X}", x, value),
+            Instruction::AddToRegister { x, value } => {
+                write!(f, "ADD V{:X}, 0x{:02X}", x, value)
+            }
+            Instruction::CopyRegister { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Subtract { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubtractReversed { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfRegistersNotEqual { x, y } => {
+                write!(f, "SNE V{:X}, V{:X}", x, y)
+            }
+            Instruction::SetIndex { address } => write!(f, "LD I, 0x{:03X}", address),
+            Instruction::JumpWithOffset { address, .. } => write!(f, "JP V0, 0x{:03X}", address),
+            Instruction::Random { x, mask } => write!(f, "RND V{:X}, 0x{:02X}", x, mask),
+            Instruction::Draw { x, y, height } => {
+                write!(f, "DRW V{:X}, V{:X}, {}", x, y, height)
+            }
+            Instruction::SkipIfKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::GetDelayTimer { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitForKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddToIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::SetIndexToFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::Unknown { opcode } => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Just enough JSON (RFC 8259) to read manifests: the whole syntax is parsed,
+//! but numbers are kept as `f64`, like JavaScript does.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Members in the order they appear in.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn parse(text: &str) -> Result<JsonValue, JsonError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value(0)?;
+        parser.whitespace();
+        if parser.position != text.len() {
+            return Err(parser.error());
+        }
+
+        Ok(value)
+    }
+
+    /// The member of an object with this name, the last one if there are
+    /// several.
+    pub fn get(&self, name: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(member, _)| member == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            JsonValue::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Where, in bytes from the start, the text stops being valid JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub position: usize,
+}
+
+/// Deeper nesting than any manifest needs, which would otherwise let a
+/// malicious file overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error());
+        }
+        self.whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string().map(JsonValue::String),
+            Some(b't') => self.literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.literal("null", JsonValue::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        self.position += 1;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.eat(b'}') {
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error());
+            }
+            let name = self.string()?;
+            self.whitespace();
+            if !self.eat(b':') {
+                return Err(self.error());
+            }
+            members.push((name, self.value(depth + 1)?));
+            self.whitespace();
+            if self.eat(b'}') {
+                return Ok(JsonValue::Object(members));
+            }
+            if !self.eat(b',') {
+                return Err(self.error());
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        self.position += 1;
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.eat(b']') {
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.value(depth + 1)?);
+            self.whitespace();
+            if self.eat(b']') {
+                return Ok(JsonValue::Array(values));
+            }
+            if !self.eat(b',') {
+                return Err(self.error());
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            let start = self.position;
+            while let Some(byte) = self.peek() {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.position += 1;
+            }
+            // The text came from a `&str` and this stops at ASCII bytes, so
+            // it's always valid UTF-8.
+            string.push_str(core::str::from_utf8(&self.text[start..self.position]).unwrap());
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.position += 1;
+                            string.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.error()),
+                    };
+                    self.position += 1;
+                    string.push(escaped);
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, after the `u`, combining surrogate
+    /// pairs. Unpaired surrogates become U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or('\u{FFFD}'));
+        }
+        if !self.text[self.position..].starts_with(b"\\u") {
+            return Ok('\u{FFFD}');
+        }
+        self.position += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Ok('\u{FFFD}');
+        }
+
+        Ok(char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).unwrap())
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .and_then(|digits| core::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error())?;
+        self.position += 4;
+
+        Ok(digits)
+    }
+
+    fn number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.position;
+        self.eat(b'-');
+        if !self.eat(b'0') && !self.digits() {
+            return Err(self.error());
+        }
+        if self.eat(b'.') && !self.digits() {
+            return Err(self.error());
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            let _ = self.eat(b'+') || self.eat(b'-');
+            if !self.digits() {
+                return Err(self.error());
+            }
+        }
+
+        core::str::from_utf8(&self.text[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(JsonValue::Number)
+            .ok_or(JsonError { position: start })
+    }
+
+    /// Whether there was at least one digit.
+    fn digits(&mut self) -> bool {
+        let start = self.position;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+
+        self.position > start
+    }
+
+    fn literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        if !self.text[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.error());
+        }
+        self.position += literal.len();
+
+        Ok(value)
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matches = self.peek() == Some(byte);
+        if matches {
+            self.position += 1;
+        }
+
+        matches
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).copied()
+    }
+
+    fn error(&self) -> JsonError {
+        JsonError {
+            position: self.position,
+        }
+    }
+}
@@ -1 +1,56 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod chip8_cpu;
+pub mod coverage;
+pub mod crc32;
+pub mod display;
+pub mod instruction;
+pub mod platform;
+pub mod quirks;
+pub mod speed;
+pub mod traits;
+
+#[cfg(feature = "alloc")]
+pub mod analysis;
+#[cfg(feature = "alloc")]
+pub mod archive;
+#[cfg(feature = "alloc")]
+pub mod block_cache;
+#[cfg(feature = "alloc")]
+pub mod cheats;
+#[cfg(feature = "alloc")]
+pub mod export;
+#[cfg(feature = "alloc")]
+pub mod gif;
+#[cfg(feature = "alloc")]
+pub mod image;
+#[cfg(feature = "alloc")]
+pub mod inflate;
+#[cfg(feature = "alloc")]
+pub mod json;
+#[cfg(feature = "alloc")]
+pub mod library;
+#[cfg(feature = "alloc")]
+pub mod movie;
+#[cfg(feature = "alloc")]
+pub mod patch;
+#[cfg(feature = "alloc")]
+pub mod profiler;
+#[cfg(feature = "alloc")]
+pub mod reference;
+#[cfg(feature = "alloc")]
+pub mod sprite;
+#[cfg(feature = "alloc")]
+pub mod trace;
+
+#[cfg(feature = "std")]
 pub mod chip8;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "recompiler")]
+pub mod recompiler;
+#[cfg(feature = "std")]
+pub mod snapshot;
@@ -0,0 +1,194 @@
+//! The games a frontend offers, by name, with what's known about each of them.
+//!
+//! Games are kept sorted by name, so listings come out in the same order
+//! every time.
+
+use crate::chip8_cpu::MAX_ROM_SIZE;
+use crate::crc32::crc32;
+use crate::quirks::Quirks;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// The machine a ROM was written for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GamePlatform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl GamePlatform {
+    pub fn name(self) -> &'static str {
+        match self {
+            GamePlatform::Chip8 => "chip8",
+            GamePlatform::SuperChip => "schip",
+            GamePlatform::XoChip => "xochip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GamePlatform> {
+        match name {
+            "chip8" => Some(GamePlatform::Chip8),
+            "schip" => Some(GamePlatform::SuperChip),
+            "xochip" => Some(GamePlatform::XoChip),
+            _ => None,
+        }
+    }
+}
+
+/// Everything is optional, an empty string meaning unknown.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameMetadata {
+    pub platform: GamePlatform,
+    pub author: String,
+    pub year: Option<u16>,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub licence: String,
+    /// The quirks the game needs, when known.
+    pub quirks: Option<Quirks>,
+    /// The speed the game was meant to run at, when known.
+    pub instructions_per_frame: Option<u32>,
+    /// Which keypad keys do what.
+    pub key_help: String,
+}
+
+impl GameMetadata {
+    /// Tags are compared ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| own.eq_ignore_ascii_case(tag))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    pub name: String,
+    pub rom: Vec<u8>,
+    pub metadata: GameMetadata,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LibraryError {
+    /// There's no game by that name.
+    UnknownGame,
+    /// Another game already has that name.
+    NameTaken,
+    EmptyName,
+    /// The ROM has this many bytes, more than fit in memory, see
+    /// [`MAX_ROM_SIZE`].
+    RomTooLarge(usize),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameLibrary {
+    games: BTreeMap<String, Game>,
+}
+
+impl GameLibrary {
+    pub fn new() -> GameLibrary {
+        GameLibrary::default()
+    }
+
+    pub fn add(
+        &mut self,
+        name: &str,
+        rom: Vec<u8>,
+        metadata: GameMetadata,
+    ) -> Result<(), LibraryError> {
+        self.check_name(name)?;
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(LibraryError::RomTooLarge(rom.len()));
+        }
+        self.games.insert(
+            name.to_string(),
+            Game {
+                name: name.to_string(),
+                rom,
+                metadata,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Game, LibraryError> {
+        self.games.remove(name).ok_or(LibraryError::UnknownGame)
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), LibraryError> {
+        if name == new_name && self.games.contains_key(name) {
+            return Ok(());
+        }
+        self.check_name(new_name)?;
+        let mut game = self.remove(name)?;
+        game.name = new_name.to_string();
+        self.games.insert(game.name.clone(), game);
+
+        Ok(())
+    }
+
+    pub fn set_metadata(&mut self, name: &str, metadata: GameMetadata) -> Result<(), LibraryError> {
+        let game = self.games.get_mut(name).ok_or(LibraryError::UnknownGame)?;
+        game.metadata = metadata;
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Game> {
+        self.games.get(name)
+    }
+
+    /// The game whose ROM has this CRC-32, like the one a movie was recorded on.
+    pub fn find_by_crc32(&self, rom_crc32: u32) -> Option<&Game> {
+        self.iter().find(|game| crc32(&game.rom) == rom_crc32)
+    }
+
+    /// All games, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Game> {
+        self.games.values()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.games.keys().map(String::as_str)
+    }
+
+    /// The games with `query` in their name, author, description or tags,
+    /// ignoring case, sorted by name.
+    pub fn search<'a>(&'a self, query: &str) -> impl Iterator<Item = &'a Game> {
+        let query = query.to_lowercase();
+        self.iter().filter(move |game| {
+            let metadata = &game.metadata;
+            [&game.name, &metadata.author, &metadata.description]
+                .iter()
+                .copied()
+                .chain(metadata.tags.iter())
+                .any(|text| text.to_lowercase().contains(&query))
+        })
+    }
+
+    pub fn with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a Game> {
+        self.iter().filter(move |game| game.metadata.has_tag(tag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), LibraryError> {
+        if name.trim().is_empty() {
+            return Err(LibraryError::EmptyName);
+        }
+        if self.games.contains_key(name) {
+            return Err(LibraryError::NameTaken);
+        }
+
+        Ok(())
+    }
+}
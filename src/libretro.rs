@@ -0,0 +1,732 @@
+//! A libretro core wrapping [`Chip8CPU`], so the emulator runs inside RetroArch
+//! and other libretro frontends. Build with `--features libretro`, the cdylib
+//! then exports the `retro_*` entry points.
+//!
+//! Only the parts of `libretro.h` this core uses are declared here.
+
+use crate::chip8_cpu::{Chip8CPU, MEMORY_SIZE, STATE_SIZE};
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::platform::XorShiftRng;
+use crate::quirks::Quirks;
+use crate::traits::{Audio, Logger, Observer, RandomSource};
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A NUL terminated string literal as a C string pointer.
+macro_rules! cstr {
+    ($s:expr) => {
+        concat!($s, "\0").as_ptr() as *const c_char
+    };
+}
+
+pub const RETRO_API_VERSION: c_uint = 1;
+
+pub const RETRO_DEVICE_JOYPAD: c_uint = 1;
+pub const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+
+pub const RETRO_DEVICE_ID_JOYPAD_B: c_uint = 0;
+pub const RETRO_DEVICE_ID_JOYPAD_Y: c_uint = 1;
+pub const RETRO_DEVICE_ID_JOYPAD_SELECT: c_uint = 2;
+pub const RETRO_DEVICE_ID_JOYPAD_START: c_uint = 3;
+pub const RETRO_DEVICE_ID_JOYPAD_UP: c_uint = 4;
+pub const RETRO_DEVICE_ID_JOYPAD_DOWN: c_uint = 5;
+pub const RETRO_DEVICE_ID_JOYPAD_LEFT: c_uint = 6;
+pub const RETRO_DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
+pub const RETRO_DEVICE_ID_JOYPAD_A: c_uint = 8;
+pub const RETRO_DEVICE_ID_JOYPAD_X: c_uint = 9;
+
+pub const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+pub const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+pub const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+pub const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+pub const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+pub const RETRO_ENVIRONMENT_GET_LOG_INTERFACE: c_uint = 27;
+
+pub const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+
+pub const RETRO_PIXEL_FORMAT_XRGB8888: c_int = 1;
+pub const RETRO_REGION_NTSC: c_uint = 0;
+pub const RETRO_LOG_INFO: c_int = 1;
+pub const RETRO_LOG_ERROR: c_int = 3;
+
+pub type RetroEnvironment = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type RetroVideoRefresh =
+    unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type RetroAudioSample = unsafe extern "C" fn(left: i16, right: i16);
+pub type RetroAudioSampleBatch = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type RetroInputPoll = unsafe extern "C" fn();
+pub type RetroInputState =
+    unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+pub type RetroLogPrintf = unsafe extern "C" fn(level: c_int, fmt: *const c_char, ...);
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[repr(C)]
+pub struct RetroVariable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+#[repr(C)]
+pub struct RetroInputDescriptor {
+    pub port: c_uint,
+    pub device: c_uint,
+    pub index: c_uint,
+    pub id: c_uint,
+    pub description: *const c_char,
+}
+
+#[repr(C)]
+pub struct RetroLogCallback {
+    pub log: Option<RetroLogPrintf>,
+}
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: usize = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE / 60;
+const BUZZER_FREQUENCY: usize = 440;
+const BUZZER_VOLUME: i16 = 4000;
+const ON_PIXEL: u32 = 0x00FF_FFFF;
+const OFF_PIXEL: u32 = 0x0000_0000;
+
+const SPEED_VARIABLE: *const c_char = cstr!("chip8_speed");
+const SHIFT_QUIRK_VARIABLE: *const c_char = cstr!("chip8_quirk_shift");
+const LOAD_STORE_QUIRK_VARIABLE: *const c_char = cstr!("chip8_quirk_load_store");
+const JUMP_QUIRK_VARIABLE: *const c_char = cstr!("chip8_quirk_jump");
+const VF_RESET_QUIRK_VARIABLE: *const c_char = cstr!("chip8_quirk_vf_reset");
+const KEY_WAIT_QUIRK_VARIABLE: *const c_char = cstr!("chip8_quirk_key_wait");
+
+/// Keyboard layout, COSMAC VIP keypad on the left hand side of a QWERTY
+/// keyboard. Values are `retro_key` codes, which match ASCII for these keys.
+const KEYBOARD_MAP: [(c_uint, usize); 16] = [
+    (b'1' as c_uint, 0x1),
+    (b'2' as c_uint, 0x2),
+    (b'3' as c_uint, 0x3),
+    (b'4' as c_uint, 0xC),
+    (b'q' as c_uint, 0x4),
+    (b'w' as c_uint, 0x5),
+    (b'e' as c_uint, 0x6),
+    (b'r' as c_uint, 0xD),
+    (b'a' as c_uint, 0x7),
+    (b's' as c_uint, 0x8),
+    (b'd' as c_uint, 0x9),
+    (b'f' as c_uint, 0xE),
+    (b'z' as c_uint, 0xA),
+    (b'x' as c_uint, 0x0),
+    (b'c' as c_uint, 0xB),
+    (b'v' as c_uint, 0xF),
+];
+
+/// Joypad layout, most games steer with 2/4/6/8 and act with 5.
+const JOYPAD_MAP: [(c_uint, usize, *const c_char); 8] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2, cstr!("Up (2)")),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8, cstr!("Down (8)")),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4, cstr!("Left (4)")),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6, cstr!("Right (6)")),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5, cstr!("Action (5)")),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0, cstr!("0")),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0xA, cstr!("A")),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0xB, cstr!("B")),
+];
+
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+    log: Option<RetroLogPrintf>,
+}
+
+/// Platform for libretro: log messages go to the frontend's log interface and
+/// the buzzer state is sampled by `retro_run`.
+struct RetroPlatform {
+    rng: XorShiftRng,
+    log: Option<RetroLogPrintf>,
+    buzzer_on: bool,
+}
+
+impl RandomSource for RetroPlatform {
+    fn random_byte(&mut self) -> u8 {
+        self.rng.random_byte()
+    }
+}
+
+impl Logger for RetroPlatform {
+    fn log(&mut self, message: fmt::Arguments) {
+        log_message(self.log, RETRO_LOG_INFO, message);
+    }
+}
+
+impl Audio for RetroPlatform {
+    fn set_buzzer(&mut self, on: bool) {
+        self.buzzer_on = on;
+    }
+}
+
+impl Observer for RetroPlatform {}
+
+struct Core {
+    cpu: Chip8CPU<RetroPlatform>,
+    rom: Vec<u8>,
+    instructions_per_frame: u32,
+    frame_buffer: Vec<u32>,
+    audio_buffer: Vec<i16>,
+    audio_phase: usize,
+    /// Set once the interpreter panicked, the core then only outputs the last
+    /// frame until the game is reset or reloaded.
+    crashed: bool,
+}
+
+impl Core {
+    fn new(rom: Vec<u8>, log: Option<RetroLogPrintf>) -> Core {
+        let mut core = Core {
+            cpu: Chip8CPU::with_platform(make_platform(log)),
+            rom,
+            instructions_per_frame: 10,
+            frame_buffer: vec![OFF_PIXEL; (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize],
+            audio_buffer: vec![0; SAMPLES_PER_FRAME * 2],
+            audio_phase: 0,
+            crashed: false,
+        };
+        core.reset();
+
+        core
+    }
+
+    fn reset(&mut self) {
+        let quirks = self.cpu.get_quirks();
+        let log = self.cpu.platform().log;
+        self.cpu = Chip8CPU::with_platform(make_platform(log));
+        self.cpu.set_quirks(quirks);
+        self.cpu
+            .load_rom(&self.rom)
+            .expect("retro_load_game checked the ROM fits");
+        self.crashed = false;
+    }
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+    log: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn callbacks() -> std::sync::MutexGuard<'static, Callbacks> {
+    CALLBACKS.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+fn core() -> std::sync::MutexGuard<'static, Option<Core>> {
+    CORE.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+fn make_platform(log: Option<RetroLogPrintf>) -> RetroPlatform {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0);
+
+    RetroPlatform {
+        rng: XorShiftRng::new(seed),
+        log,
+        buzzer_on: false,
+    }
+}
+
+fn log_message(log: Option<RetroLogPrintf>, level: c_int, message: fmt::Arguments) {
+    let log = match log {
+        None => return,
+        Some(log) => log,
+    };
+    let message = format!("[chip8] {}\n", message).replace('\0', "");
+    if let Ok(message) = CString::new(message) {
+        unsafe { log(level, cstr!("%s"), message.as_ptr()) };
+    }
+}
+
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match callbacks().environment {
+        None => false,
+        Some(environment) => unsafe { environment(cmd, data) },
+    }
+}
+
+fn get_variable(key: *const c_char) -> Option<String> {
+    let mut variable = RetroVariable {
+        key,
+        value: ptr::null(),
+    };
+    if !environment(
+        RETRO_ENVIRONMENT_GET_VARIABLE,
+        &mut variable as *mut RetroVariable as *mut c_void,
+    ) || variable.value.is_null()
+    {
+        return None;
+    }
+
+    Some(
+        unsafe { CStr::from_ptr(variable.value) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+fn get_enabled_variable(key: *const c_char, default: bool) -> bool {
+    match get_variable(key).as_deref() {
+        Some("enabled") => true,
+        Some("disabled") => false,
+        _ => default,
+    }
+}
+
+fn apply_variables(core: &mut Core) {
+    if let Some(speed) = get_variable(SPEED_VARIABLE).and_then(|speed| speed.parse().ok()) {
+        core.instructions_per_frame = speed;
+    }
+
+    let defaults = Quirks::new();
+    core.cpu.set_quirks(Quirks {
+        shift_uses_vy: get_enabled_variable(SHIFT_QUIRK_VARIABLE, defaults.shift_uses_vy),
+        load_store_increments_i: get_enabled_variable(
+            LOAD_STORE_QUIRK_VARIABLE,
+            defaults.load_store_increments_i,
+        ),
+        jump_uses_vx: get_enabled_variable(JUMP_QUIRK_VARIABLE, defaults.jump_uses_vx),
+        vf_reset: get_enabled_variable(VF_RESET_QUIRK_VARIABLE, defaults.vf_reset),
+        key_wait_release: get_enabled_variable(KEY_WAIT_QUIRK_VARIABLE, defaults.key_wait_release),
+    });
+}
+
+fn poll_keys(core: &mut Core) {
+    let (input_poll, input_state) = {
+        let callbacks = callbacks();
+        (callbacks.input_poll, callbacks.input_state)
+    };
+    if let Some(input_poll) = input_poll {
+        unsafe { input_poll() };
+    }
+    let input_state = match input_state {
+        None => return,
+        Some(input_state) => input_state,
+    };
+
+    let mut pressed = [false; 16];
+    KEYBOARD_MAP.iter().for_each(|(retro_key, key)| {
+        pressed[*key] |= unsafe { input_state(0, RETRO_DEVICE_KEYBOARD, 0, *retro_key) } != 0;
+    });
+    JOYPAD_MAP.iter().for_each(|(button, key, _)| {
+        pressed[*key] |= unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, *button) } != 0;
+    });
+    pressed
+        .iter()
+        .enumerate()
+        .for_each(|(key, pressed)| core.cpu.set_key(key, *pressed));
+}
+
+fn render_audio(core: &mut Core) {
+    let period = SAMPLE_RATE / BUZZER_FREQUENCY;
+    let buzzer_on = core.cpu.platform().buzzer_on;
+    let mut phase = core.audio_phase;
+    core.audio_buffer.chunks_mut(2).for_each(|frame| {
+        let sample = match (buzzer_on, phase < period / 2) {
+            (false, _) => 0,
+            (true, true) => BUZZER_VOLUME,
+            (true, false) => -BUZZER_VOLUME,
+        };
+        frame[0] = sample;
+        frame[1] = sample;
+        phase = (phase + 1) % period;
+    });
+    core.audio_phase = phase;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: RetroEnvironment) {
+    callbacks().environment = Some(callback);
+
+    let speed_value = cstr!("Instructions per frame; 10|5|15|20|30|50|100|200");
+    let variables = [
+        RetroVariable {
+            key: SPEED_VARIABLE,
+            value: speed_value,
+        },
+        RetroVariable {
+            key: SHIFT_QUIRK_VARIABLE,
+            value: cstr!("Quirk: 8XY6/8XYE shift VY; disabled|enabled"),
+        },
+        RetroVariable {
+            key: LOAD_STORE_QUIRK_VARIABLE,
+            value: cstr!("Quirk: FX55/FX65 increment I; enabled|disabled"),
+        },
+        RetroVariable {
+            key: JUMP_QUIRK_VARIABLE,
+            value: cstr!("Quirk: BNNN jumps with VX; disabled|enabled"),
+        },
+        RetroVariable {
+            key: VF_RESET_QUIRK_VARIABLE,
+            value: cstr!("Quirk: logic ops reset VF; disabled|enabled"),
+        },
+        RetroVariable {
+            key: KEY_WAIT_QUIRK_VARIABLE,
+            value: cstr!("Quirk: FX0A waits for release; disabled|enabled"),
+        },
+        RetroVariable {
+            key: ptr::null(),
+            value: ptr::null(),
+        },
+    ];
+    environment(
+        RETRO_ENVIRONMENT_SET_VARIABLES,
+        variables.as_ptr() as *mut c_void,
+    );
+
+    let mut log_callback = RetroLogCallback { log: None };
+    if environment(
+        RETRO_ENVIRONMENT_GET_LOG_INTERFACE,
+        &mut log_callback as *mut RetroLogCallback as *mut c_void,
+    ) {
+        callbacks().log = log_callback.log;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: RetroVideoRefresh) {
+    callbacks().video_refresh = Some(callback);
+}
+
+/// Unused, audio is always submitted in batches.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: RetroAudioSampleBatch) {
+    callbacks().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: RetroInputPoll) {
+    callbacks().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: RetroInputState) {
+    callbacks().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *core() = None;
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: cstr!("CHIP-8 (chip8-wasm)"),
+        library_version: cstr!(env!("CARGO_PKG_VERSION")),
+        valid_extensions: cstr!("ch8|c8|rom"),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: DISPLAY_WIDTH,
+            base_height: DISPLAY_HEIGHT,
+            max_width: DISPLAY_WIDTH,
+            max_height: DISPLAY_HEIGHT,
+            aspect_ratio: DISPLAY_WIDTH as f32 / DISPLAY_HEIGHT as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: FPS,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = core().as_mut() {
+        core.reset();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let mut core_guard = core();
+    let core = match core_guard.as_mut() {
+        None => return,
+        Some(core) => core,
+    };
+
+    let mut updated = false;
+    if environment(
+        RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE,
+        &mut updated as *mut bool as *mut c_void,
+    ) && updated
+    {
+        apply_variables(core);
+    }
+
+    poll_keys(core);
+
+    if !core.crashed {
+        let instructions_per_frame = core.instructions_per_frame;
+        let cpu = &mut core.cpu;
+        // A panic in the interpreter mustn't unwind into the frontend.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cpu.run_frame(instructions_per_frame);
+        }));
+        if core.cpu.is_halted() {
+            let program_counter = core.cpu.get_program_counter() as usize;
+            let opcode = core.cpu.read_range(program_counter, 2).unwrap_or(&[0, 0]);
+            log_message(
+                callbacks().log,
+                RETRO_LOG_ERROR,
+                format_args!(
+                    "Emulation stopped: unknown opcode {:02X}{:02X} at {:03X}",
+                    opcode[0], opcode[1], program_counter
+                ),
+            );
+            core.crashed = true;
+        }
+        if let Err(error) = result {
+            let message = error
+                .downcast_ref::<String>()
+                .map(|message| message.as_str())
+                .or_else(|| error.downcast_ref::<&str>().copied())
+                .unwrap_or("unknown error");
+            log_message(
+                callbacks().log,
+                RETRO_LOG_ERROR,
+                format_args!("Emulation stopped: {}", message),
+            );
+            core.crashed = true;
+        }
+    }
+
+    let buffer = core.cpu.display.get_buffer();
+    core.frame_buffer
+        .iter_mut()
+        .zip(buffer)
+        .for_each(|(pixel, value)| *pixel = if *value != 0 { ON_PIXEL } else { OFF_PIXEL });
+    render_audio(core);
+
+    let (video_refresh, audio_sample_batch) = {
+        let callbacks = callbacks();
+        (callbacks.video_refresh, callbacks.audio_sample_batch)
+    };
+    if let Some(video_refresh) = video_refresh {
+        unsafe {
+            video_refresh(
+                core.frame_buffer.as_ptr() as *const c_void,
+                DISPLAY_WIDTH,
+                DISPLAY_HEIGHT,
+                DISPLAY_WIDTH as usize * 4,
+            )
+        };
+    }
+    if let Some(audio_sample_batch) = audio_sample_batch {
+        unsafe { audio_sample_batch(core.audio_buffer.as_ptr(), SAMPLES_PER_FRAME) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let core_guard = core();
+    let core = match core_guard.as_ref() {
+        None => return false,
+        Some(core) => core,
+    };
+    let buffer = slice::from_raw_parts_mut(data as *mut u8, size);
+
+    core.cpu.save_state(buffer).is_ok()
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core_guard = core();
+    let core = match core_guard.as_mut() {
+        None => return false,
+        Some(core) => core,
+    };
+    let buffer = slice::from_raw_parts(data as *const u8, size);
+    if core.cpu.load_state(buffer).is_err() {
+        return false;
+    }
+    core.crashed = false;
+
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` must be null or point to a valid `retro_game_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    if rom.len() > crate::chip8_cpu::MAX_ROM_SIZE {
+        return false;
+    }
+
+    let mut pixel_format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut pixel_format as *mut c_int as *mut c_void,
+    ) {
+        return false;
+    }
+
+    let mut descriptors = JOYPAD_MAP
+        .iter()
+        .map(|(button, _, description)| RetroInputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id: *button,
+            description: *description,
+        })
+        .collect::<Vec<RetroInputDescriptor>>();
+    descriptors.push(RetroInputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null(),
+    });
+    environment(
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr() as *mut c_void,
+    );
+
+    let mut core = Core::new(rom, callbacks().log);
+    apply_variables(&mut core);
+    *self::core() = Some(core);
+
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// The 4 KiB of memory, for cheat and achievement tools. It stays at the same
+/// address, resets included, until the game is unloaded.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match core().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => {
+            core.cpu.get_memory_mut().as_mut_ptr() as *mut c_void
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match core().as_ref() {
+        Some(_) if id == RETRO_MEMORY_SYSTEM_RAM => MEMORY_SIZE,
+        _ => 0,
+    }
+}
@@ -0,0 +1,335 @@
+//! Input movies: every key press and release together with the frame it
+//! happened on, plus everything else needed to replay a run exactly.
+//!
+//! # File format
+//!
+//! All multi-byte integers are little endian.
+//!
+//! | Offset | Size | Content                                             |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 4    | Magic, `C8MV`                                       |
+//! | 4      | 1    | Format version, currently 1                         |
+//! | 5      | 1    | Quirks, see [`Quirks::to_bits`]                     |
+//! | 6      | 2    | Instructions per frame                              |
+//! | 8      | 4    | CRC-32 of the ROM                                   |
+//! | 12     | 8    | RNG seed                                            |
+//! | 20     | 4    | Length of the movie in frames                       |
+//! | 24     | 4    | Number of input events                              |
+//! | 28     | 1    | Length N of the emulator version                    |
+//! | 29     | N    | Emulator version, UTF-8                             |
+//! | 29 + N | ...  | Input events                                        |
+//!
+//! Each input event is the number of frames since the previous event (or the
+//! start of the movie) as an unsigned LEB128 varint, followed by one byte with
+//! the key in the low nibble and bit 7 set for a press. Events apply before the
+//! frame they're recorded on runs.
+
+use crate::chip8_cpu::{Chip8CPU, RomError, KEY_COUNT};
+use crate::crc32::crc32;
+use crate::quirks::Quirks;
+use crate::traits::Platform;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+const MOVIE_MAGIC: [u8; 4] = *b"C8MV";
+const MOVIE_VERSION: u8 = 1;
+const PRESSED_BIT: u8 = 0x80;
+
+pub const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The bytes don't start with a movie header.
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    /// The file ends in the middle of the header or an event.
+    Truncated,
+    /// The movie was recorded on a different ROM.
+    RomMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The ROM can't be loaded.
+    InvalidRom(RomError),
+    /// More instructions per frame than the format can hold.
+    TooFast(u32),
+    /// More frames or events than the format can hold, or events past the
+    /// last frame there can be.
+    TooLong,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub quirks: Quirks,
+    pub rng_seed: u64,
+    pub instructions_per_frame: u32,
+    pub emulator_version: String,
+    pub frame_count: u64,
+    pub events: Vec<InputEvent>,
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MovieError> {
+        let instructions_per_frame = u16::try_from(self.instructions_per_frame)
+            .map_err(|_| MovieError::TooFast(self.instructions_per_frame))?;
+        let frame_count = u32::try_from(self.frame_count).map_err(|_| MovieError::TooLong)?;
+        let event_count = u32::try_from(self.events.len()).map_err(|_| MovieError::TooLong)?;
+        let version = self.emulator_version.as_bytes();
+        let version = &version[..version.len().min(u8::MAX as usize)];
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MOVIE_MAGIC);
+        bytes.push(MOVIE_VERSION);
+        bytes.push(self.quirks.to_bits());
+        bytes.extend_from_slice(&instructions_per_frame.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_crc32.to_le_bytes());
+        bytes.extend_from_slice(&self.rng_seed.to_le_bytes());
+        bytes.extend_from_slice(&frame_count.to_le_bytes());
+        bytes.extend_from_slice(&event_count.to_le_bytes());
+        bytes.push(version.len() as u8);
+        bytes.extend_from_slice(version);
+
+        let mut previous_frame = 0;
+        self.events.iter().for_each(|event| {
+            write_varint(&mut bytes, event.frame - previous_frame);
+            bytes.push(event.key | if event.pressed { PRESSED_BIT } else { 0 });
+            previous_frame = event.frame;
+        });
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = MovieReader { bytes, offset: 0 };
+        if reader.read(MOVIE_MAGIC.len())? != MOVIE_MAGIC {
+            return Err(MovieError::InvalidHeader);
+        }
+        let version = reader.read_u8()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let quirks = Quirks::from_bits(reader.read_u8()?);
+        let instructions_per_frame = u16::from_le_bytes(reader.read_array()?) as u32;
+        let rom_crc32 = u32::from_le_bytes(reader.read_array()?);
+        let rng_seed = u64::from_le_bytes(reader.read_array()?);
+        let frame_count = u32::from_le_bytes(reader.read_array()?) as u64;
+        let event_count = u32::from_le_bytes(reader.read_array()?) as usize;
+        let version_length = reader.read_u8()? as usize;
+        let emulator_version = String::from_utf8_lossy(reader.read(version_length)?).into();
+
+        let mut frame: u64 = 0;
+        let mut events = Vec::with_capacity(event_count.min(bytes.len()));
+        for _ in 0..event_count {
+            frame = frame
+                .checked_add(reader.read_varint()?)
+                .ok_or(MovieError::TooLong)?;
+            let key = reader.read_u8()?;
+            events.push(InputEvent {
+                frame,
+                key: key & 0x0F,
+                pressed: key & PRESSED_BIT != 0,
+            });
+        }
+
+        Ok(Movie {
+            rom_crc32,
+            quirks,
+            rng_seed,
+            instructions_per_frame,
+            emulator_version,
+            frame_count,
+            events,
+        })
+    }
+}
+
+/// Records the inputs of a run. Keys must go through the recorder and frames
+/// through [`MovieRecorder::run_frame`] for the movie to be complete.
+pub struct MovieRecorder {
+    movie: Movie,
+    keys: [bool; KEY_COUNT],
+    start_frame: u64,
+}
+
+impl MovieRecorder {
+    /// Starts recording on a CPU that was just created with `rng_seed` and had
+    /// `rom` loaded.
+    pub fn new<P: Platform>(
+        cpu: &Chip8CPU<P>,
+        rom: &[u8],
+        rng_seed: u64,
+        instructions_per_frame: u32,
+    ) -> MovieRecorder {
+        MovieRecorder {
+            movie: Movie {
+                rom_crc32: crc32(rom),
+                quirks: cpu.get_quirks(),
+                rng_seed,
+                instructions_per_frame,
+                emulator_version: String::from(EMULATOR_VERSION),
+                frame_count: 0,
+                events: Vec::new(),
+            },
+            keys: [false; KEY_COUNT],
+            start_frame: cpu.get_frame_count(),
+        }
+    }
+
+    pub fn set_key<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>, key: usize, pressed: bool) {
+        cpu.set_key(key, pressed);
+        if self.keys[key] == pressed {
+            return;
+        }
+
+        self.keys[key] = pressed;
+        self.movie.events.push(InputEvent {
+            frame: cpu.get_frame_count() - self.start_frame,
+            key: key as u8,
+            pressed,
+        });
+    }
+
+    pub fn run_frame<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>) {
+        cpu.run_frame(self.movie.instructions_per_frame);
+        self.movie.frame_count = cpu.get_frame_count() - self.start_frame;
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Replays a movie. The CPU has to be created with a platform seeded from
+/// [`Movie::rng_seed`] and set up through [`MoviePlayer::prepare`].
+pub struct MoviePlayer {
+    movie: Movie,
+    next_event: usize,
+    frame: u64,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> MoviePlayer {
+        MoviePlayer {
+            movie,
+            next_event: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn get_movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Checks the ROM is the one the movie was recorded on, then applies the
+    /// movie's quirks and loads it.
+    pub fn prepare<P: Platform>(
+        &self,
+        cpu: &mut Chip8CPU<P>,
+        rom: &[u8],
+    ) -> Result<(), MovieError> {
+        let actual = crc32(rom);
+        if actual != self.movie.rom_crc32 {
+            return Err(MovieError::RomMismatch {
+                expected: self.movie.rom_crc32,
+                actual,
+            });
+        }
+
+        cpu.set_quirks(self.movie.quirks);
+        cpu.load_rom(rom).map_err(MovieError::InvalidRom)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frame_count
+    }
+
+    /// Applies the inputs recorded for the next frame and runs it. Returns
+    /// false once the movie is over.
+    pub fn run_frame<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        while let Some(event) = self.movie.events.get(self.next_event) {
+            if event.frame != self.frame {
+                break;
+            }
+
+            cpu.set_key(event.key as usize, event.pressed);
+            self.next_event += 1;
+        }
+
+        cpu.run_frame(self.movie.instructions_per_frame);
+        self.frame += 1;
+
+        true
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+struct MovieReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> MovieReader<'a> {
+    fn read(&mut self, length: usize) -> Result<&'a [u8], MovieError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + length)
+            .ok_or(MovieError::Truncated)?;
+        self.offset += length;
+
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], MovieError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read(N)?);
+
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, MovieError> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_varint(&mut self) -> Result<u64, MovieError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+
+            shift += 7;
+        }
+    }
+}
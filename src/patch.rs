@@ -0,0 +1,269 @@
+//! IPS and BPS patches, the formats ROM translations and fixes are shared in.
+//!
+//! IPS is a list of byte ranges to overwrite, with no checks at all. BPS
+//! describes the target as copies from the source, the target so far and the
+//! patch, and carries CRC-32s of the source, the target and itself, which are
+//! all verified.
+
+use crate::chip8_cpu::MAX_ROM_SIZE;
+use crate::crc32::crc32;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC-32s.
+const BPS_FOOTER_SIZE: usize = 12;
+/// Far beyond any CHIP-8 ROM, but keeps a bogus size from exhausting memory.
+const MAX_TARGET_SIZE: u64 = 0x100_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// The patch is neither IPS nor BPS.
+    UnknownFormat,
+    /// The patch ends in the middle of a record or before its footer.
+    Truncated,
+    /// A BPS patch for a source of a different size.
+    SourceSizeMismatch { expected: usize, actual: usize },
+    /// A BPS patch for a different source.
+    SourceChecksumMismatch { expected: u32, actual: u32 },
+    /// Applying a BPS patch didn't give the ROM it was made from.
+    TargetChecksumMismatch { expected: u32, actual: u32 },
+    /// The BPS patch itself is corrupt.
+    PatchChecksumMismatch { expected: u32, actual: u32 },
+    /// A BPS action reads or writes outside the source or target.
+    OutOfRange,
+    /// The patched ROM has this many bytes, more than fit in memory, see
+    /// [`MAX_ROM_SIZE`].
+    RomTooLarge(usize),
+}
+
+/// Applies an IPS or BPS patch to `rom`, going by the patch's header. The
+/// patched ROM has to fit in memory.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let patched = if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }?;
+    if patched.len() > MAX_ROM_SIZE {
+        return Err(PatchError::RomTooLarge(patched.len()));
+    }
+
+    Ok(patched)
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+
+    let mut reader = PatchReader {
+        patch,
+        offset: IPS_MAGIC.len(),
+    };
+    let mut target = rom.to_vec();
+    loop {
+        let record = reader.read(3)?;
+        if record == IPS_END {
+            break;
+        }
+
+        let offset = u32::from_be_bytes([0, record[0], record[1], record[2]]) as usize;
+        let size = reader.read_u16_be()? as usize;
+        // A zero size marks a run of one repeated byte.
+        let (length, run) = if size == 0 {
+            (reader.read_u16_be()? as usize, Some(reader.read(1)?[0]))
+        } else {
+            (size, None)
+        };
+
+        if target.len() < offset + length {
+            target.resize(offset + length, 0);
+        }
+        match run {
+            Some(value) => target[offset..offset + length].fill(value),
+            None => target[offset..offset + length].copy_from_slice(reader.read(length)?),
+        }
+    }
+
+    // Some patches truncate the ROM after the end marker.
+    if let Ok(length) = reader.read(3) {
+        target.truncate(u32::from_be_bytes([0, length[0], length[1], length[2]]) as usize);
+    }
+
+    Ok(target)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(BPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+
+    let footer = &patch[patch.len() - BPS_FOOTER_SIZE..];
+    let checksum = |offset: usize| {
+        u32::from_le_bytes([
+            footer[offset],
+            footer[offset + 1],
+            footer[offset + 2],
+            footer[offset + 3],
+        ])
+    };
+    let (source_crc32, target_crc32, patch_crc32) = (checksum(0), checksum(4), checksum(8));
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc32 {
+        return Err(PatchError::PatchChecksumMismatch {
+            expected: patch_crc32,
+            actual,
+        });
+    }
+    let actual = crc32(rom);
+    if actual != source_crc32 {
+        return Err(PatchError::SourceChecksumMismatch {
+            expected: source_crc32,
+            actual,
+        });
+    }
+
+    let mut reader = PatchReader {
+        patch: &patch[..patch.len() - BPS_FOOTER_SIZE],
+        offset: BPS_MAGIC.len(),
+    };
+    let source_size = reader.read_number()? as usize;
+    let target_size = reader.read_number()?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::OutOfRange);
+    }
+    let target_size = target_size as usize;
+    let metadata_size = reader.read_number()? as usize;
+    reader.read(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(PatchError::SourceSizeMismatch {
+            expected: source_size,
+            actual: rom.len(),
+        });
+    }
+
+    let mut target = vec![0; target_size];
+    let mut output = 0;
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while !reader.is_at_end() {
+        let action = reader.read_number()?;
+        if output as u64 + (action >> 2) + 1 > target_size as u64 {
+            return Err(PatchError::OutOfRange);
+        }
+        let length = (action >> 2) as usize + 1;
+
+        match action & 0x3 {
+            // Source read: the same bytes as the source at this position.
+            0 => {
+                let bytes = rom
+                    .get(output..output + length)
+                    .ok_or(PatchError::OutOfRange)?;
+                target[output..output + length].copy_from_slice(bytes);
+            }
+            // Target read: bytes from the patch.
+            1 => target[output..output + length].copy_from_slice(reader.read(length)?),
+            // Source copy: bytes from anywhere in the source.
+            2 => {
+                source_offset = reader.read_relative_offset(source_offset)?;
+                let bytes = rom
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::OutOfRange)?;
+                target[output..output + length].copy_from_slice(bytes);
+                source_offset += length;
+            }
+            // Target copy: bytes from the target so far, one at a time since
+            // the ranges may overlap to repeat a pattern.
+            _ => {
+                target_offset = reader.read_relative_offset(target_offset)?;
+                if target_offset >= output {
+                    return Err(PatchError::OutOfRange);
+                }
+                (0..length).for_each(|index| {
+                    target[output + index] = target[target_offset + index];
+                });
+                target_offset += length;
+            }
+        }
+        output += length;
+    }
+
+    let actual = crc32(&target);
+    if actual != target_crc32 {
+        return Err(PatchError::TargetChecksumMismatch {
+            expected: target_crc32,
+            actual,
+        });
+    }
+
+    Ok(target)
+}
+
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn read(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self
+            .patch
+            .get(self.offset..self.offset.saturating_add(length))
+            .ok_or(PatchError::Truncated)?;
+        self.offset += length;
+
+        Ok(bytes)
+    }
+
+    fn read_u16_be(&mut self) -> Result<u16, PatchError> {
+        let bytes = self.read(2)?;
+
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// BPS numbers: 7 bits per byte, least significant first, with the top
+    /// bit marking the last byte and each continuation adding one more, so
+    /// every number has a single encoding.
+    fn read_number(&mut self) -> Result<u64, PatchError> {
+        let mut number: u64 = 0;
+        let mut shift: u64 = 1;
+        loop {
+            let byte = self.read(1)?[0];
+            number = number
+                .checked_add((byte & 0x7F) as u64 * shift)
+                .ok_or(PatchError::OutOfRange)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfRange)?;
+            number = number.checked_add(shift).ok_or(PatchError::OutOfRange)?;
+        }
+    }
+
+    /// A signed offset from `from`, its sign in the lowest bit.
+    fn read_relative_offset(&mut self, from: usize) -> Result<usize, PatchError> {
+        let number = self.read_number()?;
+        let distance = (number >> 1) as usize;
+        let offset = if number & 1 == 0 {
+            from.checked_add(distance)
+        } else {
+            from.checked_sub(distance)
+        };
+
+        offset.ok_or(PatchError::OutOfRange)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.offset >= self.patch.len()
+    }
+}
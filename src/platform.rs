@@ -0,0 +1,66 @@
+use crate::traits::{Audio, Logger, Observer, RandomSource};
+
+/// A small xorshift generator, good enough for CXNN and available without an
+/// operating system to seed from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XorShiftRng {
+    state: u64,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> XorShiftRng {
+        XorShiftRng {
+            // Xorshift gets stuck on a zero state.
+            state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+
+        x
+    }
+}
+
+impl RandomSource for XorShiftRng {
+    fn random_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+/// Platform used when the host doesn't provide its own: a seeded xorshift RNG,
+/// no logging and a silent buzzer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefaultPlatform {
+    rng: XorShiftRng,
+}
+
+impl DefaultPlatform {
+    pub fn with_seed(seed: u64) -> DefaultPlatform {
+        DefaultPlatform {
+            rng: XorShiftRng::new(seed),
+        }
+    }
+}
+
+impl Default for DefaultPlatform {
+    fn default() -> DefaultPlatform {
+        DefaultPlatform::with_seed(0)
+    }
+}
+
+impl RandomSource for DefaultPlatform {
+    fn random_byte(&mut self) -> u8 {
+        self.rng.random_byte()
+    }
+}
+
+impl Logger for DefaultPlatform {}
+
+impl Audio for DefaultPlatform {}
+
+impl Observer for DefaultPlatform {}
@@ -0,0 +1,264 @@
+//! Instruction-level profiling: how often each opcode class and each address
+//! was executed, how many instructions were spent in each subroutine, and how
+//! many instructions each frame ran, while profiling is enabled on a CPU (see
+//! [`Chip8CPU::enable_profiler`](crate::chip8_cpu::Chip8CPU::enable_profiler)).
+//!
+//! Subroutines are followed through 2NNN and 00EE pairs. Time is counted in
+//! executed instructions, which is what a frame's budget is made of.
+
+use crate::chip8_cpu::MEMORY_SIZE;
+use crate::instruction::Instruction;
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Call stacks deeper than the CHIP-8 stack can't happen on a working
+/// program, deeper ones are cut off rather than growing without bound.
+const MAX_CALL_DEPTH: usize = 64;
+
+/// Instructions spent in a subroutine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubroutineProfile {
+    pub address: u16,
+    pub calls: u64,
+    /// Instructions executed in the subroutine itself, including its 00EE.
+    pub self_instructions: u64,
+    /// Instructions executed in the subroutine and everything it called.
+    pub total_instructions: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profiler {
+    pc_counts: Vec<u64>,
+    class_counts: BTreeMap<&'static str, u64>,
+    calls: BTreeMap<u16, u64>,
+    /// Subroutine addresses, outermost first.
+    call_stack: Vec<u16>,
+    /// Instructions executed with exactly this call stack on top of the
+    /// program's main code.
+    stack_counts: BTreeMap<Vec<u16>, u64>,
+    frame_instructions: Vec<u32>,
+    current_frame_instructions: u32,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            pc_counts: vec![0; MEMORY_SIZE],
+            class_counts: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            call_stack: Vec::new(),
+            stack_counts: BTreeMap::new(),
+            frame_instructions: Vec::new(),
+            current_frame_instructions: 0,
+        }
+    }
+
+    /// Counts `instruction`, about to be executed at `address`.
+    pub fn record(&mut self, address: u16, instruction: Instruction) {
+        if let Some(count) = self.pc_counts.get_mut(address as usize) {
+            *count += 1;
+        }
+        *self.class_counts.entry(instruction.pattern()).or_insert(0) += 1;
+        self.current_frame_instructions = self.current_frame_instructions.saturating_add(1);
+
+        // The call counts towards the caller and the return towards the callee.
+        match self.stack_counts.get_mut(&self.call_stack) {
+            Some(count) => *count += 1,
+            None => {
+                self.stack_counts.insert(self.call_stack.clone(), 1);
+            }
+        }
+
+        match instruction {
+            Instruction::Call { address } => {
+                *self.calls.entry(address).or_insert(0) += 1;
+                if self.call_stack.len() < MAX_CALL_DEPTH {
+                    self.call_stack.push(address);
+                }
+            }
+            Instruction::Return => {
+                self.call_stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes the current frame's instruction count.
+    pub fn end_frame(&mut self) {
+        self.frame_instructions
+            .push(self.current_frame_instructions);
+        self.current_frame_instructions = 0;
+    }
+
+    pub fn get_pc_count(&self, address: u16) -> u64 {
+        self.pc_counts.get(address as usize).copied().unwrap_or(0)
+    }
+
+    /// Executions per opcode class, keyed by pattern such as `8XY4`.
+    pub fn get_class_counts(&self) -> &BTreeMap<&'static str, u64> {
+        &self.class_counts
+    }
+
+    /// Instructions run by each completed frame, oldest first.
+    pub fn get_frame_instructions(&self) -> &[u32] {
+        &self.frame_instructions
+    }
+
+    pub fn get_instruction_count(&self) -> u64 {
+        self.class_counts.values().sum()
+    }
+
+    /// Subroutines that were called, by address.
+    pub fn subroutines(&self) -> Vec<SubroutineProfile> {
+        let mut subroutines: BTreeMap<u16, SubroutineProfile> = self
+            .calls
+            .iter()
+            .map(|(address, calls)| {
+                let profile = SubroutineProfile {
+                    address: *address,
+                    calls: *calls,
+                    self_instructions: 0,
+                    total_instructions: 0,
+                };
+                (*address, profile)
+            })
+            .collect();
+
+        for (stack, count) in self.stack_counts.iter() {
+            if let Some(profile) = stack.last().and_then(|top| subroutines.get_mut(top)) {
+                profile.self_instructions += count;
+            }
+            // A recursive subroutine only counts once per stack.
+            for (depth, address) in stack.iter().enumerate() {
+                if stack[..depth].contains(address) {
+                    continue;
+                }
+                if let Some(profile) = subroutines.get_mut(address) {
+                    profile.total_instructions += count;
+                }
+            }
+        }
+
+        subroutines.into_values().collect()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Profiler::new();
+    }
+
+    /// A plain text report: opcode classes and addresses by execution count,
+    /// subroutines by total instructions, and instructions per frame.
+    pub fn flat_profile(&self) -> String {
+        let total = self.get_instruction_count();
+        let percent = |count: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 * 100.0 / total as f64
+            }
+        };
+        let mut report = String::new();
+
+        writeln!(report, "{} instructions executed", total).unwrap();
+
+        writeln!(report, "\nOPCODE      COUNT       %").unwrap();
+        let mut classes: Vec<(&str, u64)> = self
+            .class_counts
+            .iter()
+            .map(|(pattern, count)| (*pattern, *count))
+            .collect();
+        classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (pattern, count) in classes {
+            writeln!(
+                report,
+                "{}   {:>10} {:>6.2}",
+                pattern,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nADDR        COUNT       %").unwrap();
+        let mut addresses: Vec<(usize, u64)> = self
+            .pc_counts
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (address, count) in addresses {
+            writeln!(
+                report,
+                "{:03X}    {:>10} {:>6.2}",
+                address,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nSUBROUTINE   CALLS       SELF      TOTAL       %").unwrap();
+        let mut subroutines = self.subroutines();
+        subroutines.sort_by(|a, b| {
+            b.total_instructions
+                .cmp(&a.total_instructions)
+                .then(a.address.cmp(&b.address))
+        });
+        for subroutine in subroutines {
+            writeln!(
+                report,
+                "{:03X}     {:>10} {:>10} {:>10} {:>6.2}",
+                subroutine.address,
+                subroutine.calls,
+                subroutine.self_instructions,
+                subroutine.total_instructions,
+                percent(subroutine.total_instructions)
+            )
+            .unwrap();
+        }
+
+        let frames = &self.frame_instructions;
+        if !frames.is_empty() {
+            let sum: u64 = frames.iter().map(|count| *count as u64).sum();
+            writeln!(
+                report,
+                "\n{} frames, instructions per frame: min {}, average {:.1}, max {}",
+                frames.len(),
+                frames.iter().min().unwrap(),
+                sum as f64 / frames.len() as f64,
+                frames.iter().max().unwrap()
+            )
+            .unwrap();
+        }
+
+        report
+    }
+
+    /// The call tree in the folded stack format flame graph tools read: one
+    /// line per call stack, frames separated by `;`, then the number of
+    /// instructions executed with exactly that stack.
+    pub fn folded_stacks(&self) -> String {
+        let mut folded = String::new();
+        for (stack, count) in self.stack_counts.iter() {
+            folded.push_str("main");
+            for address in stack {
+                write!(folded, ";sub_{:03X}", address).unwrap();
+            }
+            writeln!(folded, " {}", count).unwrap();
+        }
+
+        folded
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
@@ -0,0 +1,54 @@
+/// Behaviours that differ between CHIP8 interpreters. ROMs are written against
+/// one interpreter or another, so these have to be configurable per game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VY into VX instead of shifting VX in place
+    /// (COSMAC VIP).
+    pub shift_uses_vy: bool,
+    /// FX55 and FX65 leave I pointing past the last register they touched.
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to NNN plus VX instead of NNN plus V0 (SUPER-CHIP).
+    pub jump_uses_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0 (COSMAC VIP).
+    pub vf_reset: bool,
+    /// FX0A waits for the key to be released as well as pressed (COSMAC VIP),
+    /// instead of going on as soon as one is down.
+    pub key_wait_release: bool,
+}
+
+impl Quirks {
+    pub fn new() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: false,
+            key_wait_release: false,
+        }
+    }
+
+    /// Packs the quirks into a byte, one bit per quirk in field order.
+    pub fn to_bits(self) -> u8 {
+        (self.shift_uses_vy as u8)
+            | (self.load_store_increments_i as u8) << 1
+            | (self.jump_uses_vx as u8) << 2
+            | (self.vf_reset as u8) << 3
+            | (self.key_wait_release as u8) << 4
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift_uses_vy: bits & 0x1 != 0,
+            load_store_increments_i: bits & 0x2 != 0,
+            jump_uses_vx: bits & 0x4 != 0,
+            vf_reset: bits & 0x8 != 0,
+            key_wait_release: bits & 0x10 != 0,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::new()
+    }
+}
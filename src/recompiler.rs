@@ -0,0 +1,802 @@
+//! Ahead-of-time recompiler from a ROM to a standalone WebAssembly module.
+//!
+//! Code reachable from 0x200 is split into basic blocks and every block becomes
+//! a wasm function. The module's linear memory starts with a saved state (see
+//! [`Chip8CPU::save_state`]), so a host moves a CPU into the module by copying
+//! its saved state to offset 0 and back out again afterwards.
+//!
+//! The module exports:
+//!
+//! - `memory`: the state, followed at [`ENABLED_OFFSET`] by one byte per
+//!   address that is set when compiled code can run from there.
+//! - `run(budget) -> executed`: runs compiled blocks from the current program
+//!   counter until `budget` instructions ran or it reaches code that wasn't
+//!   compiled, and returns the number of instructions it ran.
+//! - `invalidate(start, length)`: disables every block containing any of the
+//!   given bytes of CHIP-8 memory.
+//! - `end_frame()`: counts a frame, to call after running a frame's worth of
+//!   instructions.
+//!
+//! Whenever `run` stops short of its budget, the host runs one instruction on
+//! the interpreter and carries on. That covers computed jumps (BNNN), drawing,
+//! random numbers, waiting for a key, and the instructions that go through
+//! memory at I (FX33, FX55 and FX65). After those the host has to `invalidate`
+//! the bytes FX33 and FX55 wrote, so self-modifying code goes back to the
+//! interpreter.
+
+use crate::block_cache::BlockEngine;
+use crate::chip8_cpu::{
+    Chip8CPU, Processor, RomError, KEY_COUNT, MEMORY_SIZE, STACK_SIZE, STATE_BUZZER_OFFSET,
+    STATE_DELAY_TIMER_OFFSET, STATE_DISPLAY_OFFSET, STATE_DRAW_FLAG_OFFSET,
+    STATE_FRAME_COUNT_OFFSET, STATE_INDEX_REGISTER_OFFSET, STATE_KEYS_OFFSET,
+    STATE_PROGRAM_COUNTER_OFFSET, STATE_REGISTERS_OFFSET, STATE_SIZE, STATE_SOUND_TIMER_OFFSET,
+    STATE_STACK_OFFSET, STATE_STACK_POINTER_OFFSET,
+};
+use crate::display::BUFFER_SIZE;
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use crate::traits::Platform;
+
+use std::collections::BTreeSet;
+
+use wasm_encoder::{
+    BlockType, CodeSection, ConstExpr, DataSection, ElementSection, Elements, ExportKind,
+    ExportSection, Function, FunctionSection, MemArg, MemorySection, MemoryType, Module, RefType,
+    TableSection, TableType, TypeSection, ValType,
+};
+
+const ROM_START: u16 = 0x200;
+/// Upper bound on the instructions in a block, like the block cache.
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// Where the byte per address marking compiled code starts.
+pub const ENABLED_OFFSET: usize = (STATE_SIZE + 0xFF) & !0xFF;
+/// Enough for the enabled bytes of any 16-bit program counter.
+const MEMORY_PAGES: u64 = 2;
+
+const RUN_TYPE: u32 = 0;
+const INVALIDATE_TYPE: u32 = 1;
+const BLOCK_TYPE: u32 = 2;
+const END_FRAME_TYPE: u32 = 3;
+const RUN_FUNCTION: u32 = 0;
+const INVALIDATE_FUNCTION: u32 = 1;
+const END_FRAME_FUNCTION: u32 = 2;
+const FIRST_BLOCK_FUNCTION: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecompileError {
+    /// The ROM doesn't fit in memory after 0x200.
+    RomTooLarge(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompiledBlock {
+    pub start: u16,
+    /// Number of instructions compiled into the block.
+    pub length: usize,
+}
+
+pub struct CompiledRom {
+    pub wasm: Vec<u8>,
+    pub blocks: Vec<CompiledBlock>,
+}
+
+/// Compiles every block reachable from 0x200 in `rom`, for a CPU using
+/// `quirks`.
+pub fn compile_rom(rom: &[u8], quirks: Quirks) -> Result<CompiledRom, RecompileError> {
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(rom)
+        .map_err(|RomError::TooLarge(length)| RecompileError::RomTooLarge(length))?;
+    let memory = cpu.get_memory();
+
+    let mut blocks = Vec::new();
+    let mut queued = BTreeSet::new();
+    let mut queue = vec![ROM_START];
+    queued.insert(ROM_START);
+    while let Some(start) = queue.pop() {
+        let (instructions, successors) = find_block(memory, start);
+        if !instructions.is_empty() {
+            blocks.push((start, instructions));
+        }
+
+        successors
+            .into_iter()
+            .filter(|address| (*address as usize) + 1 < MEMORY_SIZE)
+            .for_each(|address| {
+                if queued.insert(address) {
+                    queue.push(address);
+                }
+            });
+    }
+    blocks.sort_by_key(|(start, _)| *start);
+
+    Ok(CompiledRom {
+        wasm: emit_module(&blocks, quirks),
+        blocks: blocks
+            .iter()
+            .map(|(start, instructions)| CompiledBlock {
+                start: *start,
+                length: instructions.len(),
+            })
+            .collect(),
+    })
+}
+
+/// Runs one instruction on the interpreter, for when a compiled module's `run`
+/// stops short. Returns the memory the instruction wrote as start and length,
+/// which the host has to pass on to `invalidate`.
+pub fn step<P: Platform>(cpu: &mut Chip8CPU<P>) -> Option<(usize, usize)> {
+    let instruction = Instruction::decode(Processor::fetch_opcode(cpu));
+    let written = BlockEngine::written_range(cpu, instruction);
+    cpu.cycle();
+
+    written
+}
+
+/// Whether an instruction can be compiled, or has to run on the interpreter.
+fn is_compilable(instruction: Instruction) -> bool {
+    !matches!(
+        instruction,
+        Instruction::JumpWithOffset { .. }
+            | Instruction::Random { .. }
+            | Instruction::Draw { .. }
+            | Instruction::WaitForKey { .. }
+            | Instruction::StoreBcd { .. }
+            | Instruction::StoreRegisters { .. }
+            | Instruction::LoadRegisters { .. }
+            | Instruction::Unknown { .. }
+    )
+}
+
+/// Decodes the compilable instructions of the block at `start`, and returns
+/// them with the addresses execution can continue at afterwards.
+fn find_block(memory: &[u8; MEMORY_SIZE], start: u16) -> (Vec<Instruction>, Vec<u16>) {
+    let mut instructions = Vec::new();
+    let mut address = start;
+    loop {
+        let opcode = ((memory[address as usize] as u16) << 8) | memory[address as usize + 1] as u16;
+        let instruction = Instruction::decode(opcode);
+        if !is_compilable(instruction) {
+            let successors = match instruction {
+                Instruction::JumpWithOffset { .. } | Instruction::Unknown { .. } => vec![],
+                _ => vec![address + 2],
+            };
+            return (instructions, successors);
+        }
+
+        instructions.push(instruction);
+        let successors = match instruction {
+            Instruction::Return => vec![],
+            Instruction::Jump { address: target } => vec![target],
+            Instruction::Call { address: target } => vec![target, address + 2],
+            _ if instruction.is_control_flow() => vec![address + 2, address + 4],
+            _ if instructions.len() == MAX_BLOCK_LENGTH || address as usize + 3 >= MEMORY_SIZE => {
+                vec![address + 2]
+            }
+            _ => {
+                address += 2;
+                continue;
+            }
+        };
+        return (instructions, successors);
+    }
+}
+
+fn emit_module(blocks: &[(u16, Vec<Instruction>)], quirks: Quirks) -> Vec<u8> {
+    let mut types = TypeSection::new();
+    types.ty().function([ValType::I32], [ValType::I32]);
+    types.ty().function([ValType::I32, ValType::I32], []);
+    types
+        .ty()
+        .function([ValType::I32, ValType::I32], [ValType::I32]);
+    types.ty().function([], []);
+
+    let mut functions = FunctionSection::new();
+    functions.function(RUN_TYPE);
+    functions.function(INVALIDATE_TYPE);
+    functions.function(END_FRAME_TYPE);
+    blocks.iter().for_each(|_| {
+        functions.function(BLOCK_TYPE);
+    });
+
+    let mut tables = TableSection::new();
+    tables.table(TableType {
+        element_type: RefType::FUNCREF,
+        table64: false,
+        minimum: MEMORY_SIZE as u64,
+        maximum: Some(MEMORY_SIZE as u64),
+        shared: false,
+    });
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: MEMORY_PAGES,
+        maximum: Some(MEMORY_PAGES),
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+
+    let mut exports = ExportSection::new();
+    exports.export("memory", ExportKind::Memory, 0);
+    exports.export("run", ExportKind::Func, RUN_FUNCTION);
+    exports.export("invalidate", ExportKind::Func, INVALIDATE_FUNCTION);
+    exports.export("end_frame", ExportKind::Func, END_FRAME_FUNCTION);
+
+    // Every instruction of a block is an entry point, so execution can pick up
+    // again in the middle of a block after running out of budget. Where blocks
+    // overlap, the later one gets the address.
+    let mut entries = vec![None; MEMORY_SIZE];
+    let mut code = CodeSection::new();
+    code.function(&emit_run());
+    code.function(&emit_invalidate(blocks));
+    code.function(&emit_end_frame());
+    for (index, (start, instructions)) in blocks.iter().enumerate() {
+        (0..instructions.len()).for_each(|instruction| {
+            entries[*start as usize + instruction * 2] = Some(FIRST_BLOCK_FUNCTION + index as u32);
+        });
+        code.function(&BlockEmitter::emit(*start, instructions, quirks));
+    }
+
+    let mut elements = ElementSection::new();
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(|(address, entry)| entry.map(|function| (address, [function])))
+        .for_each(|(address, function)| {
+            elements.active(
+                None,
+                &ConstExpr::i32_const(address as i32),
+                Elements::Functions(function[..].into()),
+            );
+        });
+
+    let mut data = DataSection::new();
+    data.active(
+        0,
+        &ConstExpr::i32_const(ENABLED_OFFSET as i32),
+        entries.iter().map(|entry| entry.is_some() as u8),
+    );
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&functions)
+        .section(&tables)
+        .section(&memories)
+        .section(&exports)
+        .section(&elements)
+        .section(&code)
+        .section(&data);
+
+    module.finish()
+}
+
+fn memory_at(offset: usize) -> MemArg {
+    MemArg {
+        offset: offset as u64,
+        align: 0,
+        memory_index: 0,
+    }
+}
+
+/// `run(budget)`: calls the block at the program counter for as long as there
+/// is one and budget left.
+fn emit_run() -> Function {
+    const BUDGET: u32 = 0;
+    const EXECUTED: u32 = 1;
+    const PROGRAM_COUNTER: u32 = 2;
+    const BLOCK_EXECUTED: u32 = 3;
+
+    let mut function = Function::new([(3, ValType::I32)]);
+    function
+        .instructions()
+        .block(BlockType::Empty)
+        .loop_(BlockType::Empty)
+        // Out of budget?
+        .local_get(EXECUTED)
+        .local_get(BUDGET)
+        .i32_ge_u()
+        .br_if(1)
+        // No compiled block at the program counter?
+        .i32_const(0)
+        .i32_load16_u(memory_at(STATE_PROGRAM_COUNTER_OFFSET))
+        .local_tee(PROGRAM_COUNTER)
+        .i32_load8_u(memory_at(ENABLED_OFFSET))
+        .i32_eqz()
+        .br_if(1)
+        // Run the block, and stop if it couldn't run a single instruction.
+        .local_get(BUDGET)
+        .local_get(EXECUTED)
+        .i32_sub()
+        .local_get(PROGRAM_COUNTER)
+        .local_get(PROGRAM_COUNTER)
+        .call_indirect(0, BLOCK_TYPE)
+        .local_tee(BLOCK_EXECUTED)
+        .i32_eqz()
+        .br_if(1)
+        .local_get(EXECUTED)
+        .local_get(BLOCK_EXECUTED)
+        .i32_add()
+        .local_set(EXECUTED)
+        .br(0)
+        .end()
+        .end()
+        .local_get(EXECUTED)
+        .end();
+
+    function
+}
+
+/// `invalidate(start, length)`: clears the enabled bytes of every block
+/// overlapping the range.
+fn emit_invalidate(blocks: &[(u16, Vec<Instruction>)]) -> Function {
+    const START: u32 = 0;
+    const LENGTH: u32 = 1;
+
+    let mut function = Function::new([]);
+    let mut sink = function.instructions();
+    for (start, instructions) in blocks {
+        let end = *start as i32 + instructions.len() as i32 * 2;
+        sink.local_get(START)
+            .i32_const(end)
+            .i32_lt_u()
+            .local_get(START)
+            .local_get(LENGTH)
+            .i32_add()
+            .i32_const(*start as i32)
+            .i32_gt_u()
+            .i32_and()
+            .if_(BlockType::Empty)
+            .i32_const((ENABLED_OFFSET + *start as usize) as i32)
+            .i32_const(0)
+            .i32_const(instructions.len() as i32 * 2)
+            .memory_fill(0)
+            .end();
+    }
+    sink.end();
+
+    function
+}
+
+/// `end_frame()`: counts a frame, like [`Chip8CPU::run_frame`] does.
+fn emit_end_frame() -> Function {
+    let mut function = Function::new([]);
+    function
+        .instructions()
+        .i32_const(0)
+        .i32_const(0)
+        .i64_load(memory_at(STATE_FRAME_COUNT_OFFSET))
+        .i64_const(1)
+        .i64_add()
+        .i64_store(memory_at(STATE_FRAME_COUNT_OFFSET))
+        .end();
+
+    function
+}
+
+/// Translates one block. Block functions take the instruction budget and the
+/// address of the instruction to start at, and return how many instructions
+/// they ran, leaving the program counter in memory pointing at the next one.
+struct BlockEmitter {
+    function: Function,
+    quirks: Quirks,
+}
+
+impl BlockEmitter {
+    const BUDGET: u32 = 0;
+    const ENTRY_ADDRESS: u32 = 1;
+    /// Index of the first instruction to run.
+    const ENTRY: u32 = 2;
+    /// The budget plus the entry index, to compare instruction indices with.
+    const LIMIT: u32 = 3;
+
+    fn emit(start: u16, instructions: &[Instruction], quirks: Quirks) -> Function {
+        let mut emitter = BlockEmitter {
+            function: Function::new([(2, ValType::I32)]),
+            quirks,
+        };
+
+        let length = instructions.len() as u32;
+        let mut sink = emitter.function.instructions();
+        sink.local_get(Self::ENTRY_ADDRESS)
+            .i32_const(start as i32)
+            .i32_sub()
+            .i32_const(1)
+            .i32_shr_u()
+            .local_tee(Self::ENTRY)
+            .local_get(Self::BUDGET)
+            .i32_add()
+            .local_set(Self::LIMIT);
+        // Jump to the entry instruction: its code follows the end of the
+        // entry's block.
+        (0..length).for_each(|_| {
+            sink.block(BlockType::Empty);
+        });
+        sink.local_get(Self::ENTRY).br_table(0..length, length - 1);
+
+        let mut address = start;
+        for (index, instruction) in instructions.iter().enumerate() {
+            emitter.function.instructions().end();
+            if index > 0 {
+                emitter.exit_if_out_of_budget(address, index);
+            }
+            emitter.instruction(*instruction, address, index);
+            emitter.tick_timers();
+            address += 2;
+        }
+
+        let last = *instructions.last().expect("Blocks aren't empty");
+        if !last.is_control_flow() {
+            emitter.store16(STATE_PROGRAM_COUNTER_OFFSET, |e| e.constant(address));
+        }
+        emitter.executed(instructions.len());
+        emitter.function.instructions().end();
+
+        emitter.function
+    }
+
+    /// Pushes the number of instructions run before the one at `index`.
+    fn executed(&mut self, index: usize) {
+        self.function
+            .instructions()
+            .i32_const(index as i32)
+            .local_get(Self::ENTRY)
+            .i32_sub();
+    }
+
+    fn constant(&mut self, value: u16) {
+        self.function.instructions().i32_const(value as i32);
+    }
+
+    fn load8(&mut self, offset: usize) {
+        self.function
+            .instructions()
+            .i32_const(0)
+            .i32_load8_u(memory_at(offset));
+    }
+
+    fn load16(&mut self, offset: usize) {
+        self.function
+            .instructions()
+            .i32_const(0)
+            .i32_load16_u(memory_at(offset));
+    }
+
+    fn store8(&mut self, offset: usize, value: impl FnOnce(&mut Self)) {
+        self.function.instructions().i32_const(0);
+        value(self);
+        self.function.instructions().i32_store8(memory_at(offset));
+    }
+
+    fn store16(&mut self, offset: usize, value: impl FnOnce(&mut Self)) {
+        self.function.instructions().i32_const(0);
+        value(self);
+        self.function.instructions().i32_store16(memory_at(offset));
+    }
+
+    fn register(x: usize) -> usize {
+        STATE_REGISTERS_OFFSET + x
+    }
+
+    /// Returns before the instruction at `index` with the program counter at
+    /// its `address` when the condition on the stack holds.
+    fn exit_if(&mut self, address: u16, index: usize) {
+        self.function.instructions().if_(BlockType::Empty);
+        self.store16(STATE_PROGRAM_COUNTER_OFFSET, |e| e.constant(address));
+        self.executed(index);
+        self.function.instructions().return_().end();
+    }
+
+    fn exit_if_out_of_budget(&mut self, address: u16, index: usize) {
+        self.function
+            .instructions()
+            .local_get(Self::LIMIT)
+            .i32_const(index as i32)
+            .i32_le_u();
+        self.exit_if(address, index);
+    }
+
+    /// Sets the program counter to `address + 4` when the condition on the
+    /// stack holds, and to `address + 2` otherwise.
+    fn skip_if(&mut self, address: u16, condition: impl FnOnce(&mut Self)) {
+        self.store16(STATE_PROGRAM_COUNTER_OFFSET, |e| {
+            e.constant(address + 4);
+            e.constant(address + 2);
+            condition(e);
+            e.function.instructions().select();
+        });
+    }
+
+    fn binary(&mut self, x: usize, y: usize, operation: impl FnOnce(&mut Self)) {
+        self.store8(Self::register(x), |e| {
+            e.load8(Self::register(x));
+            e.load8(Self::register(y));
+            operation(e);
+        });
+    }
+
+    /// Runs `operation`, then sets VF to the flag `flag` computes from the
+    /// registers as they were before it, so the flag wins when X is F.
+    fn with_flag(&mut self, flag: impl FnOnce(&mut Self), operation: impl FnOnce(&mut Self)) {
+        self.function.instructions().i32_const(0);
+        flag(self);
+        operation(self);
+        self.function
+            .instructions()
+            .i32_store8(memory_at(Self::register(0xF)));
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.store8(Self::register(0xF), |e| e.constant(0));
+        }
+    }
+
+    /// Exits when the key in VX isn't a valid key, leaving the wrap around to
+    /// the interpreter.
+    fn key_pressed(&mut self, x: usize, address: u16, index: usize) {
+        self.load8(Self::register(x));
+        self.function
+            .instructions()
+            .i32_const(KEY_COUNT as i32)
+            .i32_ge_u();
+        self.exit_if(address, index);
+
+        self.load8(Self::register(x));
+        self.function
+            .instructions()
+            .i32_load8_u(memory_at(STATE_KEYS_OFFSET));
+    }
+
+    /// Mirrors `Processor::execute`, including its quirks.
+    fn instruction(&mut self, instruction: Instruction, address: u16, index: usize) {
+        match instruction {
+            Instruction::ClearScreen => {
+                self.function
+                    .instructions()
+                    .i32_const(STATE_DISPLAY_OFFSET as i32)
+                    .i32_const(0)
+                    .i32_const(BUFFER_SIZE as i32)
+                    .memory_fill(0);
+                self.store8(STATE_DRAW_FLAG_OFFSET, |e| e.constant(1));
+            }
+            Instruction::Return => {
+                // An empty or wrapped around stack is left to the interpreter.
+                self.load8(STATE_STACK_POINTER_OFFSET);
+                self.function
+                    .instructions()
+                    .i32_const(1)
+                    .i32_sub()
+                    .i32_const(STACK_SIZE as i32)
+                    .i32_ge_u();
+                self.exit_if(address, index);
+
+                self.store8(STATE_STACK_POINTER_OFFSET, |e| {
+                    e.load8(STATE_STACK_POINTER_OFFSET);
+                    e.function.instructions().i32_const(1).i32_sub();
+                });
+                self.store16(STATE_PROGRAM_COUNTER_OFFSET, |e| {
+                    e.load8(STATE_STACK_POINTER_OFFSET);
+                    e.function
+                        .instructions()
+                        .i32_const(1)
+                        .i32_shl()
+                        .i32_load16_u(memory_at(STATE_STACK_OFFSET))
+                        .i32_const(2)
+                        .i32_add();
+                });
+            }
+            Instruction::Jump { address: target } => {
+                self.store16(STATE_PROGRAM_COUNTER_OFFSET, |e| e.constant(target));
+            }
+            Instruction::Call { address: target } => {
+                self.load8(STATE_STACK_POINTER_OFFSET);
+                self.function
+                    .instructions()
+                    .i32_const(STACK_SIZE as i32)
+                    .i32_ge_u();
+                self.exit_if(address, index);
+
+                self.load8(STATE_STACK_POINTER_OFFSET);
+                self.function
+                    .instructions()
+                    .i32_const(1)
+                    .i32_shl()
+                    .i32_const(address as i32)
+                    .i32_store16(memory_at(STATE_STACK_OFFSET));
+                self.store8(STATE_STACK_POINTER_OFFSET, |e| {
+                    e.load8(STATE_STACK_POINTER_OFFSET);
+                    e.function.instructions().i32_const(1).i32_add();
+                });
+                self.store16(STATE_PROGRAM_COUNTER_OFFSET, |e| e.constant(target));
+            }
+            Instruction::SkipIfEqual { x, value } => self.skip_if(address, |e| {
+                e.load8(Self::register(x));
+                e.function.instructions().i32_const(value as i32).i32_eq();
+            }),
+            Instruction::SkipIfNotEqual { x, value } => self.skip_if(address, |e| {
+                e.load8(Self::register(x));
+                e.function.instructions().i32_const(value as i32).i32_ne();
+            }),
+            Instruction::SkipIfRegistersEqual { x, y } => self.skip_if(address, |e| {
+                e.load8(Self::register(x));
+                e.load8(Self::register(y));
+                e.function.instructions().i32_eq();
+            }),
+            Instruction::SkipIfRegistersNotEqual { x, y } => self.skip_if(address, |e| {
+                e.load8(Self::register(x));
+                e.load8(Self::register(y));
+                e.function.instructions().i32_ne();
+            }),
+            Instruction::SetRegister { x, value } => {
+                self.store8(Self::register(x), |e| e.constant(value as u16));
+            }
+            Instruction::AddToRegister { x, value } => {
+                self.store8(Self::register(x), |e| {
+                    e.load8(Self::register(x));
+                    e.function.instructions().i32_const(value as i32).i32_add();
+                });
+            }
+            Instruction::CopyRegister { x, y } => {
+                self.store8(Self::register(x), |e| e.load8(Self::register(y)));
+            }
+            Instruction::Or { x, y } => {
+                self.binary(x, y, |e| {
+                    e.function.instructions().i32_or();
+                });
+                self.reset_vf();
+            }
+            Instruction::And { x, y } => {
+                self.binary(x, y, |e| {
+                    e.function.instructions().i32_and();
+                });
+                self.reset_vf();
+            }
+            Instruction::Xor { x, y } => {
+                self.binary(x, y, |e| {
+                    e.function.instructions().i32_xor();
+                });
+                self.reset_vf();
+            }
+            Instruction::Add { x, y } => self.with_flag(
+                |e| {
+                    e.load8(Self::register(x));
+                    e.load8(Self::register(y));
+                    e.function
+                        .instructions()
+                        .i32_add()
+                        .i32_const(0xFF)
+                        .i32_gt_u();
+                },
+                |e| {
+                    e.binary(x, y, |e| {
+                        e.function.instructions().i32_add();
+                    })
+                },
+            ),
+            Instruction::Subtract { x, y } => self.with_flag(
+                |e| {
+                    e.load8(Self::register(y));
+                    e.load8(Self::register(x));
+                    e.function.instructions().i32_le_u();
+                },
+                |e| {
+                    e.binary(x, y, |e| {
+                        e.function.instructions().i32_sub();
+                    })
+                },
+            ),
+            Instruction::SubtractReversed { x, y } => self.with_flag(
+                |e| {
+                    e.load8(Self::register(x));
+                    e.load8(Self::register(y));
+                    e.function.instructions().i32_le_u();
+                },
+                |e| {
+                    e.store8(Self::register(x), |e| {
+                        e.load8(Self::register(y));
+                        e.load8(Self::register(x));
+                        e.function.instructions().i32_sub();
+                    })
+                },
+            ),
+            Instruction::ShiftRight { x, y } => {
+                let source = Self::register(if self.quirks.shift_uses_vy { y } else { x });
+                self.with_flag(
+                    |e| {
+                        e.load8(source);
+                        e.function.instructions().i32_const(1).i32_and();
+                    },
+                    |e| {
+                        e.store8(Self::register(x), |e| {
+                            e.load8(source);
+                            e.function.instructions().i32_const(1).i32_shr_u();
+                        })
+                    },
+                );
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let source = Self::register(if self.quirks.shift_uses_vy { y } else { x });
+                self.with_flag(
+                    |e| {
+                        e.load8(source);
+                        e.function.instructions().i32_const(7).i32_shr_u();
+                    },
+                    |e| {
+                        e.store8(Self::register(x), |e| {
+                            e.load8(source);
+                            e.function.instructions().i32_const(1).i32_shl();
+                        })
+                    },
+                );
+            }
+            Instruction::SetIndex { address } => {
+                self.store16(STATE_INDEX_REGISTER_OFFSET, |e| e.constant(address));
+            }
+            Instruction::SkipIfKeyPressed { x } => self.skip_if(address, |e| {
+                e.key_pressed(x, address, index);
+                e.function.instructions().i32_const(0).i32_ne();
+            }),
+            Instruction::SkipIfKeyNotPressed { x } => self.skip_if(address, |e| {
+                e.key_pressed(x, address, index);
+                e.function.instructions().i32_eqz();
+            }),
+            Instruction::GetDelayTimer { x } => {
+                self.store8(Self::register(x), |e| e.load8(STATE_DELAY_TIMER_OFFSET));
+            }
+            Instruction::SetDelayTimer { x } => {
+                self.store8(STATE_DELAY_TIMER_OFFSET, |e| e.load8(Self::register(x)));
+            }
+            Instruction::SetSoundTimer { x } => {
+                self.store8(STATE_SOUND_TIMER_OFFSET, |e| e.load8(Self::register(x)));
+            }
+            Instruction::AddToIndex { x } => {
+                self.store16(STATE_INDEX_REGISTER_OFFSET, |e| {
+                    e.load16(STATE_INDEX_REGISTER_OFFSET);
+                    e.load8(Self::register(x));
+                    e.function.instructions().i32_add();
+                });
+            }
+            Instruction::SetIndexToFont { x } => {
+                self.store16(STATE_INDEX_REGISTER_OFFSET, |e| {
+                    e.load8(Self::register(x));
+                    e.function
+                        .instructions()
+                        .i32_const(0xF)
+                        .i32_and()
+                        .i32_const(5)
+                        .i32_mul();
+                });
+            }
+            Instruction::JumpWithOffset { .. }
+            | Instruction::Random { .. }
+            | Instruction::Draw { .. }
+            | Instruction::WaitForKey { .. }
+            | Instruction::StoreBcd { .. }
+            | Instruction::StoreRegisters { .. }
+            | Instruction::LoadRegisters { .. }
+            | Instruction::Unknown { .. } => unreachable!("Not compilable: {:?}", instruction),
+        }
+    }
+
+    /// Mirrors `Processor::update_timers`.
+    fn tick_timers(&mut self) {
+        for offset in [STATE_DELAY_TIMER_OFFSET, STATE_SOUND_TIMER_OFFSET] {
+            self.store8(offset, |e| {
+                e.load8(offset);
+                e.function
+                    .instructions()
+                    .i32_const(1)
+                    .i32_sub()
+                    .i32_const(0);
+                e.load8(offset);
+                e.function.instructions().select();
+            });
+        }
+        self.store8(STATE_BUZZER_OFFSET, |e| {
+            e.load8(STATE_SOUND_TIMER_OFFSET);
+            e.function.instructions().i32_const(0).i32_ne();
+        });
+    }
+}
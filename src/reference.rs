@@ -0,0 +1,492 @@
+//! A deliberately simple reference implementation of the CHIP-8 instruction
+//! set, run in lockstep with [`Chip8CPU`] to catch the first instruction where
+//! the two disagree.
+//!
+//! The reference follows the commonly documented behaviour of each
+//! instruction, honouring the same [`Quirks`], and favours being obviously
+//! right over being fast. A few things follow the interpreter instead, since
+//! they're implementation choices rather than instruction semantics: timers
+//! count down once per cycle, the stack holds the address of each 2NNN, and
+//! CXNN takes its random byte from whatever the interpreter produced, as long
+//! as it fits the mask.
+//!
+//! ```no_run
+//! use chip8_wasm::chip8_cpu::Chip8CPU;
+//! use chip8_wasm::reference::Differential;
+//!
+//! let mut cpu = Chip8CPU::new();
+//! cpu.load_rom(&[0x60, 0x05]).unwrap();
+//! let mut differential = Differential::new(&cpu);
+//! if let Err(mismatch) = differential.run(&mut cpu, 1000) {
+//!     println!("{}", mismatch);
+//! }
+//! ```
+
+use crate::chip8_cpu::{
+    Chip8CPU, KeyWait, KEY_COUNT, MEMORY_SIZE, REGISTER_COUNT, STACK_SIZE,
+    STATE_DELAY_TIMER_OFFSET, STATE_DISPLAY_OFFSET, STATE_INDEX_REGISTER_OFFSET, STATE_KEYS_OFFSET,
+    STATE_KEY_WAIT_OFFSET, STATE_MEMORY_OFFSET, STATE_PROGRAM_COUNTER_OFFSET,
+    STATE_REGISTERS_OFFSET, STATE_SIZE, STATE_SOUND_TIMER_OFFSET, STATE_STACK_OFFSET,
+    STATE_STACK_POINTER_OFFSET,
+};
+use crate::display::{BUFFER_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use crate::traits::Platform;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use core::fmt;
+
+/// Everything the two implementations are compared on. The draw flag, buzzer
+/// and frame count are signals for the frontend, so they're left out.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MachineState {
+    pub memory: [u8; MEMORY_SIZE],
+    pub registers: [u8; REGISTER_COUNT],
+    pub stack: [u16; STACK_SIZE],
+    pub stack_pointer: u8,
+    pub program_counter: u16,
+    pub index_register: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: [u8; KEY_COUNT],
+    pub display: [u8; BUFFER_SIZE],
+    pub key_wait: KeyWait,
+}
+
+impl MachineState {
+    pub fn capture<P: Platform>(cpu: &Chip8CPU<P>) -> MachineState {
+        let mut buffer = vec![0; STATE_SIZE];
+        cpu.save_state(&mut buffer)
+            .expect("The buffer fits a state");
+        let u16_at = |offset: usize| u16::from_le_bytes([buffer[offset], buffer[offset + 1]]);
+
+        let mut state = MachineState {
+            memory: [0; MEMORY_SIZE],
+            registers: [0; REGISTER_COUNT],
+            stack: [0; STACK_SIZE],
+            stack_pointer: buffer[STATE_STACK_POINTER_OFFSET],
+            program_counter: u16_at(STATE_PROGRAM_COUNTER_OFFSET),
+            index_register: u16_at(STATE_INDEX_REGISTER_OFFSET),
+            delay_timer: buffer[STATE_DELAY_TIMER_OFFSET],
+            sound_timer: buffer[STATE_SOUND_TIMER_OFFSET],
+            keys: [0; KEY_COUNT],
+            display: [0; BUFFER_SIZE],
+            key_wait: KeyWait::from_byte(buffer[STATE_KEY_WAIT_OFFSET]),
+        };
+        state
+            .memory
+            .copy_from_slice(&buffer[STATE_MEMORY_OFFSET..STATE_MEMORY_OFFSET + MEMORY_SIZE]);
+        state.registers.copy_from_slice(
+            &buffer[STATE_REGISTERS_OFFSET..STATE_REGISTERS_OFFSET + REGISTER_COUNT],
+        );
+        (0..STACK_SIZE)
+            .for_each(|index| state.stack[index] = u16_at(STATE_STACK_OFFSET + index * 2));
+        state
+            .keys
+            .copy_from_slice(&buffer[STATE_KEYS_OFFSET..STATE_KEYS_OFFSET + KEY_COUNT]);
+        state
+            .display
+            .copy_from_slice(&buffer[STATE_DISPLAY_OFFSET..STATE_DISPLAY_OFFSET + BUFFER_SIZE]);
+
+        state
+    }
+
+    fn opcode_at(&self, address: u16) -> u16 {
+        let address = address as usize;
+
+        ((self.memory[address % MEMORY_SIZE] as u16) << 8)
+            | self.memory[(address + 1) % MEMORY_SIZE] as u16
+    }
+}
+
+/// The registers, timers and stack. Memory and the display are too big to
+/// print, [`Mismatch`] lists where they differ instead.
+impl fmt::Display for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V:")?;
+        for register in self.registers.iter() {
+            write!(f, " {:02X}", register)?;
+        }
+        writeln!(
+            f,
+            "\nPC:{:04X} I:{:04X} SP:{:02X} DT:{:02X} ST:{:02X} FX0A:{:?}",
+            self.program_counter,
+            self.index_register,
+            self.stack_pointer,
+            self.delay_timer,
+            self.sound_timer,
+            self.key_wait
+        )?;
+        write!(f, "Stack:")?;
+        for address in self.stack.iter() {
+            write!(f, " {:04X}", address)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+pub struct ReferenceCpu {
+    pub state: MachineState,
+    pub quirks: Quirks,
+}
+
+impl ReferenceCpu {
+    pub fn new(state: MachineState, quirks: Quirks) -> ReferenceCpu {
+        ReferenceCpu { state, quirks }
+    }
+
+    /// Runs one instruction and counts the timers down, like
+    /// [`Chip8CPU::cycle`]. CXNN uses `random_byte`.
+    pub fn cycle(&mut self, random_byte: u8) {
+        let quirks = self.quirks;
+        let state = &mut self.state;
+        let instruction = Instruction::decode(state.opcode_at(state.program_counter));
+        let next = state.program_counter.wrapping_add(2);
+        let skip = state.program_counter.wrapping_add(4);
+        let index_register = state.index_register as usize;
+        let address = move |offset: usize| (index_register + offset) % MEMORY_SIZE;
+        let v = &mut state.registers;
+
+        // A halted machine does nothing, timers included.
+        if let Instruction::Unknown { .. } = instruction {
+            return;
+        }
+        state.program_counter = match instruction {
+            Instruction::ClearScreen => {
+                state.display = [0; BUFFER_SIZE];
+                next
+            }
+            Instruction::Return => {
+                state.stack_pointer = state.stack_pointer.wrapping_sub(1);
+                state.stack[state.stack_pointer as usize % STACK_SIZE].wrapping_add(2)
+            }
+            Instruction::Jump { address } => address,
+            Instruction::Call { address } => {
+                state.stack[state.stack_pointer as usize % STACK_SIZE] = state.program_counter;
+                state.stack_pointer = state.stack_pointer.wrapping_add(1);
+                address
+            }
+            Instruction::SkipIfEqual { x, value } if v[x] == value => skip,
+            Instruction::SkipIfNotEqual { x, value } if v[x] != value => skip,
+            Instruction::SkipIfRegistersEqual { x, y } if v[x] == v[y] => skip,
+            Instruction::SkipIfRegistersNotEqual { x, y } if v[x] != v[y] => skip,
+            Instruction::SkipIfEqual { .. }
+            | Instruction::SkipIfNotEqual { .. }
+            | Instruction::SkipIfRegistersEqual { .. }
+            | Instruction::SkipIfRegistersNotEqual { .. } => next,
+            Instruction::SetRegister { x, value } => {
+                v[x] = value;
+                next
+            }
+            Instruction::AddToRegister { x, value } => {
+                v[x] = v[x].wrapping_add(value);
+                next
+            }
+            Instruction::CopyRegister { x, y } => {
+                v[x] = v[y];
+                next
+            }
+            Instruction::Or { x, y } | Instruction::And { x, y } | Instruction::Xor { x, y } => {
+                v[x] = match instruction {
+                    Instruction::Or { .. } => v[x] | v[y],
+                    Instruction::And { .. } => v[x] & v[y],
+                    _ => v[x] ^ v[y],
+                };
+                if quirks.vf_reset {
+                    v[0xF] = 0;
+                }
+                next
+            }
+            // The flag is written last, so it wins when X is F.
+            Instruction::Add { x, y } => {
+                let (result, carry) = v[x].overflowing_add(v[y]);
+                v[x] = result;
+                v[0xF] = carry as u8;
+                next
+            }
+            Instruction::Subtract { x, y } => {
+                let (result, borrow) = v[x].overflowing_sub(v[y]);
+                v[x] = result;
+                v[0xF] = !borrow as u8;
+                next
+            }
+            Instruction::SubtractReversed { x, y } => {
+                let (result, borrow) = v[y].overflowing_sub(v[x]);
+                v[x] = result;
+                v[0xF] = !borrow as u8;
+                next
+            }
+            Instruction::ShiftRight { x, y } => {
+                let source = if quirks.shift_uses_vy { v[y] } else { v[x] };
+                v[x] = source >> 1;
+                v[0xF] = source & 0x1;
+                next
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let source = if quirks.shift_uses_vy { v[y] } else { v[x] };
+                v[x] = source << 1;
+                v[0xF] = source >> 7;
+                next
+            }
+            Instruction::SetIndex { address } => {
+                state.index_register = address;
+                next
+            }
+            Instruction::JumpWithOffset { address, x } => {
+                let offset_register = if quirks.jump_uses_vx { x } else { 0 };
+                address + v[offset_register] as u16
+            }
+            Instruction::Random { x, mask } => {
+                v[x] = random_byte & mask;
+                next
+            }
+            Instruction::Draw { x, y, height } => {
+                // The sprite starts wrapped onto the screen, and is clipped at its edges.
+                let left = v[x] as usize % DISPLAY_WIDTH as usize;
+                let top = v[y] as usize % DISPLAY_HEIGHT as usize;
+                v[0xF] = 0;
+                for row in 0..height as usize {
+                    let sprite = state.memory[address(row)];
+                    for column in 0..8 {
+                        let (pixel_x, pixel_y) = (left + column, top + row);
+                        if sprite & (0x80 >> column) == 0
+                            || pixel_x >= DISPLAY_WIDTH as usize
+                            || pixel_y >= DISPLAY_HEIGHT as usize
+                        {
+                            continue;
+                        }
+
+                        let pixel = &mut state.display[pixel_y * DISPLAY_WIDTH as usize + pixel_x];
+                        if *pixel == 1 {
+                            v[0xF] = 1;
+                        }
+                        *pixel ^= 1;
+                    }
+                }
+                next
+            }
+            Instruction::SkipIfKeyPressed { x } if state.keys[v[x] as usize % KEY_COUNT] != 0 => {
+                skip
+            }
+            Instruction::SkipIfKeyNotPressed { x }
+                if state.keys[v[x] as usize % KEY_COUNT] == 0 =>
+            {
+                skip
+            }
+            Instruction::SkipIfKeyPressed { .. } | Instruction::SkipIfKeyNotPressed { .. } => next,
+            Instruction::GetDelayTimer { x } => {
+                v[x] = state.delay_timer;
+                next
+            }
+            // Waits for a press, and with the quirk for that key's release.
+            Instruction::WaitForKey { x } => {
+                let pressed = state.keys.iter().position(|key| *key != 0);
+                match (state.key_wait, pressed) {
+                    (KeyWait::Release(key), _) if state.keys[key as usize] != 0 => {
+                        state.program_counter
+                    }
+                    (KeyWait::Release(key), _) => {
+                        state.key_wait = KeyWait::Idle;
+                        v[x] = key;
+                        next
+                    }
+                    (_, None) => {
+                        state.key_wait = KeyWait::Press;
+                        state.program_counter
+                    }
+                    (_, Some(key)) if quirks.key_wait_release => {
+                        state.key_wait = KeyWait::Release(key as u8);
+                        state.program_counter
+                    }
+                    (_, Some(key)) => {
+                        state.key_wait = KeyWait::Idle;
+                        v[x] = key as u8;
+                        next
+                    }
+                }
+            }
+            Instruction::SetDelayTimer { x } => {
+                state.delay_timer = v[x];
+                next
+            }
+            Instruction::SetSoundTimer { x } => {
+                state.sound_timer = v[x];
+                next
+            }
+            Instruction::AddToIndex { x } => {
+                state.index_register = state.index_register.wrapping_add(v[x] as u16);
+                next
+            }
+            Instruction::SetIndexToFont { x } => {
+                state.index_register = (v[x] & 0xF) as u16 * 5;
+                next
+            }
+            Instruction::StoreBcd { x } => {
+                let value = v[x];
+                state.memory[address(0)] = value / 100;
+                state.memory[address(1)] = value / 10 % 10;
+                state.memory[address(2)] = value % 10;
+                next
+            }
+            Instruction::StoreRegisters { x } => {
+                for (index, register) in v.iter().enumerate().take(x + 1) {
+                    state.memory[address(index)] = *register;
+                }
+                if quirks.load_store_increments_i {
+                    state.index_register += x as u16 + 1;
+                }
+                next
+            }
+            Instruction::LoadRegisters { x } => {
+                for (index, register) in v.iter_mut().enumerate().take(x + 1) {
+                    *register = state.memory[address(index)];
+                }
+                if quirks.load_store_increments_i {
+                    state.index_register += x as u16 + 1;
+                }
+                next
+            }
+            Instruction::Unknown { .. } => unreachable!("Returned early"),
+        };
+
+        state.delay_timer = state.delay_timer.saturating_sub(1);
+        state.sound_timer = state.sound_timer.saturating_sub(1);
+    }
+}
+
+/// The first cycle after which the interpreter and the reference disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Cycles run since the [`Differential`] started, counting this one.
+    pub cycle: u64,
+    pub opcode: u16,
+    /// Both states from before the cycle.
+    pub before: Box<MachineState>,
+    pub reference: Box<MachineState>,
+    pub interpreter: Box<MachineState>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Mismatch on cycle {} running {:04X} ({}) at {:04X}",
+            self.cycle,
+            self.opcode,
+            Instruction::decode(self.opcode),
+            self.before.program_counter
+        )?;
+        writeln!(f, "Before:\n{}", self.before)?;
+        writeln!(f, "Reference:\n{}", self.reference)?;
+        write!(f, "Interpreter:\n{}", self.interpreter)?;
+
+        for address in 0..MEMORY_SIZE {
+            let (expected, actual) = (
+                self.reference.memory[address],
+                self.interpreter.memory[address],
+            );
+            if expected != actual {
+                write!(
+                    f,
+                    "\nMemory {:03X}: reference {:02X}, interpreter {:02X}",
+                    address, expected, actual
+                )?;
+            }
+        }
+        let differing_pixels = self
+            .reference
+            .display
+            .iter()
+            .zip(self.interpreter.display.iter())
+            .filter(|(expected, actual)| expected != actual)
+            .count();
+        if differing_pixels > 0 {
+            write!(f, "\n{} pixels differ", differing_pixels)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs a [`Chip8CPU`] and a [`ReferenceCpu`] side by side, comparing their
+/// whole state after every cycle.
+pub struct Differential {
+    reference: ReferenceCpu,
+    cycle: u64,
+}
+
+impl Differential {
+    /// Starts the reference from `cpu`'s current state and quirks.
+    pub fn new<P: Platform>(cpu: &Chip8CPU<P>) -> Differential {
+        Differential {
+            reference: ReferenceCpu::new(MachineState::capture(cpu), cpu.get_quirks()),
+            cycle: 0,
+        }
+    }
+
+    pub fn get_reference(&self) -> &ReferenceCpu {
+        &self.reference
+    }
+
+    /// Runs a cycle on both. After a mismatch the reference carries on from
+    /// the interpreter's state.
+    pub fn cycle<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>) -> Result<(), Mismatch> {
+        // Keys are input rather than state either side computes.
+        self.reference
+            .state
+            .keys
+            .copy_from_slice(&MachineState::capture(cpu).keys);
+        let before = self.reference.state.clone();
+        let opcode = before.opcode_at(before.program_counter);
+
+        cpu.cycle();
+        let interpreter = MachineState::capture(cpu);
+        let random_byte = match Instruction::decode(opcode) {
+            Instruction::Random { x, .. } => interpreter.registers[x],
+            _ => 0,
+        };
+        self.reference.cycle(random_byte);
+        self.cycle += 1;
+
+        if self.reference.state == interpreter {
+            return Ok(());
+        }
+
+        let mismatch = Mismatch {
+            cycle: self.cycle,
+            opcode,
+            before: Box::new(before),
+            reference: Box::new(self.reference.state.clone()),
+            interpreter: Box::new(interpreter.clone()),
+        };
+        self.reference.state = interpreter;
+
+        Err(mismatch)
+    }
+
+    /// Runs up to `cycles` cycles, stopping at the first mismatch.
+    pub fn run<P: Platform>(&mut self, cpu: &mut Chip8CPU<P>, cycles: u32) -> Result<(), Mismatch> {
+        (0..cycles).try_for_each(|_| self.cycle(cpu))
+    }
+
+    /// Equivalent of [`Chip8CPU::run_frame`].
+    pub fn run_frame<P: Platform>(
+        &mut self,
+        cpu: &mut Chip8CPU<P>,
+        instructions_per_frame: u32,
+    ) -> Result<(), Mismatch> {
+        let result = self.run(cpu, instructions_per_frame);
+        cpu.end_frame();
+
+        result
+    }
+}
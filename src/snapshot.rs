@@ -0,0 +1,153 @@
+//! Snapshots of the machine state as plain JavaScript objects, for debugger
+//! panels, and diffs between them, see [`Chip8::get_state`](crate::chip8::Chip8::get_state).
+
+use crate::chip8_cpu::Chip8CPU;
+use crate::quirks::Quirks;
+use crate::traits::Platform;
+
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+/// Property names, in the order of [`StateSnapshot::fields`].
+pub const FIELD_NAMES: [&str; 13] = [
+    "registers",
+    "indexRegister",
+    "programCounter",
+    "stack",
+    "stackPointer",
+    "delayTimer",
+    "soundTimer",
+    "pressedKeys",
+    "quirks",
+    "mode",
+    "frameCount",
+    "cycleCount",
+    "generation",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Number(f64),
+    Numbers(Vec<u16>),
+    Text(&'static str),
+    Quirks(Quirks),
+}
+
+impl FieldValue {
+    pub fn to_js(&self) -> JsValue {
+        match self {
+            FieldValue::Number(number) => JsValue::from_f64(*number),
+            FieldValue::Numbers(numbers) => numbers
+                .iter()
+                .map(|number| JsValue::from(*number))
+                .collect::<Array>()
+                .into(),
+            FieldValue::Text(text) => JsValue::from_str(text),
+            FieldValue::Quirks(quirks) => {
+                let object = Object::new();
+                set(&object, "shiftUsesVy", quirks.shift_uses_vy.into());
+                set(
+                    &object,
+                    "loadStoreIncrementsI",
+                    quirks.load_store_increments_i.into(),
+                );
+                set(&object, "jumpUsesVx", quirks.jump_uses_vx.into());
+                set(&object, "vfReset", quirks.vf_reset.into());
+                set(&object, "keyWaitRelease", quirks.key_wait_release.into());
+                object.into()
+            }
+        }
+    }
+}
+
+/// The machine state a debugger shows, one value per name in [`FIELD_NAMES`].
+/// Only the live stack frames, below the stack pointer, are included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateSnapshot {
+    pub fields: [FieldValue; FIELD_NAMES.len()],
+}
+
+impl StateSnapshot {
+    /// `mode` is what the frontend is doing, like playing back a movie.
+    pub fn capture<P: Platform>(cpu: &Chip8CPU<P>, mode: &'static str) -> StateSnapshot {
+        let live_stack = &cpu.get_stack()[..cpu.get_stack_pointer() as usize];
+        let pressed_keys = cpu
+            .get_keys()
+            .iter()
+            .enumerate()
+            .filter(|(_, pressed)| **pressed != 0)
+            .map(|(key, _)| key as u16)
+            .collect();
+
+        StateSnapshot {
+            fields: [
+                FieldValue::Numbers(cpu.get_registers().iter().map(|v| *v as u16).collect()),
+                FieldValue::Number(cpu.get_index_register() as f64),
+                FieldValue::Number(cpu.get_program_counter() as f64),
+                FieldValue::Numbers(live_stack.to_vec()),
+                FieldValue::Number(cpu.get_stack_pointer() as f64),
+                FieldValue::Number(cpu.get_delay_timer() as f64),
+                FieldValue::Number(cpu.get_sound_timer() as f64),
+                FieldValue::Numbers(pressed_keys),
+                FieldValue::Quirks(cpu.get_quirks()),
+                FieldValue::Text(mode),
+                FieldValue::Number(cpu.get_frame_count() as f64),
+                FieldValue::Number(cpu.get_cycle_count() as f64),
+                FieldValue::Number(cpu.get_generation() as f64),
+            ],
+        }
+    }
+
+    /// The fields for which `include` returns true, by index, as an object.
+    pub fn to_object(&self, include: impl Fn(usize) -> bool) -> Object {
+        let object = Object::new();
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| include(*index))
+            .for_each(|(index, value)| set(&object, FIELD_NAMES[index], value.to_js()));
+
+        object
+    }
+}
+
+/// Remembers the generation each field last changed in, as far as the
+/// snapshots it's been shown tell, to answer "what changed since".
+#[derive(Clone, Debug, Default)]
+pub struct StateHistory {
+    last: Option<StateSnapshot>,
+    changed_in: [u64; FIELD_NAMES.len()],
+}
+
+impl StateHistory {
+    /// Records `snapshot`, taken at `generation`, and returns the fields that
+    /// changed after `since_generation`. A field that changed between two
+    /// recorded snapshots counts as changed at the later one.
+    pub fn record(
+        &mut self,
+        snapshot: StateSnapshot,
+        generation: u64,
+        since_generation: u64,
+    ) -> Object {
+        match &self.last {
+            None => self.changed_in = [generation; FIELD_NAMES.len()],
+            Some(last) => last
+                .fields
+                .iter()
+                .zip(snapshot.fields.iter())
+                .zip(self.changed_in.iter_mut())
+                .filter(|((before, after), _)| before != after)
+                .for_each(|(_, changed_in)| *changed_in = generation),
+        }
+
+        let changed_in = self.changed_in;
+        let object = snapshot.to_object(|index| changed_in[index] > since_generation);
+        self.last = Some(snapshot);
+
+        object
+    }
+}
+
+fn set(object: &Object, name: &str, value: JsValue) {
+    Reflect::set(object, &JsValue::from_str(name), &value).expect("Setting on a plain object");
+}
@@ -0,0 +1,116 @@
+//! Emulation speed: pausing, frame advance, slow motion and fast-forward.
+//!
+//! Speed is applied by running a varying number of whole 60 Hz frames for
+//! each frame the host displays, so the CPU and its timers speed up and slow
+//! down together and movies stay in step with their inputs. Speeds are kept
+//! in hundredths so that, for example, 0.1x runs exactly one frame in ten.
+
+pub const MIN_SPEED_PERCENT: u32 = 10;
+pub const MAX_SPEED_PERCENT: u32 = 1000;
+pub const NORMAL_SPEED_PERCENT: u32 = 100;
+/// The speed of a capped fast-forward.
+pub const FAST_FORWARD_SPEED_PERCENT: u32 = MAX_SPEED_PERCENT;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeedError {
+    /// Outside [`MIN_SPEED_PERCENT`]..=[`MAX_SPEED_PERCENT`].
+    OutOfRange(u32),
+}
+
+/// How many frames to run for one host frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramesDue {
+    Count(u32),
+    /// As many as the host can run before its next frame.
+    Uncapped,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpeedControl {
+    speed_percent: u32,
+    paused: bool,
+    fast_forward: bool,
+    uncapped: bool,
+    /// Hundredths of a frame carried over from the last host frame.
+    remainder: u32,
+}
+
+impl Default for SpeedControl {
+    fn default() -> SpeedControl {
+        SpeedControl::new()
+    }
+}
+
+impl SpeedControl {
+    pub fn new() -> SpeedControl {
+        SpeedControl {
+            speed_percent: NORMAL_SPEED_PERCENT,
+            paused: false,
+            fast_forward: false,
+            uncapped: false,
+            remainder: 0,
+        }
+    }
+
+    pub fn get_speed_percent(&self) -> u32 {
+        self.speed_percent
+    }
+
+    pub fn set_speed_percent(&mut self, speed_percent: u32) -> Result<(), SpeedError> {
+        if !(MIN_SPEED_PERCENT..=MAX_SPEED_PERCENT).contains(&speed_percent) {
+            return Err(SpeedError::OutOfRange(speed_percent));
+        }
+        self.speed_percent = speed_percent;
+        self.remainder = 0;
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pausing drops any part-frame built up by slow motion, so resuming
+    /// always starts from a whole frame. Frame advance is up to the caller,
+    /// since nothing is due while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.remainder = 0;
+    }
+
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.fast_forward
+    }
+
+    /// Fast-forward overrides the speed for as long as it's held, and is
+    /// ignored while paused.
+    pub fn set_fast_forward(&mut self, fast_forward: bool) {
+        self.fast_forward = fast_forward;
+        self.remainder = 0;
+    }
+
+    pub fn is_uncapped(&self) -> bool {
+        self.uncapped
+    }
+
+    /// Whether fast-forward runs as fast as the host allows rather than at
+    /// [`FAST_FORWARD_SPEED_PERCENT`].
+    pub fn set_uncapped(&mut self, uncapped: bool) {
+        self.uncapped = uncapped;
+    }
+
+    /// The frames to run for one host frame, at 60 Hz.
+    pub fn frames_due(&mut self) -> FramesDue {
+        if self.paused {
+            return FramesDue::Count(0);
+        }
+        if self.fast_forward {
+            if self.uncapped {
+                return FramesDue::Uncapped;
+            }
+            return FramesDue::Count(FAST_FORWARD_SPEED_PERCENT / NORMAL_SPEED_PERCENT);
+        }
+        let due = self.remainder + self.speed_percent;
+        self.remainder = due % NORMAL_SPEED_PERCENT;
+        FramesDue::Count(due / NORMAL_SPEED_PERCENT)
+    }
+}
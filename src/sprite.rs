@@ -0,0 +1,259 @@
+//! Sprites as bitmaps, to edit the graphics of a ROM and put them back.
+//!
+//! A sprite is rows of pixels, one bit each, most significant on the left:
+//! one byte per row for the 8xN sprites DXYN draws, two for the 16x16 ones
+//! SUPER-CHIP draws with DXY0. XO-CHIP draws a sprite to each selected plane
+//! in turn, so a sprite for several planes is one for each, back to back. In
+//! a bitmap, bit `n` of a pixel's colour is whether it's lit on plane `n`.
+
+use crate::analysis::{analyze_rom, ENTRY_POINT};
+use crate::image::Bitmap;
+
+use alloc::vec::Vec;
+
+/// Sheets lay sprites out in cells the size of the largest, with a gap
+/// between them.
+const SHEET_CELL_SIZE: u32 = 16;
+const SHEET_GAP: u32 = 1;
+const SHEET_COLUMNS: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpriteShape {
+    /// 8, or 16 for 16x16 sprites.
+    pub width: u8,
+    pub height: u8,
+    /// 1, or up to 4 on XO-CHIP.
+    pub planes: u8,
+}
+
+impl SpriteShape {
+    /// What DXYN draws with `planes` planes selected.
+    pub fn from_draw(height: u8, planes: u8) -> SpriteShape {
+        match height {
+            0 => SpriteShape {
+                width: 16,
+                height: 16,
+                planes,
+            },
+            _ => SpriteShape {
+                width: 8,
+                height,
+                planes,
+            },
+        }
+    }
+
+    pub fn byte_len(self) -> usize {
+        self.width as usize / 8 * self.height as usize * self.planes as usize
+    }
+
+    fn check(self) -> Result<(), SpriteError> {
+        let size_valid = match self.width {
+            8 => (1..=15).contains(&self.height),
+            16 => self.height == 16,
+            _ => false,
+        };
+        if !size_valid || !(1..=4).contains(&self.planes) {
+            return Err(SpriteError::InvalidShape);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpriteError {
+    /// Not a size DXYN draws, or more planes than XO-CHIP has.
+    InvalidShape,
+    /// Bytes or a bitmap that don't fit the shape.
+    WrongSize,
+    /// A pixel lit on a plane the sprite doesn't have.
+    InvalidColour { x: u32, y: u32 },
+    /// A sprite that isn't all inside the ROM.
+    OutOfRange(u16),
+}
+
+pub fn decode_sprite(shape: SpriteShape, bytes: &[u8]) -> Result<Bitmap, SpriteError> {
+    shape.check()?;
+    if bytes.len() != shape.byte_len() {
+        return Err(SpriteError::WrongSize);
+    }
+
+    let mut bitmap = Bitmap::new(shape.width as u32, shape.height as u32);
+    let row_size = shape.width as usize / 8;
+    let plane_size = row_size * shape.height as usize;
+    for (plane, plane_bytes) in bytes.chunks(plane_size).enumerate() {
+        for (y, row) in plane_bytes.chunks(row_size).enumerate() {
+            for x in 0..shape.width as u32 {
+                if row[x as usize / 8] & (0x80 >> (x % 8)) != 0 {
+                    let colour = bitmap.get(x, y as u32).unwrap_or(0);
+                    bitmap.set(x, y as u32, colour | 1 << plane);
+                }
+            }
+        }
+    }
+
+    Ok(bitmap)
+}
+
+pub fn encode_sprite(shape: SpriteShape, bitmap: &Bitmap) -> Result<Vec<u8>, SpriteError> {
+    shape.check()?;
+    if bitmap.width != shape.width as u32 || bitmap.height != shape.height as u32 {
+        return Err(SpriteError::WrongSize);
+    }
+
+    let mut bytes = Vec::with_capacity(shape.byte_len());
+    for plane in 0..shape.planes {
+        for y in 0..bitmap.height {
+            for byte in 0..bitmap.width / 8 {
+                let mut bits = 0;
+                for bit in 0..8 {
+                    let x = byte * 8 + bit;
+                    let colour = bitmap.get(x, y).unwrap_or(0);
+                    if colour >> shape.planes != 0 {
+                        return Err(SpriteError::InvalidColour { x, y });
+                    }
+                    if colour & 1 << plane != 0 {
+                        bits |= 0x80 >> bit;
+                    }
+                }
+                bytes.push(bits);
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// A sprite in a ROM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FoundSprite {
+    pub address: u16,
+    pub shape: SpriteShape,
+}
+
+impl FoundSprite {
+    /// Where the sprite is in the ROM, or `None` when it isn't all inside it.
+    fn rom_range(self, rom: &[u8]) -> Option<core::ops::Range<usize>> {
+        let start = self.address.checked_sub(ENTRY_POINT)? as usize;
+        let end = start + self.shape.byte_len();
+
+        Some(start..end).filter(|_| end <= rom.len())
+    }
+
+    pub fn read(self, rom: &[u8]) -> Result<Bitmap, SpriteError> {
+        let range = self
+            .rom_range(rom)
+            .ok_or(SpriteError::OutOfRange(self.address))?;
+
+        decode_sprite(self.shape, &rom[range])
+    }
+
+    /// Overwrites the sprite in `rom` with `bitmap`.
+    pub fn write(self, rom: &mut [u8], bitmap: &Bitmap) -> Result<(), SpriteError> {
+        let range = self
+            .rom_range(rom)
+            .ok_or(SpriteError::OutOfRange(self.address))?;
+        rom[range].copy_from_slice(&encode_sprite(self.shape, bitmap)?);
+
+        Ok(())
+    }
+}
+
+/// The sprites a ROM draws, going by where I points at each DXYN, see
+/// [`analyze_rom`]. Sprites drawn with different heights from the same
+/// address count as the tallest. They're found as single plane sprites, since
+/// which planes are selected isn't tracked.
+pub fn find_sprites(rom: &[u8]) -> Vec<FoundSprite> {
+    let mut sprites: Vec<FoundSprite> = Vec::new();
+    for reference in analyze_rom(rom).sprites {
+        let sprite = FoundSprite {
+            address: reference.address,
+            shape: SpriteShape::from_draw(reference.height, 1),
+        };
+        if sprite.rom_range(rom).is_none() {
+            continue;
+        }
+        // References come sorted by address, then height.
+        match sprites.last_mut() {
+            Some(last) if last.address == sprite.address => *last = sprite,
+            _ => sprites.push(sprite),
+        }
+    }
+
+    sprites
+}
+
+/// A grid of sprites in one bitmap, to edit them all at once in an image
+/// editor and put them back with [`SpriteSheet::apply`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpriteSheet {
+    pub sprites: Vec<FoundSprite>,
+}
+
+impl SpriteSheet {
+    /// A sheet of every sprite [`find_sprites`] finds in `rom`.
+    pub fn find(rom: &[u8]) -> SpriteSheet {
+        SpriteSheet {
+            sprites: find_sprites(rom),
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        let columns = (self.sprites.len() as u32).clamp(1, SHEET_COLUMNS);
+        columns * (SHEET_CELL_SIZE + SHEET_GAP) - SHEET_GAP
+    }
+
+    pub fn get_height(&self) -> u32 {
+        let rows = (self.sprites.len() as u32).div_ceil(SHEET_COLUMNS).max(1);
+        rows * (SHEET_CELL_SIZE + SHEET_GAP) - SHEET_GAP
+    }
+
+    /// The top left corner of the cell the sprite at `index` goes in.
+    pub fn cell_position(index: usize) -> (u32, u32) {
+        let index = index as u32;
+        (
+            index % SHEET_COLUMNS * (SHEET_CELL_SIZE + SHEET_GAP),
+            index / SHEET_COLUMNS * (SHEET_CELL_SIZE + SHEET_GAP),
+        )
+    }
+
+    pub fn render(&self, rom: &[u8]) -> Result<Bitmap, SpriteError> {
+        let mut sheet = Bitmap::new(self.get_width(), self.get_height());
+        for (index, sprite) in self.sprites.iter().enumerate() {
+            let (x, y) = SpriteSheet::cell_position(index);
+            sheet.blit(&sprite.read(rom)?, x, y);
+        }
+
+        Ok(sheet)
+    }
+
+    /// Writes the sprites from an edited sheet back into `rom`. Nothing is
+    /// written when any of them is invalid.
+    pub fn apply(&self, rom: &mut [u8], sheet: &Bitmap) -> Result<(), SpriteError> {
+        if sheet.width != self.get_width() || sheet.height != self.get_height() {
+            return Err(SpriteError::WrongSize);
+        }
+
+        let mut edited = rom.to_vec();
+        for (index, sprite) in self.sprites.iter().enumerate() {
+            let (x, y) = SpriteSheet::cell_position(index);
+            let width = sprite.shape.width as u32;
+            let height = sprite.shape.height as u32;
+            sprite
+                .write(&mut edited, &sheet.crop(x, y, width, height))
+                .map_err(|error| match error {
+                    SpriteError::InvalidColour { x: column, y: row } => {
+                        SpriteError::InvalidColour {
+                            x: x + column,
+                            y: y + row,
+                        }
+                    }
+                    error => error,
+                })?;
+        }
+        rom.copy_from_slice(&edited);
+
+        Ok(())
+    }
+}
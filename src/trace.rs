@@ -0,0 +1,245 @@
+//! Execution traces: one line per executed instruction, with the machine state
+//! from just before it ran, while tracing is enabled on a CPU (see
+//! [`Chip8CPU::enable_trace`](crate::chip8_cpu::Chip8CPU::enable_trace)).
+//!
+//! Lines are fixed width, so two traces line up in any diff tool:
+//!
+//! ```text
+//! 00000002 PC:0204 OP:D015 DRW V0, V1, 5      V:05 0A 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:022A SP:00 DT:00 ST:00
+//! ```
+//!
+//! Fields are separated by whitespace:
+//!
+//! | Field       | Content                                              |
+//! |-------------|------------------------------------------------------|
+//! | Cycle       | Instructions the CPU ran before this one, in decimal |
+//! | `PC:`       | Program counter                                      |
+//! | `OP:`       | Opcode                                               |
+//! | Disassembly | Any number of words, only meant for people           |
+//! | `V:`        | V0 to VF, separated by spaces                        |
+//! | `I:`        | Index register                                       |
+//! | `SP:`       | Stack pointer                                        |
+//! | `DT:`, `ST:`| Delay and sound timers                               |
+//!
+//! Everything but the cycle is hexadecimal, upper or lower case. Since the
+//! cycle counts from the CPU's start rather than from when tracing began, traces
+//! started at different points still line up.
+//!
+//! [`TraceEntry::parse`] reads lines from other emulators that follow the
+//! table, whatever their spacing, case or disassembly, and
+//! [`first_divergence`] compares lines that way, so `chip8-trace-diff` can
+//! check a trace from another emulator against one of ours. Fields will only
+//! ever be added to the end of the line, so readers should ignore anything
+//! after `ST:`.
+
+use crate::chip8_cpu::REGISTER_COUNT;
+use crate::instruction::Instruction;
+
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use core::convert::TryFrom;
+use core::fmt::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    /// The named field, like `"PC"`, is missing or isn't a valid value.
+    InvalidField(&'static str),
+}
+
+/// The machine state an instruction was executed from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub program_counter: u16,
+    pub opcode: u16,
+    pub registers: [u8; REGISTER_COUNT],
+    pub index_register: u16,
+    pub stack_pointer: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl TraceEntry {
+    /// Reads a trace line, see the [module documentation](self) for the
+    /// format.
+    pub fn parse(line: &str) -> Result<TraceEntry, TraceError> {
+        let mut fields = line.split_whitespace();
+        let cycle = fields
+            .next()
+            .and_then(|cycle| cycle.parse().ok())
+            .ok_or(TraceError::InvalidField("cycle"))?;
+        let program_counter = hex_field(fields.next(), "PC")?;
+        let opcode = hex_field(fields.next(), "OP")?;
+
+        // Skips the disassembly, however many words it is.
+        let mut fields = fields.skip_while(|field| !field.starts_with("V:"));
+        let mut registers = [0; REGISTER_COUNT];
+        let invalid_register = TraceError::InvalidField("V");
+        let first_register = fields.next().ok_or(invalid_register)?;
+        registers[0] = byte(hex_field(Some(first_register), "V")?, "V")?;
+        for register in registers.iter_mut().skip(1) {
+            *register = fields
+                .next()
+                .and_then(|value| u8::from_str_radix(value, 16).ok())
+                .ok_or(invalid_register)?;
+        }
+
+        Ok(TraceEntry {
+            cycle,
+            program_counter,
+            opcode,
+            registers,
+            index_register: hex_field(fields.next(), "I")?,
+            stack_pointer: byte(hex_field(fields.next(), "SP")?, "SP")?,
+            delay_timer: byte(hex_field(fields.next(), "DT")?, "DT")?,
+            sound_timer: byte(hex_field(fields.next(), "ST")?, "ST")?,
+        })
+    }
+}
+
+/// The value of a `NAME:value` field.
+fn hex_field(field: Option<&str>, name: &'static str) -> Result<u16, TraceError> {
+    field
+        .and_then(|field| field.split_once(':'))
+        .filter(|(field_name, _)| *field_name == name)
+        .and_then(|(_, value)| u16::from_str_radix(value, 16).ok())
+        .ok_or(TraceError::InvalidField(name))
+}
+
+fn byte(value: u16, name: &'static str) -> Result<u8, TraceError> {
+    u8::try_from(value).map_err(|_| TraceError::InvalidField(name))
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let disassembly = format!("{}", Instruction::decode(self.opcode));
+        write!(
+            f,
+            "{:08} PC:{:04X} OP:{:04X} {:<18} V:",
+            self.cycle, self.program_counter, self.opcode, disassembly
+        )?;
+        for (index, register) in self.registers.iter().enumerate() {
+            let separator = if index == 0 { "" } else { " " };
+            write!(f, "{}{:02X}", separator, register)?;
+        }
+        write!(
+            f,
+            " I:{:04X} SP:{:02X} DT:{:02X} ST:{:02X}",
+            self.index_register, self.stack_pointer, self.delay_timer, self.sound_timer
+        )
+    }
+}
+
+/// Keeps the most recent entries, dropping the oldest once full. Frontends
+/// streaming a trace to a callback or a file drain it after every frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceBuffer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl TraceBuffer {
+    pub fn new(capacity: usize) -> TraceBuffer {
+        TraceBuffer {
+            entries: VecDeque::with_capacity(capacity.min(0x10000)),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    /// Removes and returns the buffered entries, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = TraceEntry> + '_ {
+        self.entries.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of entries pushed out by newer ones before being read.
+    pub fn get_dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The buffered entries as trace lines.
+    pub fn to_lines(&self) -> String {
+        let mut lines = String::new();
+        self.entries
+            .iter()
+            .for_each(|entry| writeln!(lines, "{}", entry).unwrap());
+
+        lines
+    }
+}
+
+/// Where two traces first differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceDivergence<'a> {
+    /// Starting from 1.
+    pub line_number: usize,
+    /// `None` when that trace ended first.
+    pub left: Option<&'a str>,
+    pub right: Option<&'a str>,
+}
+
+impl fmt::Display for TraceDivergence<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Traces diverge at line {}:", self.line_number)?;
+        writeln!(f, "< {}", self.left.unwrap_or("(end of trace)"))?;
+        write!(f, "> {}", self.right.unwrap_or("(end of trace)"))
+    }
+}
+
+/// Finds the first line that differs between two traces, or `None` when
+/// they're the same. Lines that both parse as [`TraceEntry`] are compared field
+/// by field, ignoring spacing, case, disassembly and anything after `ST:`;
+/// any others are compared as text, ignoring trailing whitespace.
+pub fn first_divergence<'a>(left: &'a str, right: &'a str) -> Option<TraceDivergence<'a>> {
+    let mut left_lines = left.lines().map(str::trim_end);
+    let mut right_lines = right.lines().map(str::trim_end);
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+        match (left_lines.next(), right_lines.next()) {
+            (None, None) => return None,
+            (Some(left), Some(right)) if same_line(left, right) => {}
+            (left, right) => {
+                return Some(TraceDivergence {
+                    line_number,
+                    left,
+                    right,
+                })
+            }
+        }
+    }
+}
+
+fn same_line(left: &str, right: &str) -> bool {
+    match (TraceEntry::parse(left), TraceEntry::parse(right)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
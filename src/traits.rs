@@ -1,25 +1,72 @@
-pub trait FillableVector<T>
-where
-    T: Copy,
-{
-    fn with_filled_capacity(size: usize, value: T) -> Vec<T>;
-    fn fill(&mut self, limit: usize, value: T);
+use core::fmt;
+
+/// Source of the random bytes consumed by CXNN.
+pub trait RandomSource {
+    fn random_byte(&mut self) -> u8;
 }
 
-impl<T> FillableVector<T> for Vec<T>
-where
-    T: Copy,
-{
-    fn with_filled_capacity(size: usize, value: T) -> Vec<T> {
-        let mut filled_vec: Vec<T> = Vec::with_capacity(size);
-        filled_vec.fill(size, value);
+/// Receives the diagnostic messages the interpreter emits, like the size of a
+/// freshly loaded ROM.
+pub trait Logger {
+    fn log(&mut self, _message: fmt::Arguments) {}
+}
 
-        filled_vec
-    }
+/// The CHIP8 only has a single tone buzzer, which sounds for as long as the
+/// sound timer is non-zero.
+pub trait Audio {
+    fn set_buzzer(&mut self, _on: bool) {}
+}
 
-    fn fill(&mut self, limit: usize, value: T) {
-        for _ in 0..limit {
-            self.push(value);
-        }
-    }
+/// Something the running program did, for hosts to react to rather than poll
+/// for, see [`Observer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuEvent {
+    /// 00E0.
+    ScreenCleared,
+    /// DXYN, with VX and VY, and whether a lit pixel was turned off.
+    SpriteDrawn {
+        x: u8,
+        y: u8,
+        height: u8,
+        address: u16,
+        collision: bool,
+    },
+    /// The sound timer went from zero to running, or back.
+    SoundStarted,
+    SoundStopped,
+    /// FX0A found no key, and execution stops there until there is one.
+    KeyWaitStarted {
+        register: u8,
+    },
+    /// FX0A got `key`, and execution carries on.
+    KeyWaitEnded {
+        register: u8,
+        key: u8,
+    },
+    /// 2NNN, calling `address`.
+    SubroutineCalled {
+        address: u16,
+        return_address: u16,
+    },
+    /// 00EE, returning to `address`.
+    SubroutineReturned {
+        address: u16,
+    },
+    /// The CPU reached an opcode it doesn't know at `address`, and halted
+    /// there, see [`Chip8CPU::is_halted`](crate::chip8_cpu::Chip8CPU::is_halted).
+    Halted {
+        address: u16,
+        opcode: u16,
+    },
 }
+
+/// Receives [`CpuEvent`]s as the instructions causing them execute.
+pub trait Observer {
+    fn on_event(&mut self, _event: CpuEvent) {}
+}
+
+/// Everything the interpreter core needs from the machine it runs on. Anything
+/// implementing the individual traits is a platform.
+pub trait Platform: RandomSource + Logger + Audio + Observer {}
+
+impl<T> Platform for T where T: RandomSource + Logger + Audio + Observer {}
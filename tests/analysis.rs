@@ -0,0 +1,94 @@
+use chip8_wasm::analysis::{analyze_rom, ByteKind, EdgeKind, Region};
+
+const ROM: [u8; 0x18] = [
+    0xA2, 0x10, // 200: I = 0x210
+    0xD0, 0x15, // 202: Draw 5 rows
+    0x22, 0x16, // 204: Call 0x216
+    0x30, 0x00, // 206: Skip if V0 == 0
+    0x12, 0x06, // 208: Jump to 0x206
+    0x12, 0x0A, // 20A: Jump to itself
+    0x60, 0x01, // 20C: V0 = 1, never reached
+    0x61, 0x02, // 20E: V1 = 2, never reached
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 210: Sprite
+    0x00, // 215: Padding
+    0x00, 0xEE, // 216: Return
+];
+
+#[test]
+fn finds_blocks_subroutines_and_sprites() {
+    let analysis = analyze_rom(&ROM);
+
+    let blocks: Vec<(u16, u16)> = analysis
+        .blocks
+        .iter()
+        .map(|block| (block.start, block.end))
+        .collect();
+    assert_eq!(
+        blocks,
+        vec![
+            (0x200, 0x206),
+            (0x206, 0x208),
+            (0x208, 0x20A),
+            (0x20A, 0x20C),
+            (0x216, 0x218)
+        ]
+    );
+    assert_eq!(analysis.blocks[0].successors[0].kind, EdgeKind::Call);
+    assert_eq!(analysis.blocks[1].successors[1].target, 0x20A);
+    assert!(analysis.blocks[4].successors.is_empty());
+
+    assert_eq!(analysis.subroutines.len(), 1);
+    assert_eq!(analysis.subroutines[0].address, 0x216);
+    assert_eq!(analysis.subroutines[0].callers, vec![0x204]);
+
+    let kinds: Vec<(u16, u16, ByteKind)> = analysis
+        .regions()
+        .iter()
+        .map(|region| (region.start, region.end, region.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (0x200, 0x20C, ByteKind::Code),
+            (0x20C, 0x210, ByteKind::Unknown),
+            (0x210, 0x215, ByteKind::Sprite),
+            (0x215, 0x216, ByteKind::Unknown),
+            (0x216, 0x218, ByteKind::Code),
+        ]
+    );
+    assert_eq!(
+        analysis.unreachable_code,
+        vec![Region {
+            start: 0x20C,
+            end: 0x210,
+            kind: ByteKind::Unknown
+        }]
+    );
+
+    let json = analysis.to_json();
+    assert!(json.contains("\"subroutines\":[{\"address\":534,\"callers\":[516]}]"));
+    let dot = analysis.to_dot();
+    assert!(dot.contains("\"200\" -> \"216\" [style=dashed, label=\"call\"];"));
+    assert!(dot.contains("\"216\" [label=\"216: 00EE\\l\", peripheries=2];"));
+}
+
+#[test]
+fn flags_computed_jumps() {
+    let analysis = analyze_rom(&[0xB3, 0x00, 0x12, 0x00]);
+
+    assert_eq!(analysis.computed_jumps, vec![0x200]);
+    assert_eq!(analysis.byte_kind(0x202), Some(ByteKind::Unknown));
+    assert_eq!(analysis.byte_kind(0x204), None);
+}
+
+#[test]
+fn analyzes_the_bundled_games() {
+    let analysis = analyze_rom(include_bytes!("../src/games/PONG"));
+
+    assert_eq!(analysis.blocks[0].start, 0x200);
+    assert!(!analysis.subroutines.is_empty());
+    assert!(analysis
+        .regions()
+        .iter()
+        .any(|region| region.kind == ByteKind::Sprite));
+}
@@ -0,0 +1,131 @@
+use chip8_wasm::archive::{load_zip, read_zip, ArchiveError};
+use chip8_wasm::chip8_cpu::MAX_ROM_SIZE;
+use chip8_wasm::crc32::crc32;
+use chip8_wasm::library::{GameLibrary, GameMetadata, GamePlatform};
+use std::convert::TryInto;
+
+const ARCHIVE: &[u8] = include_bytes!("data/homebrew.zip");
+
+#[test]
+fn loads_the_roms_described_by_the_manifest() {
+    let mut library = GameLibrary::new();
+    let names = load_zip(&mut library, ARCHIVE).unwrap();
+    assert_eq!(names, ["Maze", "extra"]);
+
+    let maze = library.get("Maze").unwrap();
+    assert_eq!(maze.rom, include_bytes!("data/maze.ch8"));
+    assert_eq!(maze.metadata.author, "Someone");
+    assert_eq!(maze.metadata.year, Some(2026));
+    assert_eq!(maze.metadata.tags, ["puzzle"]);
+    assert_eq!(maze.metadata.instructions_per_frame, Some(15));
+    assert_eq!(maze.metadata.key_help, "4: Left, 5: Jump, 6: Right");
+    let quirks = maze.metadata.quirks.unwrap();
+    assert!(quirks.shift_uses_vy && quirks.vf_reset && !quirks.jump_uses_vx);
+
+    // Not in the manifest, so named after the file.
+    let extra = library.get("extra").unwrap();
+    assert_eq!(extra.rom, include_bytes!("data/extra.ch8"));
+    assert_eq!(extra.metadata.platform, GamePlatform::Chip8);
+
+    // The text file is read, and checked, but isn't a ROM.
+    let files = read_zip(ARCHIVE).unwrap();
+    let readme = files.iter().find(|file| file.path == "README.txt").unwrap();
+    assert!(String::from_utf8_lossy(&readme.data).starts_with("This week's homebrew builds."));
+}
+
+#[test]
+fn adds_nothing_when_any_game_fails() {
+    let mut library = GameLibrary::new();
+    library
+        .add("Maze", vec![0x00, 0xE0], GameMetadata::default())
+        .unwrap();
+    assert_eq!(
+        load_zip(&mut library, ARCHIVE),
+        Err(ArchiveError::InvalidName("Maze".to_string()))
+    );
+    assert!(library.get("extra").is_none());
+
+    let mut corrupt = ARCHIVE.to_vec();
+    let rom = corrupt
+        .windows(4)
+        .position(|bytes| bytes == [0xA2, 0x30, 0x60, 0x00]);
+    corrupt[rom.unwrap() + 1] ^= 0xFF;
+    assert_eq!(
+        load_zip(&mut GameLibrary::new(), &corrupt),
+        Err(ArchiveError::ChecksumMismatch("extra.ch8".to_string()))
+    );
+
+    let small = [0x00, 0xE0];
+    let large = vec![0x00; MAX_ROM_SIZE + 1];
+    let archive = stored_zip(&[("small.ch8", &small), ("large.ch8", &large)]);
+    assert_eq!(
+        load_zip(&mut library, &archive),
+        Err(ArchiveError::RomTooLarge("large.ch8".to_string()))
+    );
+    assert!(library.get("small").is_none());
+
+    // Other files can be larger, as long as they aren't ROMs.
+    let archive = stored_zip(&[("small.ch8", &small), ("notes.txt", &large)]);
+    assert_eq!(load_zip(&mut library, &archive).unwrap(), ["small"]);
+}
+
+/// A zip archive of uncompressed files.
+fn stored_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (path, data) in files {
+        let mut header = Vec::new();
+        header.extend_from_slice(&[0, 0, 0, 0]); // Flags and method.
+        header.extend_from_slice(&[0, 0, 0, 0]); // Time and date.
+        header.extend_from_slice(&crc32(data).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(path.len() as u16).to_le_bytes());
+
+        directory.extend_from_slice(&0x0201_4B50u32.to_le_bytes());
+        directory.extend_from_slice(&[20, 0, 20, 0]);
+        directory.extend_from_slice(&header);
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&(archive.len() as u32).to_le_bytes());
+        directory.extend_from_slice(path.as_bytes());
+
+        archive.extend_from_slice(&0x0403_4B50u32.to_le_bytes());
+        archive.extend_from_slice(&[20, 0]);
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(&[0, 0]);
+        archive.extend_from_slice(path.as_bytes());
+        archive.extend_from_slice(data);
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend_from_slice(&directory);
+    archive.extend_from_slice(&0x0605_4B50u32.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]);
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&directory_offset.to_le_bytes());
+    archive.extend_from_slice(&[0, 0]);
+
+    archive
+}
+
+#[test]
+fn refuses_offsets_beyond_the_archive() {
+    let archive = stored_zip(&[("small.ch8", &[0x00, 0xE0])]);
+    let end = archive.len() - 22;
+
+    let mut far_directory = archive.clone();
+    far_directory[end + 16..end + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(read_zip(&far_directory), Err(ArchiveError::Truncated));
+
+    // The local header offset, in the only central directory header.
+    let directory = u32::from_le_bytes(archive[end + 16..end + 20].try_into().unwrap()) as usize;
+    let mut far_file = archive.clone();
+    far_file[directory + 42..directory + 46].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(read_zip(&far_file), Err(ArchiveError::Truncated));
+
+    let mut long_name = archive;
+    long_name[directory + 28..directory + 30].copy_from_slice(&u16::MAX.to_le_bytes());
+    assert_eq!(read_zip(&long_name), Err(ArchiveError::Truncated));
+}
@@ -0,0 +1,82 @@
+use chip8_wasm::block_cache::BlockEngine;
+use chip8_wasm::chip8_cpu::{Chip8CPU, STATE_SIZE};
+
+fn saved_state(cpu: &Chip8CPU) -> Vec<u8> {
+    let mut state = vec![0; STATE_SIZE];
+    cpu.save_state(&mut state).unwrap();
+
+    state
+}
+
+#[test]
+fn matches_the_interpreter_on_self_modifying_code() {
+    let mut rom = vec![
+        0x60, 0x73, // LD V0, 0x73
+        0x61, 0x05, // LD V1, 0x05
+        0x22, 0x20, // CALL 0x220
+        0x22, 0x20, // CALL 0x220, now starting with ADD V3, 5
+        0x64, 0xC8, // LD V4, 200
+        0xA2, 0x1F, // LD I, 0x21F
+        0xF4, 0x33, // LD B, V4, turning 0x220 into CLS
+        0x22, 0x20, // CALL 0x220
+        0x22, 0x20, // CALL 0x220
+        0xFF, 0xFF, // Unknown, to stop
+    ];
+    rom.resize(0x20, 0);
+    rom.extend_from_slice(&[
+        0x72, 0x01, // ADD V2, 1
+        0xA2, 0x20, // LD I, 0x220
+        0xF1, 0x55, // LD [I], V1, overwriting the first instruction
+        0x00, 0xEE, // RET
+    ]);
+
+    let mut interpreter = Chip8CPU::new();
+    interpreter.load_rom(&rom).unwrap();
+    let mut cached = Chip8CPU::new();
+    cached.load_rom(&rom).unwrap();
+    let mut engine = BlockEngine::new();
+
+    let mut cycles = 0;
+    while !interpreter.is_halted() {
+        interpreter.cycle();
+        engine.cycle(&mut cached);
+        cycles += 1;
+        assert_eq!(
+            saved_state(&cached),
+            saved_state(&interpreter),
+            "Cycle {}",
+            cycles
+        );
+    }
+    assert!(cached.is_halted());
+
+    // The rewritten subroutine ran once as CLS and twice as ADD V3, 5.
+    assert_eq!(cached.get_registers()[2..4], [1, 10]);
+    assert_eq!(cached.get_program_counter(), 0x212);
+}
+
+#[test]
+fn drops_blocks_over_written_memory() {
+    let rom = [
+        0x60, 0x01, // LD V0, 1
+        0x61, 0x02, // LD V1, 2
+        0x00, 0xEE, // RET, ending the block
+    ];
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(&rom).unwrap();
+    let mut engine = BlockEngine::new();
+    engine.cycle(&mut cpu);
+    assert_eq!(engine.len(), 1);
+
+    // Writes next to the block leave it alone.
+    engine.invalidate_range(0x206, 2);
+    engine.invalidate_range(0x1F0, 0x10);
+    assert_eq!(engine.len(), 1);
+    engine.invalidate_range(0x1FF, 2);
+    assert!(engine.is_empty());
+
+    // The block is decoded again from what's in memory now.
+    cpu.poke(0x203, 0x09).unwrap();
+    engine.cycle(&mut cpu);
+    assert_eq!(cpu.get_registers()[..2], [1, 9]);
+}
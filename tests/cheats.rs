@@ -0,0 +1,64 @@
+use chip8_wasm::cheats::{Cheat, CheatError, CheatKind, CheatList, RamSearch, SearchRelation};
+use chip8_wasm::chip8_cpu::Chip8CPU;
+
+#[test]
+fn narrows_down_a_counter() {
+    let mut cpu = Chip8CPU::new();
+    cpu.write_range(0x300, &[3, 3]).unwrap();
+    let mut search = RamSearch::new(cpu.get_memory());
+
+    // 0x300 counts down, 0x301 stays put.
+    cpu.poke(0x300, 2).unwrap();
+    search.filter(cpu.get_memory(), SearchRelation::Decreased);
+    assert_eq!(search.get_candidates(), [0x300]);
+
+    cpu.write_range(0x300, &[2, 1]).unwrap();
+    assert_eq!(
+        search.filter(cpu.get_memory(), SearchRelation::EqualTo(2)),
+        1
+    );
+    assert_eq!(search.filter(cpu.get_memory(), SearchRelation::Changed), 0);
+}
+
+#[test]
+fn freezes_and_sets_memory() {
+    let mut cpu = Chip8CPU::new();
+    let mut cheats = CheatList::new(0x1234);
+    cheats
+        .add(Cheat::new(CheatKind::Freeze, 0x300, 9, "Lives"))
+        .unwrap();
+    cheats
+        .add(Cheat::new(CheatKind::Set, 0x301, 5, ""))
+        .unwrap();
+    assert_eq!(
+        cheats.add(Cheat::new(CheatKind::Set, 0x1000, 0, "")),
+        Err(CheatError::AddressOutOfRange(0x1000))
+    );
+
+    cheats.apply(&mut cpu);
+    cpu.write_range(0x300, &[0, 0]).unwrap();
+    cheats.apply(&mut cpu);
+    assert_eq!(cpu.read_range(0x300, 2), Ok(&[9, 0][..]));
+}
+
+#[test]
+fn round_trips_cheat_files() {
+    let text = "# Comment\nrom 00001234\nfreeze 2F0 03 Infinite lives\n-set 2F4 09\n";
+    let cheats = CheatList::parse(text).unwrap();
+    assert_eq!(cheats.rom_crc32, 0x1234);
+    assert_eq!(cheats.cheats[0].description, "Infinite lives");
+    assert!(!cheats.cheats[1].enabled);
+    assert_eq!(
+        cheats.to_text(),
+        "rom 00001234\nfreeze 2F0 03 Infinite lives\n-set 2F4 09\n"
+    );
+
+    assert_eq!(
+        CheatList::parse("freeze 2F0 03"),
+        Err(CheatError::MissingRom)
+    );
+    assert_eq!(
+        CheatList::parse("rom 1234\nfreeze 2F0"),
+        Err(CheatError::InvalidLine(2))
+    );
+}
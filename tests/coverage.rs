@@ -0,0 +1,50 @@
+use chip8_wasm::chip8_cpu::Chip8CPU;
+use chip8_wasm::coverage::MemoryAccess;
+
+const ROM: [u8; 9] = [
+    0xA2, 0x08, // 200: I = 0x208
+    0xD0, 0x11, // 202: Draw 1 row
+    0xF0, 0x33, // 204: Store V0 as BCD at I
+    0xF1, 0x65, // 206: Load V0 and V1 from I
+    0xF0, // 208: Sprite
+];
+
+#[test]
+fn counts_fetches_reads_and_writes() {
+    let mut cpu = Chip8CPU::new();
+    cpu.enable_coverage();
+    cpu.load_rom(&ROM).unwrap();
+
+    (0..4).for_each(|_| cpu.cycle());
+
+    let coverage = cpu.get_coverage().unwrap();
+    let fetched: Vec<usize> = (0x200..0x20A)
+        .filter(|address| coverage.get_count(MemoryAccess::Fetch, *address) > 0)
+        .collect();
+    assert_eq!(fetched, (0x200..0x208).collect::<Vec<usize>>());
+    // The sprite row, then the two bytes FX65 loads.
+    assert_eq!(
+        coverage.get_counts(MemoryAccess::Read)[0x207..0x20B],
+        [0, 2, 1, 0]
+    );
+    assert_eq!(
+        coverage.get_counts(MemoryAccess::Read).iter().sum::<u32>(),
+        3
+    );
+    assert_eq!(
+        coverage.get_counts(MemoryAccess::Write)[0x208..0x20C],
+        [1, 1, 1, 0]
+    );
+    assert_eq!(
+        coverage.get_counts(MemoryAccess::Write).iter().sum::<u32>(),
+        3
+    );
+
+    let dump = coverage.annotated_dump(&ROM);
+    assert!(dump.starts_with("; 200-208: 8 bytes fetched, 1 read, 1 written of 9\n"));
+    assert!(dump.contains("\n202   D011          1         0         0\n"));
+    assert!(dump.contains("\n208   F0            0         2         1\n"));
+
+    cpu.disable_coverage();
+    assert!(cpu.get_coverage().is_none());
+}
@@ -0,0 +1,154 @@
+use chip8_wasm::chip8_cpu::{Chip8CPU, KeyWait, STATE_SIZE};
+use chip8_wasm::quirks::Quirks;
+use chip8_wasm::reference::Differential;
+use chip8_wasm::traits::{Audio, CpuEvent, Logger, Observer, RandomSource};
+
+#[derive(Default)]
+struct RecordingPlatform {
+    events: Vec<CpuEvent>,
+}
+
+impl RandomSource for RecordingPlatform {
+    fn random_byte(&mut self) -> u8 {
+        0
+    }
+}
+
+impl Logger for RecordingPlatform {}
+
+impl Audio for RecordingPlatform {}
+
+impl Observer for RecordingPlatform {
+    fn on_event(&mut self, event: CpuEvent) {
+        self.events.push(event);
+    }
+}
+
+#[test]
+fn reports_what_the_program_does() {
+    let rom = [
+        0x00, 0xE0, // CLS
+        0x22, 0x08, // CALL 0x208
+        0xF0, 0x0A, // LD V0, K
+        0x00, 0x00, // Padding
+        0x61, 0x02, // LD V1, 2
+        0xF1, 0x18, // LD ST, V1
+        0xD0, 0x15, // DRW V0, V1, 5
+        0x00, 0xEE, // RET
+    ];
+    let mut cpu = Chip8CPU::with_platform(RecordingPlatform::default());
+    cpu.load_rom(&rom).unwrap();
+
+    (0..6).for_each(|_| cpu.cycle());
+    assert!(cpu.draw_flag);
+    // Waiting is only reported the first time round.
+    (0..3).for_each(|_| cpu.cycle());
+    cpu.set_key(7, true);
+    cpu.cycle();
+    assert_eq!(cpu.get_program_counter(), 0x206);
+
+    assert_eq!(
+        cpu.platform().events,
+        [
+            CpuEvent::ScreenCleared,
+            CpuEvent::SubroutineCalled {
+                address: 0x208,
+                return_address: 0x204,
+            },
+            CpuEvent::SoundStarted,
+            CpuEvent::SpriteDrawn {
+                x: 0,
+                y: 2,
+                height: 5,
+                address: 0,
+                collision: false,
+            },
+            CpuEvent::SoundStopped,
+            CpuEvent::SubroutineReturned { address: 0x204 },
+            CpuEvent::KeyWaitStarted { register: 0 },
+            CpuEvent::KeyWaitEnded {
+                register: 0,
+                key: 7,
+            },
+        ]
+    );
+}
+
+#[test]
+fn waits_for_a_key_to_be_pressed_and_released() {
+    // LD V0, K; LD ST, V0 then LD V1, K.
+    let rom = [0xF0, 0x0A, 0xF0, 0x18, 0xF1, 0x0A];
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(&rom).unwrap();
+    cpu.set_quirks(Quirks {
+        key_wait_release: true,
+        ..Quirks::new()
+    });
+    cpu.set_delay_timer(10);
+    let mut differential = Differential::new(&cpu);
+
+    differential.run(&mut cpu, 3).unwrap();
+    assert!(cpu.is_waiting_for_key());
+    assert_eq!(cpu.get_delay_timer(), 7);
+    cpu.set_key(0xB, true);
+    differential.run(&mut cpu, 2).unwrap();
+    assert_eq!(cpu.get_key_wait(), KeyWait::Release(0xB));
+
+    // The wait survives saving and loading.
+    let mut state = vec![0; STATE_SIZE];
+    cpu.save_state(&mut state).unwrap();
+    let mut restored = Chip8CPU::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.get_key_wait(), KeyWait::Release(0xB));
+
+    cpu.set_key(0xB, false);
+    differential.run(&mut cpu, 2).unwrap();
+    assert!(!cpu.is_waiting_for_key());
+    assert_eq!(cpu.get_registers()[0], 0xB);
+    assert_eq!(cpu.get_program_counter(), 0x204);
+
+    // Without the quirk a key that's down is taken straight away.
+    cpu.set_quirks(Quirks {
+        key_wait_release: false,
+        ..Quirks::new()
+    });
+    let mut differential = Differential::new(&cpu);
+    cpu.set_key(0x3, true);
+    differential.run(&mut cpu, 1).unwrap();
+    assert_eq!(cpu.get_registers()[1], 0x3);
+    assert_eq!(cpu.get_program_counter(), 0x206);
+}
+
+#[test]
+fn halts_at_an_unknown_opcode() {
+    let rom = [
+        0x60, 0x05, // LD V0, 5
+        0xF0, 0x15, // LD DT, V0
+        0xF0, 0xFF, // Unknown
+        0x61, 0x01, // LD V1, 1
+    ];
+    let mut cpu = Chip8CPU::with_platform(RecordingPlatform::default());
+    cpu.load_rom(&rom).unwrap();
+    let mut differential = Differential::new(&cpu);
+
+    // The reference stops in the same place, timers and all.
+    differential.run(&mut cpu, 10).unwrap();
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.get_program_counter(), 0x204);
+    assert_eq!(cpu.get_delay_timer(), 4);
+    assert_eq!(cpu.get_registers()[1], 0);
+    assert_eq!(
+        cpu.platform().events,
+        [CpuEvent::Halted {
+            address: 0x204,
+            opcode: 0xF0FF,
+        }]
+    );
+
+    // Moving past it gets it running again.
+    cpu.set_program_counter(0x206).unwrap();
+    assert!(!cpu.is_halted());
+    cpu.cycle();
+    assert_eq!(cpu.get_registers()[1], 1);
+    assert_eq!(cpu.platform().events.len(), 1);
+}
@@ -0,0 +1,76 @@
+use chip8_wasm::crc32::crc32;
+use chip8_wasm::export::{ExportError, MovieExporter};
+use chip8_wasm::image::MONOCHROME;
+use chip8_wasm::movie::{Movie, MovieError};
+use chip8_wasm::quirks::Quirks;
+
+/// Sounds the buzzer for 15 cycles, then clears the screen.
+const BEEP: &[u8] = &[
+    0x60, 0x0F, // V0 = 15
+    0xF0, 0x18, // ST = V0
+];
+
+fn beep_movie(frame_count: u64) -> Movie {
+    Movie {
+        rom_crc32: crc32(BEEP),
+        quirks: Quirks::default(),
+        rng_seed: 7,
+        instructions_per_frame: 10,
+        emulator_version: "test".to_string(),
+        frame_count,
+        events: Vec::new(),
+    }
+}
+
+fn export(scale: u32, sample_rate: u32) -> (Vec<u8>, Vec<u8>) {
+    let mut exporter =
+        MovieExporter::new(BEEP, beep_movie(3), scale, &MONOCHROME, sample_rate).unwrap();
+    let mut video = exporter.video_header();
+    let mut audio = exporter.audio_header();
+    let mut audio_frame_sizes = Vec::new();
+    while let Some((video_frame, audio_frame)) = exporter.next_frame() {
+        assert_eq!(
+            video_frame.len(),
+            6 + 64 * 32 * scale as usize * scale as usize * 3
+        );
+        video.extend(video_frame);
+        audio_frame_sizes.push(audio_frame.len() / 2);
+        audio.extend(audio_frame);
+    }
+    // 22050 Hz is 367.5 samples a frame.
+    assert_eq!(audio_frame_sizes, [367, 368, 367]);
+
+    (video, audio)
+}
+
+#[test]
+fn exports_the_same_files_every_time() {
+    let (video, audio) = export(2, 22050);
+    assert!(video.starts_with(b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444 XCOLORRANGE=FULL\nFRAME\n"));
+    assert_eq!(&audio[..4], b"RIFF");
+    assert_eq!(&audio[8..16], b"WAVEfmt ");
+    assert_eq!(
+        u32::from_le_bytes([audio[24], audio[25], audio[26], audio[27]]),
+        22050
+    );
+    let data_size = u32::from_le_bytes([audio[40], audio[41], audio[42], audio[43]]);
+    assert_eq!(data_size as usize, 1102 * 2);
+    assert_eq!(audio.len(), 44 + data_size as usize);
+    assert!(audio[44..].iter().any(|byte| *byte != 0));
+
+    assert_eq!(export(2, 22050), (video, audio));
+}
+
+#[test]
+fn refuses_movies_of_other_roms() {
+    let mut movie = beep_movie(3);
+    movie.rom_crc32 ^= 1;
+    assert!(matches!(
+        MovieExporter::new(BEEP, movie, 1, &[], 48000),
+        Err(ExportError::InvalidMovie(MovieError::RomMismatch { .. }))
+    ));
+    assert!(matches!(
+        MovieExporter::new(BEEP, beep_movie(3), 1, &[], 10),
+        Err(ExportError::InvalidSampleRate)
+    ));
+}
@@ -0,0 +1,153 @@
+use chip8_wasm::gif::GifRecorder;
+use chip8_wasm::image::{Bitmap, ImageError, MONOCHROME};
+
+struct Image {
+    delay: u16,
+    area: [u16; 4],
+    pixels: Vec<u8>,
+}
+
+/// The images in a GIF with a global colour table of `colours` colours.
+fn decode_gif(gif: &[u8], colours: usize) -> Vec<Image> {
+    assert_eq!(&gif[..6], b"GIF89a");
+    let mut offset = 13 + colours * 3;
+    let mut images = Vec::new();
+    let mut delay = 0;
+    let read_u16 = |offset: usize| u16::from_le_bytes([gif[offset], gif[offset + 1]]);
+    let read_blocks = |offset: &mut usize| {
+        let mut data = Vec::new();
+        while gif[*offset] != 0 {
+            let length = gif[*offset] as usize;
+            data.extend_from_slice(&gif[*offset + 1..*offset + 1 + length]);
+            *offset += 1 + length;
+        }
+        *offset += 1;
+        data
+    };
+
+    loop {
+        match gif[offset] {
+            0x21 => {
+                if gif[offset + 1] == 0xF9 {
+                    delay = read_u16(offset + 4);
+                }
+                offset += 2;
+                read_blocks(&mut offset);
+            }
+            0x2C => {
+                let area = [1, 3, 5, 7].map(|field| read_u16(offset + field));
+                let min_code_size = gif[offset + 10];
+                offset += 11;
+                let pixels = lzw_decode(min_code_size, &read_blocks(&mut offset));
+                assert_eq!(pixels.len(), area[2] as usize * area[3] as usize);
+                images.push(Image {
+                    delay,
+                    area,
+                    pixels,
+                });
+            }
+            0x3B => return images,
+            other => panic!("Unexpected block {:02X}", other),
+        }
+    }
+}
+
+fn lzw_decode(min_code_size: u8, data: &[u8]) -> Vec<u8> {
+    let clear_code = 1usize << min_code_size;
+    let initial_table =
+        || -> Vec<Vec<u8>> { (0..clear_code + 2).map(|code| vec![code as u8]).collect() };
+    let mut table = initial_table();
+    let mut code_size = min_code_size + 1;
+    let mut bit = 0;
+    let mut previous: Option<Vec<u8>> = None;
+    let mut pixels = Vec::new();
+
+    loop {
+        let mut code = 0;
+        for index in 0..code_size as usize {
+            let value = data[(bit + index) / 8] >> ((bit + index) % 8) & 1;
+            code |= (value as usize) << index;
+        }
+        bit += code_size as usize;
+
+        if code == clear_code {
+            table = initial_table();
+            code_size = min_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == clear_code + 1 {
+            return pixels;
+        }
+        let entry = match table.get(code) {
+            Some(entry) => entry.clone(),
+            None => {
+                let mut entry = previous.clone().unwrap();
+                entry.push(entry[0]);
+                entry
+            }
+        };
+        if let Some(mut added) = previous.take() {
+            if table.len() < 4096 {
+                added.push(entry[0]);
+                table.push(added);
+            }
+        }
+        pixels.extend_from_slice(&entry);
+        previous = Some(entry);
+        if table.len() == 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+    }
+}
+
+#[test]
+fn merges_repeated_frames_and_writes_only_changes() {
+    let mut recorder = GifRecorder::new(4, 2, 2, &MONOCHROME).unwrap();
+    let blank = Bitmap::new(4, 2);
+    let mut lit = blank.clone();
+    lit.set(2, 1, 1);
+
+    // 6 frames of nothing is 1/10 of a second, then 3 with a pixel lit.
+    let mut gif = Vec::new();
+    for _ in 0..6 {
+        recorder.add_frame(&blank);
+    }
+    gif.extend(recorder.take_output());
+    for _ in 0..3 {
+        recorder.add_frame(&lit);
+    }
+    assert_eq!(recorder.get_frame_count(), 9);
+    gif.extend(recorder.finish());
+
+    let images = decode_gif(&gif, 2);
+    assert_eq!(images.len(), 2);
+    assert_eq!(images[0].delay, 10);
+    assert_eq!(images[0].area, [0, 0, 8, 4]);
+    assert_eq!(images[0].pixels, vec![0; 32]);
+    assert_eq!(images[1].delay, 6);
+    assert_eq!(images[1].area, [4, 2, 2, 2]);
+    assert_eq!(images[1].pixels, [1, 1, 1, 1]);
+}
+
+#[test]
+fn compresses_frames_that_fill_the_code_table() {
+    let mut noise = Bitmap::new(64, 32);
+    let mut state = 1u32;
+    for pixel in noise.pixels.iter_mut() {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        *pixel = (state >> 16) as u8 % 3;
+    }
+
+    let palette = [[0, 0, 0], [0xFF, 0, 0], [0, 0xFF, 0]];
+    let mut recorder = GifRecorder::new(64, 32, 3, &palette).unwrap();
+    recorder.add_frame(&noise);
+    let images = decode_gif(&recorder.finish(), 4);
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].pixels, noise.scale(3).pixels);
+
+    assert!(matches!(
+        GifRecorder::new(64, 32, 0, &[]),
+        Err(ImageError::InvalidScale)
+    ));
+}
@@ -0,0 +1,77 @@
+use chip8_wasm::chip8_cpu::{
+    AccessError, Chip8CPU, RomError, MAX_ROM_SIZE, MEMORY_SIZE, STACK_SIZE,
+};
+
+#[test]
+fn reads_and_writes_memory_within_bounds() {
+    let mut cpu = Chip8CPU::new();
+    let generation = cpu.get_generation();
+
+    cpu.poke(0x300, 0xAB).unwrap();
+    cpu.write_range(0x301, &[1, 2, 3]).unwrap();
+    assert_eq!(cpu.peek(0x300), Ok(0xAB));
+    assert_eq!(cpu.read_range(0x300, 4), Ok(&[0xAB, 1, 2, 3][..]));
+    assert_eq!(cpu.get_generation(), generation + 2);
+
+    assert_eq!(
+        cpu.peek(MEMORY_SIZE),
+        Err(AccessError::AddressOutOfRange(MEMORY_SIZE))
+    );
+    assert_eq!(
+        cpu.write_range(MEMORY_SIZE - 1, &[1, 2]),
+        Err(AccessError::AddressOutOfRange(MEMORY_SIZE + 1))
+    );
+    assert_eq!(cpu.peek(MEMORY_SIZE - 1), Ok(0));
+    assert_eq!(cpu.get_generation(), generation + 2);
+
+    let rom = vec![0xAA; MAX_ROM_SIZE + 1];
+    assert_eq!(
+        cpu.load_rom(&rom),
+        Err(RomError::TooLarge(MAX_ROM_SIZE + 1))
+    );
+    assert_eq!(cpu.peek(0x300), Ok(0xAB));
+    // A ROM filling the rest of memory exactly still fits.
+    cpu.load_rom(&rom[..MAX_ROM_SIZE]).unwrap();
+    assert_eq!(cpu.peek(MEMORY_SIZE - 1), Ok(0xAA));
+}
+
+#[test]
+fn edits_registers_and_the_stack() {
+    let mut cpu = Chip8CPU::new();
+
+    cpu.set_register(0xF, 7).unwrap();
+    cpu.set_index_register(0x123).unwrap();
+    cpu.set_program_counter(0x200).unwrap();
+    cpu.set_stack_entry(0, 0x208).unwrap();
+    cpu.set_stack_pointer(1).unwrap();
+    cpu.set_delay_timer(3);
+    assert_eq!(cpu.get_registers()[0xF], 7);
+    assert_eq!(cpu.get_index_register(), 0x123);
+    assert_eq!(cpu.get_program_counter(), 0x200);
+    assert_eq!(cpu.get_stack()[0], 0x208);
+    assert_eq!(cpu.get_stack_pointer(), 1);
+    assert_eq!(cpu.get_delay_timer(), 3);
+
+    assert_eq!(
+        cpu.set_register(16, 0),
+        Err(AccessError::RegisterOutOfRange(16))
+    );
+    assert_eq!(
+        cpu.set_program_counter(0xFFF),
+        Err(AccessError::AddressOutOfRange(0xFFF))
+    );
+    assert_eq!(
+        cpu.set_stack_entry(STACK_SIZE, 0),
+        Err(AccessError::StackOutOfRange(STACK_SIZE))
+    );
+    assert_eq!(
+        cpu.set_stack_pointer(STACK_SIZE as u8 + 1),
+        Err(AccessError::StackOutOfRange(STACK_SIZE + 1))
+    );
+
+    // Running an instruction moves the generation on too.
+    let generation = cpu.get_generation();
+    cpu.write_range(0x200, &[0x60, 0x01]).unwrap();
+    cpu.cycle();
+    assert_eq!(cpu.get_generation(), generation + 2);
+}
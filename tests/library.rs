@@ -0,0 +1,83 @@
+use chip8_wasm::chip8_cpu::MAX_ROM_SIZE;
+use chip8_wasm::library::{GameLibrary, GameMetadata, GamePlatform, LibraryError};
+
+fn tagged(tags: &[&str]) -> GameMetadata {
+    GameMetadata {
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        ..GameMetadata::default()
+    }
+}
+
+#[test]
+fn keeps_games_sorted_through_changes() {
+    let mut library = GameLibrary::new();
+    library.add("TANK", vec![1], tagged(&["action"])).unwrap();
+    library.add("PONG", vec![2], tagged(&["sports"])).unwrap();
+    library
+        .add("BLITZ", vec![3], GameMetadata::default())
+        .unwrap();
+    assert_eq!(
+        library.names().collect::<Vec<_>>(),
+        ["BLITZ", "PONG", "TANK"]
+    );
+
+    assert_eq!(
+        library.add("PONG", vec![4], GameMetadata::default()),
+        Err(LibraryError::NameTaken)
+    );
+    assert_eq!(
+        library.rename("BLITZ", "TANK"),
+        Err(LibraryError::NameTaken)
+    );
+    library.rename("BLITZ", "ZAP").unwrap();
+    assert_eq!(library.remove("PONG").unwrap().rom, [2]);
+    assert_eq!(library.remove("PONG"), Err(LibraryError::UnknownGame));
+    assert_eq!(library.names().collect::<Vec<_>>(), ["TANK", "ZAP"]);
+    assert_eq!(library.get("ZAP").unwrap().name, "ZAP");
+
+    let rom = vec![0xAA; MAX_ROM_SIZE + 1];
+    assert_eq!(
+        library.add("HUGE", rom.clone(), GameMetadata::default()),
+        Err(LibraryError::RomTooLarge(MAX_ROM_SIZE + 1))
+    );
+    assert!(library.get("HUGE").is_none());
+    library
+        .add(
+            "FULL",
+            rom[..MAX_ROM_SIZE].to_vec(),
+            GameMetadata::default(),
+        )
+        .unwrap();
+}
+
+#[test]
+fn searches_and_filters_by_tag() {
+    let mut library = GameLibrary::new();
+    library.add("PONG", vec![1], tagged(&["Sports"])).unwrap();
+    library.add("TANK", vec![2], tagged(&["action"])).unwrap();
+    library
+        .set_metadata(
+            "TANK",
+            GameMetadata {
+                platform: GamePlatform::SuperChip,
+                author: "Someone".to_string(),
+                year: Some(1991),
+                ..tagged(&["action", "shooter"])
+            },
+        )
+        .unwrap();
+
+    let names = |games: Vec<&chip8_wasm::library::Game>| {
+        games
+            .iter()
+            .map(|game| game.name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(library.with_tag("sports").collect()), ["PONG"]);
+    assert_eq!(names(library.search("someone").collect()), ["TANK"]);
+    assert_eq!(names(library.search("o").collect()), ["PONG", "TANK"]);
+    assert_eq!(
+        library.get("TANK").unwrap().metadata.platform.name(),
+        "schip"
+    );
+}
@@ -0,0 +1,143 @@
+//! Drives the libretro core through a minimal headless frontend.
+
+#![cfg(feature = "libretro")]
+
+use chip8_wasm::libretro::*;
+
+use std::ffi::{c_uint, c_void, CStr};
+use std::ptr;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Host {
+    variables: Vec<String>,
+    input_descriptors: usize,
+    pixel_format: i32,
+    video_frames: Vec<(c_uint, c_uint, usize)>,
+    audio_frames: Vec<usize>,
+    input_polls: usize,
+}
+
+static HOST: Mutex<Option<Host>> = Mutex::new(None);
+
+fn with_host<T>(f: impl FnOnce(&mut Host) -> T) -> T {
+    f(HOST.lock().unwrap().get_or_insert_with(Host::default))
+}
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_VARIABLES => {
+            let mut variable = data as *const RetroVariable;
+            while !(*variable).key.is_null() {
+                let key = CStr::from_ptr((*variable).key)
+                    .to_string_lossy()
+                    .into_owned();
+                with_host(|host| host.variables.push(key));
+                variable = variable.add(1);
+            }
+            true
+        }
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            with_host(|host| host.pixel_format = *(data as *const i32));
+            true
+        }
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS => {
+            let mut descriptor = data as *const RetroInputDescriptor;
+            while !(*descriptor).description.is_null() {
+                with_host(|host| host.input_descriptors += 1);
+                descriptor = descriptor.add(1);
+            }
+            true
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+            *(data as *mut bool) = false;
+            true
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(
+    _data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    with_host(|host| host.video_frames.push((width, height, pitch)));
+}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    with_host(|host| host.audio_frames.push(frames));
+    frames
+}
+
+unsafe extern "C" fn input_poll() {
+    with_host(|host| host.input_polls += 1);
+}
+
+unsafe extern "C" fn input_state(
+    _port: c_uint,
+    _device: c_uint,
+    _index: c_uint,
+    _id: c_uint,
+) -> i16 {
+    0
+}
+
+#[test]
+fn runs_a_game_in_a_headless_frontend() {
+    assert_eq!(retro_api_version(), RETRO_API_VERSION);
+
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    let rom = include_bytes!("../src/games/PONG");
+    let game = RetroGameInfo {
+        path: ptr::null(),
+        data: rom.as_ptr() as *const c_void,
+        size: rom.len(),
+        meta: ptr::null(),
+    };
+    assert!(unsafe { retro_load_game(&game) });
+
+    (0..3).for_each(|_| retro_run());
+
+    let mut state = vec![0u8; retro_serialize_size()];
+    assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+    retro_run();
+    assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+    assert!(!unsafe { retro_unserialize(state.as_ptr() as *const c_void, 16) });
+
+    assert_eq!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM), 4096);
+    assert!(retro_get_memory_data(0).is_null());
+    let memory = retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM) as *mut u8;
+    let program = unsafe { std::slice::from_raw_parts(memory.add(0x200), rom.len()) };
+    assert_eq!(program, &rom[..]);
+    // Resetting puts the ROM back in the same place.
+    unsafe { *memory.add(0x200) ^= 0xFF };
+    retro_reset();
+    assert_eq!(
+        retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM) as *mut u8,
+        memory
+    );
+    assert_eq!(unsafe { *memory.add(0x200) }, rom[0]);
+
+    retro_unload_game();
+    assert!(retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM).is_null());
+    assert_eq!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM), 0);
+    retro_deinit();
+
+    with_host(|host| {
+        assert!(host.variables.iter().any(|key| key == "chip8_speed"));
+        assert!(host.variables.iter().any(|key| key == "chip8_quirk_shift"));
+        assert_eq!(host.pixel_format, RETRO_PIXEL_FORMAT_XRGB8888);
+        assert_eq!(host.input_descriptors, 8);
+        assert_eq!(host.video_frames, vec![(64, 32, 256); 4]);
+        assert_eq!(host.audio_frames, vec![735; 4]);
+        assert_eq!(host.input_polls, 4);
+    });
+}
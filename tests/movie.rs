@@ -0,0 +1,179 @@
+use chip8_wasm::chip8_cpu::{Chip8CPU, STATE_SIZE};
+use chip8_wasm::crc32::crc32;
+use chip8_wasm::movie::{InputEvent, Movie, MovieError, MoviePlayer, MovieRecorder};
+use chip8_wasm::platform::DefaultPlatform;
+use chip8_wasm::quirks::Quirks;
+
+// LD V0, K then LD V1, K, stopping at an unknown opcode.
+const KEY_WAIT_ROM: [u8; 6] = [0xF0, 0x0A, 0xF1, 0x0A, 0xFF, 0xFF];
+
+/// Draws a dot somewhere random unless a random key is down, forever.
+const RANDOM_DOTS_ROM: [u8; 17] = [
+    0xA2, 0x10, // LD I, 0x210
+    0xC1, 0x3F, // RND V1, 0x3F
+    0xC2, 0x1F, // RND V2, 0x1F
+    0xC3, 0x0F, // RND V3, 0x0F
+    0xE3, 0x9E, // SKP V3
+    0xD1, 0x21, // DRW V1, V2, 1
+    0xB2, 0x00, // JP V0, 0x200
+    0x00, 0x00, // Padding
+    0x80, // Sprite
+];
+
+fn movie() -> Movie {
+    Movie {
+        rom_crc32: 0x1234_5678,
+        quirks: Quirks::new(),
+        rng_seed: 0xDEAD_BEEF,
+        instructions_per_frame: 500,
+        emulator_version: String::from("1.2.3"),
+        frame_count: 1000,
+        events: vec![
+            InputEvent {
+                frame: 0,
+                key: 0xF,
+                pressed: true,
+            },
+            InputEvent {
+                frame: 300,
+                key: 0xF,
+                pressed: false,
+            },
+        ],
+    }
+}
+
+fn play_rom(movie: Movie, rom: &[u8]) -> Chip8CPU<DefaultPlatform> {
+    let mut cpu = Chip8CPU::with_platform(DefaultPlatform::with_seed(movie.rng_seed));
+    let mut player = MoviePlayer::new(movie);
+    player.prepare(&mut cpu, rom).unwrap();
+    while player.run_frame(&mut cpu) {}
+
+    cpu
+}
+
+fn play(movie: Movie) -> Chip8CPU<DefaultPlatform> {
+    play_rom(movie, &KEY_WAIT_ROM)
+}
+
+fn saved_state(cpu: &Chip8CPU<DefaultPlatform>) -> Vec<u8> {
+    let mut state = vec![0; STATE_SIZE];
+    cpu.save_state(&mut state).unwrap();
+
+    state
+}
+
+#[test]
+fn encodes_and_decodes_movies() {
+    let bytes = movie().to_bytes().unwrap();
+    assert_eq!(Movie::from_bytes(&bytes), Ok(movie()));
+
+    // Frames since the previous event, so 300 takes two bytes.
+    assert_eq!(bytes[bytes.len() - 5..], [0x00, 0x8F, 0xAC, 0x02, 0x0F]);
+}
+
+#[test]
+fn refuses_broken_movies() {
+    let bytes = movie().to_bytes().unwrap();
+    (0..bytes.len()).for_each(|length| {
+        assert_eq!(
+            Movie::from_bytes(&bytes[..length]),
+            Err(MovieError::Truncated)
+        );
+    });
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(
+        Movie::from_bytes(&bad_magic),
+        Err(MovieError::InvalidHeader)
+    );
+    let mut new_version = bytes.clone();
+    new_version[4] = 2;
+    assert_eq!(
+        Movie::from_bytes(&new_version),
+        Err(MovieError::UnsupportedVersion(2))
+    );
+
+    // Two events each u64::MAX frames after the one before.
+    let mut overflowing = bytes[..bytes.len() - 5].to_vec();
+    (0..2).for_each(|_| {
+        overflowing.extend_from_slice(&[0xFF; 9]);
+        overflowing.extend_from_slice(&[0x01, 0x8F]);
+    });
+    assert_eq!(Movie::from_bytes(&overflowing), Err(MovieError::TooLong));
+}
+
+#[test]
+fn refuses_to_encode_what_the_format_cannot_hold() {
+    let too_fast = Movie {
+        instructions_per_frame: 0x1_0000,
+        ..movie()
+    };
+    assert_eq!(too_fast.to_bytes(), Err(MovieError::TooFast(0x1_0000)));
+
+    let too_long = Movie {
+        frame_count: 0x1_0000_0000,
+        ..movie()
+    };
+    assert_eq!(too_long.to_bytes(), Err(MovieError::TooLong));
+}
+
+#[test]
+fn plays_back_what_was_recorded() {
+    let mut cpu = Chip8CPU::with_platform(DefaultPlatform::with_seed(42));
+    cpu.load_rom(&RANDOM_DOTS_ROM).unwrap();
+    let mut recorder = MovieRecorder::new(&cpu, &RANDOM_DOTS_ROM, 42, 20);
+    for frame in 0..120 {
+        // A different handful of keys down every few frames.
+        if frame % 5 == 0 {
+            (0..16).for_each(|key| recorder.set_key(&mut cpu, key, (key * frame) % 3 == 0));
+        }
+        recorder.run_frame(&mut cpu);
+    }
+    let movie = Movie::from_bytes(&recorder.finish().to_bytes().unwrap()).unwrap();
+    assert_eq!(movie.frame_count, 120);
+
+    let replayed = play_rom(movie, &RANDOM_DOTS_ROM);
+    assert!(cpu.display.get_buffer().contains(&1));
+    assert_eq!(replayed.display.get_buffer(), cpu.display.get_buffer());
+    assert_eq!(saved_state(&replayed), saved_state(&cpu));
+}
+
+#[test]
+fn replays_movies_from_before_the_key_release_quirk() {
+    // Written before FX0A could wait for a release: quirks with only bit 1
+    // set, and key 5 pressed and held from frame 1.
+    let mut bytes = b"C8MV".to_vec();
+    bytes.push(1);
+    bytes.push(0x02);
+    bytes.extend_from_slice(&10u16.to_le_bytes());
+    bytes.extend_from_slice(&crc32(&KEY_WAIT_ROM).to_le_bytes());
+    bytes.extend_from_slice(&7u64.to_le_bytes());
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    bytes.push(5);
+    bytes.extend_from_slice(b"0.1.0");
+    bytes.extend_from_slice(&[0x01, 0x85]);
+
+    // A key that's down satisfies both waits straight away.
+    let cpu = play(Movie::from_bytes(&bytes).unwrap());
+    assert_eq!(cpu.get_registers()[..2], [5, 5]);
+    assert_eq!(cpu.get_program_counter(), 0x204);
+}
+
+#[test]
+fn records_press_only_waits_by_default() {
+    let mut cpu = Chip8CPU::with_platform(DefaultPlatform::with_seed(7));
+    cpu.load_rom(&KEY_WAIT_ROM).unwrap();
+    let mut recorder = MovieRecorder::new(&cpu, &KEY_WAIT_ROM, 7, 10);
+    recorder.run_frame(&mut cpu);
+    recorder.set_key(&mut cpu, 5, true);
+    (0..2).for_each(|_| recorder.run_frame(&mut cpu));
+    let bytes = recorder.finish().to_bytes().unwrap();
+    assert_eq!(bytes[5] & 0x10, 0);
+
+    let replayed = play(Movie::from_bytes(&bytes).unwrap());
+    assert_eq!(saved_state(&replayed), saved_state(&cpu));
+    assert_eq!(replayed.get_program_counter(), 0x204);
+}
@@ -0,0 +1,96 @@
+use chip8_wasm::chip8_cpu::MAX_ROM_SIZE;
+use chip8_wasm::crc32::crc32;
+use chip8_wasm::patch::{apply_patch, PatchError};
+
+const ROM: [u8; 8] = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x06];
+
+fn push_bps_number(bytes: &mut Vec<u8>, mut number: u64) {
+    loop {
+        let low = (number & 0x7F) as u8;
+        number >>= 7;
+        if number == 0 {
+            bytes.push(0x80 | low);
+            return;
+        }
+        bytes.push(low);
+        number -= 1;
+    }
+}
+
+/// Keeps the first 4 bytes, writes 2 new ones, copies the last 2 of the
+/// source, then repeats the new ones from the target.
+fn make_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    push_bps_number(&mut patch, source.len() as u64);
+    push_bps_number(&mut patch, target.len() as u64);
+    push_bps_number(&mut patch, 0);
+    push_bps_number(&mut patch, (4 - 1) << 2); // Source read
+    push_bps_number(&mut patch, ((2 - 1) << 2) | 1); // Target read
+    patch.extend_from_slice(&target[4..6]);
+    push_bps_number(&mut patch, ((2 - 1) << 2) | 2); // Source copy
+    push_bps_number(&mut patch, 6 << 1);
+    push_bps_number(&mut patch, ((2 - 1) << 2) | 3); // Target copy
+    push_bps_number(&mut patch, 4 << 1);
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc32 = crc32(&patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+
+    patch
+}
+
+#[test]
+fn applies_ips_records_and_runs() {
+    let mut patch = b"PATCH".to_vec();
+    patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x01, 0x09]); // 001: 09
+    patch.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x02, 0xAA]); // 008: AA AA
+    patch.extend_from_slice(b"EOF");
+
+    assert_eq!(
+        apply_patch(&ROM, &patch),
+        Ok(vec![
+            0x60, 0x09, 0x61, 0x02, 0x62, 0x03, 0x12, 0x06, 0xAA, 0xAA
+        ])
+    );
+
+    patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+    assert_eq!(apply_patch(&ROM, &patch), Ok(vec![0x60, 0x09]));
+}
+
+#[test]
+fn applies_bps_and_checks_its_checksums() {
+    let target = [0x60, 0x01, 0x61, 0x02, 0x63, 0x04, 0x12, 0x06, 0x63, 0x04];
+    let patch = make_bps(&ROM, &target);
+    assert_eq!(apply_patch(&ROM, &patch), Ok(target.to_vec()));
+
+    let mut other_rom = ROM;
+    other_rom[0] = 0x00;
+    assert!(matches!(
+        apply_patch(&other_rom, &patch),
+        Err(PatchError::SourceChecksumMismatch { .. })
+    ));
+
+    let mut corrupt = patch.clone();
+    corrupt[5] ^= 1;
+    assert!(matches!(
+        apply_patch(&ROM, &corrupt),
+        Err(PatchError::PatchChecksumMismatch { .. })
+    ));
+
+    assert_eq!(apply_patch(&ROM, b"NOPE"), Err(PatchError::UnknownFormat));
+
+    // One byte at the last address a ROM can use, then one past it.
+    let ips = |offset: usize| {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&[0x00, 0x01, 0xFF]);
+        patch.extend_from_slice(b"EOF");
+        patch
+    };
+    let patched = apply_patch(&ROM, &ips(MAX_ROM_SIZE - 1)).unwrap();
+    assert_eq!(patched.len(), MAX_ROM_SIZE);
+    assert_eq!(
+        apply_patch(&ROM, &ips(MAX_ROM_SIZE)),
+        Err(PatchError::RomTooLarge(MAX_ROM_SIZE + 1))
+    );
+}
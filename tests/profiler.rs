@@ -0,0 +1,51 @@
+use chip8_wasm::chip8_cpu::Chip8CPU;
+
+const ROM: [u8; 16] = [
+    0x22, 0x08, // 200: Call 0x208
+    0x22, 0x08, // 202: Call 0x208
+    0x00, 0x00, // 204: Never reached
+    0x00, 0x00, // 206: Padding
+    0x22, 0x0E, // 208: Call 0x20E
+    0x00, 0xEE, // 20A: Return
+    0x00, 0x00, // 20C: Padding
+    0x00, 0xEE, // 20E: Return
+];
+
+#[test]
+fn profiles_opcodes_subroutines_and_frames() {
+    let mut cpu = Chip8CPU::new();
+    cpu.enable_profiler();
+    cpu.load_rom(&ROM).unwrap();
+
+    cpu.run_frame(5);
+    cpu.run_frame(3);
+
+    let profiler = cpu.get_profiler().unwrap();
+    assert_eq!(profiler.get_instruction_count(), 8);
+    assert_eq!(profiler.get_frame_instructions(), [5, 3]);
+    assert_eq!(profiler.get_class_counts()["2NNN"], 4);
+    assert_eq!(profiler.get_class_counts()["00EE"], 4);
+    assert_eq!(profiler.get_pc_count(0x208), 2);
+
+    let subroutines = profiler.subroutines();
+    assert_eq!(subroutines.len(), 2);
+    assert_eq!(
+        (
+            subroutines[0].address,
+            subroutines[0].calls,
+            subroutines[0].self_instructions,
+            subroutines[0].total_instructions
+        ),
+        (0x208, 2, 4, 6)
+    );
+    assert_eq!(subroutines[1].total_instructions, 2);
+
+    assert_eq!(
+        profiler.folded_stacks(),
+        "main 2\nmain;sub_208 4\nmain;sub_208;sub_20E 2\n"
+    );
+    let report = profiler.flat_profile();
+    assert!(report.starts_with("8 instructions executed\n"));
+    assert!(report.contains("\n208              2          4          6  75.00\n"));
+    assert!(report.contains("2 frames, instructions per frame: min 3, average 4.0, max 5"));
+}
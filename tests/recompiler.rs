@@ -0,0 +1,139 @@
+//! Checks the recompiler's output is a valid module, and that running it gives
+//! the same state as the interpreter.
+
+#![cfg(feature = "recompiler")]
+
+use chip8_wasm::chip8_cpu::{Chip8CPU, STATE_SIZE};
+use chip8_wasm::quirks::Quirks;
+use chip8_wasm::recompiler::{self, compile_rom};
+
+use wasmi::{Engine, Linker, Module, Store};
+
+#[test]
+fn compiles_the_bundled_games_to_valid_modules() {
+    let games: [&[u8]; 3] = [
+        include_bytes!("../src/games/PONG"),
+        include_bytes!("../src/games/PONG2"),
+        include_bytes!("../src/games/TANK"),
+    ];
+    for rom in games {
+        let compiled = compile_rom(rom, Quirks::default()).unwrap();
+        assert!(!compiled.blocks.is_empty());
+        wasmparser::Validator::new()
+            .validate_all(&compiled.wasm)
+            .unwrap();
+    }
+}
+
+/// Arithmetic, a subroutine, skips, a jump, timers and keys, with a few
+/// instructions the compiled code hands back to the interpreter in between.
+const PROGRAM: [u16; 22] = [
+    0x6005, // V0 = 5
+    0x610A, // V1 = 10
+    0x8014, // V0 += V1
+    0x8105, // V1 -= V0
+    0x8206, // V2 >>= 1
+    0x2240, // Call 0x240
+    0x3010, // Skip if V0 == 0x10
+    0x6400, // V4 = 0
+    0x6307, // V3 = 7
+    0xF315, // DT = V3
+    0xE39E, // Skip if key V3 is pressed
+    0x6400, // V4 = 0
+    0xA300, // I = 0x300
+    0xF355, // Store V0 to V3 at I
+    0xF21E, // I += V2
+    0xD011, // Draw
+    0x6FFF, // VF = 0xFF
+    0x8FF4, // VF += VF, leaving the carry
+    0xF129, // I = font for V1
+    0x122A, // Jump to 0x22A
+    0x6E01, // VE = 1, jumped over
+    0x00E0, // Clear the screen, as does the zeroed memory up to 0x240
+];
+const SUBROUTINE: [u16; 2] = [
+    0x7001, // V0 += 1
+    0x00EE, // Return
+];
+
+fn make_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x44];
+    for (index, opcode) in PROGRAM.iter().chain(SUBROUTINE.iter()).enumerate() {
+        let address = if index < PROGRAM.len() {
+            index * 2
+        } else {
+            0x40 + (index - PROGRAM.len()) * 2
+        };
+        rom[address..address + 2].copy_from_slice(&opcode.to_be_bytes());
+    }
+
+    rom
+}
+
+fn make_cpu(rom: &[u8]) -> Chip8CPU {
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(rom).unwrap();
+    cpu.set_key(7, true);
+
+    cpu
+}
+
+#[test]
+fn compiled_code_matches_the_interpreter() {
+    const INSTRUCTIONS_PER_FRAME: i32 = 7;
+    const FRAMES: i32 = 4;
+
+    let rom = make_rom();
+    let mut cpu = make_cpu(&rom);
+    let compiled = compile_rom(&rom, cpu.get_quirks()).unwrap();
+    let starts: Vec<u16> = compiled.blocks.iter().map(|block| block.start).collect();
+    assert!(starts.contains(&0x200));
+    assert!(starts.contains(&0x20C));
+    assert!(starts.contains(&0x240));
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &compiled.wasm[..]).unwrap();
+    let mut store = Store::new(&engine, ());
+    let instance = Linker::<()>::new(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    let memory = instance.get_memory(&store, "memory").unwrap();
+    let run = instance.get_typed_func::<i32, i32>(&store, "run").unwrap();
+    let invalidate = instance
+        .get_typed_func::<(i32, i32), ()>(&store, "invalidate")
+        .unwrap();
+    let end_frame = instance
+        .get_typed_func::<(), ()>(&store, "end_frame")
+        .unwrap();
+
+    let mut interpreted = make_cpu(&rom);
+    let mut state = vec![0; STATE_SIZE];
+    cpu.save_state(&mut state).unwrap();
+    memory.data_mut(&mut store)[..STATE_SIZE].copy_from_slice(&state);
+    for _ in 0..FRAMES {
+        let mut remaining = INSTRUCTIONS_PER_FRAME;
+        while remaining > 0 {
+            remaining -= run.call(&mut store, remaining).unwrap();
+            if remaining == 0 {
+                break;
+            }
+
+            cpu.load_state(&memory.data(&store)[..STATE_SIZE]).unwrap();
+            if let Some((start, length)) = recompiler::step(&mut cpu) {
+                invalidate
+                    .call(&mut store, (start as i32, length as i32))
+                    .unwrap();
+            }
+            cpu.save_state(&mut memory.data_mut(&mut store)[..STATE_SIZE])
+                .unwrap();
+            remaining -= 1;
+        }
+        end_frame.call(&mut store, ()).unwrap();
+
+        interpreted.run_frame(INSTRUCTIONS_PER_FRAME as u32);
+        interpreted.save_state(&mut state).unwrap();
+        assert_eq!(&memory.data(&store)[..STATE_SIZE], &state[..]);
+    }
+}
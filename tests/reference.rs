@@ -0,0 +1,63 @@
+use chip8_wasm::chip8_cpu::Chip8CPU;
+use chip8_wasm::reference::Differential;
+
+fn make_cpu(program: &[u16]) -> Chip8CPU {
+    let rom: Vec<u8> = program
+        .iter()
+        .flat_map(|opcode| opcode.to_be_bytes())
+        .collect();
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(&rom).unwrap();
+
+    cpu
+}
+
+#[test]
+fn agrees_on_arithmetic_subroutines_and_memory() {
+    let mut cpu = make_cpu(&[
+        0x60FF, // 200: V0 = 0xFF
+        0x6102, // 202: V1 = 2
+        0x8014, // 204: V0 += V1
+        0x8105, // 206: V1 -= V0
+        0x2212, // 208: Call 0x212
+        0x3001, // 20A: Skip if V0 == 1
+        0x6300, // 20C: V3 = 0, skipped
+        0xA300, // 20E: I = 0x300
+        0xF155, // 210: Store V0 and V1 at I
+        0x6205, // 212: V2 = 5
+        0xF215, // 214: DT = V2
+        0x00EE, // 216: Return
+    ]);
+
+    let mut differential = Differential::new(&cpu);
+    differential.run(&mut cpu, 11).unwrap();
+    assert_eq!(
+        differential.get_reference().state.memory[0x300..0x302],
+        [1, 1]
+    );
+}
+
+#[test]
+fn reports_the_first_mismatch_with_both_states() {
+    let mut cpu = make_cpu(&[
+        0xA300, // 200: I = 0x300
+        0x6004, // 202: V0 = 4
+        0xF01E, // 204: I += V0
+    ]);
+
+    let mut differential = Differential::new(&cpu);
+    differential.run(&mut cpu, 2).unwrap();
+    // Behind the reference's back.
+    cpu.set_index_register(0x100).unwrap();
+    let mismatch = differential.run(&mut cpu, 1).unwrap_err();
+    assert_eq!(mismatch.cycle, 3);
+    assert_eq!(mismatch.opcode, 0xF01E);
+    assert_eq!(mismatch.reference.index_register, 0x304);
+    assert_eq!(mismatch.interpreter.index_register, 0x104);
+
+    let report = mismatch.to_string();
+    assert!(report.starts_with("Mismatch on cycle 3 running F01E (ADD I, V0) at 0204\n"));
+    assert!(report.contains("Reference:\nV: 04 00"));
+    assert!(report.contains("PC:0206 I:0304"));
+    assert!(report.contains("PC:0206 I:0104"));
+}
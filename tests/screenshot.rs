@@ -0,0 +1,50 @@
+use chip8_wasm::display::Display;
+use chip8_wasm::image::{screenshot, ImageError, ImageFormat};
+
+fn display_with_corner_lit() -> Display {
+    let mut display = Display::new();
+    display.set_buffer_item(0, 1);
+    display
+}
+
+#[test]
+fn scales_the_screen_into_each_format() {
+    let display = display_with_corner_lit();
+
+    let pbm = screenshot(&display, ImageFormat::Pbm, 2, &[]).unwrap();
+    let header = b"P4\n128 64\n";
+    assert_eq!(pbm[..header.len()], header[..]);
+    // The lit pixel is the top left 2x2, 16 bytes a row.
+    assert_eq!(pbm[header.len()..header.len() + 2], [0xC0, 0x00]);
+    assert_eq!(pbm[header.len() + 16], 0xC0);
+    assert_eq!(pbm[header.len() + 32], 0x00);
+
+    let palette = [[0, 0, 0], [255, 255, 255]];
+    let pgm = screenshot(&display, ImageFormat::Pgm, 1, &palette).unwrap();
+    let header = b"P5\n64 32\n255\n";
+    assert_eq!(pgm[..header.len()], header[..]);
+    assert_eq!(pgm[header.len()..header.len() + 2], [255, 0]);
+    assert_eq!(pgm.len(), header.len() + 64 * 32);
+
+    assert_eq!(
+        screenshot(&display, ImageFormat::Png, 0, &[]),
+        Err(ImageError::InvalidScale)
+    );
+}
+
+#[test]
+fn draws_a_square_per_lit_pixel_in_svg() {
+    let svg = screenshot(
+        &display_with_corner_lit(),
+        ImageFormat::Svg,
+        10,
+        &[[0, 0, 0], [0x33, 0xFF, 0x66]],
+    )
+    .unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert!(svg.contains("width=\"640\" height=\"320\" viewBox=\"0 0 64 32\""));
+    assert!(svg.contains("fill=\"#000000\""));
+    assert!(svg
+        .contains("<g fill=\"#33ff66\">\n<rect x=\"0\" y=\"0\" width=\"1\" height=\"1\"/>\n</g>"));
+    assert_eq!(svg.matches("<rect").count(), 2);
+}
@@ -0,0 +1,47 @@
+use chip8_wasm::speed::{FramesDue, SpeedControl, SpeedError, MAX_SPEED_PERCENT};
+
+fn frames_over(speed: &mut SpeedControl, host_frames: usize) -> u32 {
+    (0..host_frames)
+        .map(|_| match speed.frames_due() {
+            FramesDue::Count(count) => count,
+            FramesDue::Uncapped => panic!("Only fast-forward is uncapped"),
+        })
+        .sum()
+}
+
+#[test]
+fn scales_frames_by_speed() {
+    let mut speed = SpeedControl::new();
+    assert_eq!(frames_over(&mut speed, 60), 60);
+
+    speed.set_speed_percent(10).unwrap();
+    assert_eq!(frames_over(&mut speed, 100), 10);
+    speed.set_speed_percent(150).unwrap();
+    assert_eq!(frames_over(&mut speed, 4), 6);
+    speed.set_speed_percent(MAX_SPEED_PERCENT).unwrap();
+    assert_eq!(frames_over(&mut speed, 1), 10);
+    assert_eq!(speed.set_speed_percent(5), Err(SpeedError::OutOfRange(5)));
+    assert_eq!(speed.get_speed_percent(), MAX_SPEED_PERCENT);
+}
+
+#[test]
+fn pauses_and_fast_forwards() {
+    let mut speed = SpeedControl::new();
+    speed.set_speed_percent(50).unwrap();
+    speed.frames_due();
+    speed.set_paused(true);
+    assert_eq!(frames_over(&mut speed, 10), 0);
+
+    // Fast-forward is ignored while paused, and the half frame from before
+    // pausing is dropped.
+    speed.set_fast_forward(true);
+    assert_eq!(frames_over(&mut speed, 1), 0);
+    speed.set_paused(false);
+    assert_eq!(frames_over(&mut speed, 1), 10);
+    speed.set_uncapped(true);
+    assert_eq!(speed.frames_due(), FramesDue::Uncapped);
+
+    speed.set_fast_forward(false);
+    assert_eq!(frames_over(&mut speed, 1), 0);
+    assert_eq!(frames_over(&mut speed, 1), 1);
+}
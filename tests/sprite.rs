@@ -0,0 +1,81 @@
+use chip8_wasm::image::{decode_pbm, encode_pbm, encode_png, Bitmap, MONOCHROME};
+use chip8_wasm::inflate::inflate;
+use chip8_wasm::sprite::{
+    decode_sprite, encode_sprite, find_sprites, SpriteError, SpriteShape, SpriteSheet,
+};
+
+#[test]
+fn round_trips_every_kind_of_sprite() {
+    let arrow = [0x20, 0x70, 0xF8, 0x20, 0x20];
+    let bitmap = decode_sprite(SpriteShape::from_draw(5, 1), &arrow).unwrap();
+    assert_eq!(bitmap.width, 8);
+    assert_eq!(&bitmap.pixels[8..16], [0, 1, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(
+        encode_sprite(SpriteShape::from_draw(5, 1), &bitmap).unwrap(),
+        arrow
+    );
+
+    let big: Vec<u8> = (0..32).collect();
+    let shape = SpriteShape::from_draw(0, 1);
+    let bitmap = decode_sprite(shape, &big).unwrap();
+    assert_eq!((bitmap.width, bitmap.height), (16, 16));
+    assert_eq!(encode_sprite(shape, &bitmap).unwrap(), big);
+
+    // Plane 0 then plane 1: the top row is colour 1, then 3, then 2.
+    let shape = SpriteShape::from_draw(1, 2);
+    let bitmap = decode_sprite(shape, &[0xC0, 0x60]).unwrap();
+    assert_eq!(&bitmap.pixels[..4], [1, 3, 2, 0]);
+    assert_eq!(encode_sprite(shape, &bitmap).unwrap(), [0xC0, 0x60]);
+
+    let mut too_colourful = bitmap.clone();
+    too_colourful.set(7, 0, 4);
+    assert_eq!(
+        encode_sprite(shape, &too_colourful),
+        Err(SpriteError::InvalidColour { x: 7, y: 0 })
+    );
+}
+
+#[test]
+fn edits_a_rom_through_a_sprite_sheet() {
+    // LD I, 0x20A; LD V0, 0; LD V1, 0; DRW V0, V1, 5; JP 0x208; then the sprite.
+    let mut rom = vec![
+        0xA2, 0x0A, 0x60, 0x00, 0x61, 0x00, 0xD0, 0x15, 0x12, 0x08, 0x20, 0x70, 0xF8, 0x20, 0x20,
+    ];
+    let sprites = find_sprites(&rom);
+    assert_eq!(sprites.len(), 1);
+    assert_eq!(sprites[0].address, 0x20A);
+
+    let sheet = SpriteSheet::find(&rom);
+    let bitmap = sheet.render(&rom).unwrap();
+    assert_eq!((bitmap.width, bitmap.height), (16, 16));
+
+    // Through a file and back, with the top left pixel lit.
+    let mut edited = decode_pbm(&encode_pbm(&bitmap)).unwrap();
+    assert_eq!(edited, bitmap);
+    edited.set(0, 0, 1);
+    sheet.apply(&mut rom, &edited).unwrap();
+    assert_eq!(rom[10..], [0xA0, 0x70, 0xF8, 0x20, 0x20]);
+
+    assert_eq!(
+        sheet.apply(&mut rom, &Bitmap::new(8, 8)),
+        Err(SpriteError::WrongSize)
+    );
+}
+
+#[test]
+fn writes_a_png_of_the_pixels() {
+    let mut bitmap = Bitmap::new(3, 2);
+    bitmap.set(1, 1, 1);
+    let png = encode_png(&bitmap, &MONOCHROME);
+    assert_eq!(
+        png[..8],
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']
+    );
+
+    // The image data is the chunk after the header and palette, zlib wrapped.
+    let idat = png.windows(4).position(|kind| kind == b"IDAT").unwrap();
+    let length = u32::from_be_bytes([png[idat - 4], png[idat - 3], png[idat - 2], png[idat - 1]]);
+    let zlib = &png[idat + 4..idat + 4 + length as usize];
+    let rows = inflate(&zlib[2..zlib.len() - 4], 64).unwrap();
+    assert_eq!(rows, [0, 0, 0, 0, 0, 0, 1, 0]);
+}
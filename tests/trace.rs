@@ -0,0 +1,119 @@
+use chip8_wasm::chip8_cpu::Chip8CPU;
+use chip8_wasm::trace::{first_divergence, TraceEntry, TraceError};
+
+const ROM: [u8; 6] = [
+    0x60, 0x05, // 200: V0 = 5
+    0x61, 0x0A, // 202: V1 = 10
+    0xA2, 0x2A, // 204: I = 0x22A
+];
+
+#[test]
+fn traces_the_state_before_each_instruction() {
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(&ROM).unwrap();
+    cpu.enable_trace(2);
+    (0..3).for_each(|_| cpu.cycle());
+
+    let trace = cpu.get_trace().unwrap();
+    assert_eq!(trace.get_dropped(), 1);
+    assert_eq!(
+        trace.to_lines(),
+        "00000001 PC:0202 OP:610A LD V1, 0x0A        \
+         V:05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:00 DT:00 ST:00\n\
+         00000002 PC:0204 OP:A22A LD I, 0x22A        \
+         V:05 0A 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:00 DT:00 ST:00\n"
+    );
+
+    let drained: Vec<u64> = cpu
+        .get_trace_mut()
+        .unwrap()
+        .drain()
+        .map(|entry| entry.cycle)
+        .collect();
+    assert_eq!(drained, vec![1, 2]);
+    assert!(cpu.get_trace().unwrap().is_empty());
+}
+
+#[test]
+fn counts_cycles_from_the_start_of_the_cpu() {
+    let mut cpu = Chip8CPU::new();
+    cpu.load_rom(&ROM).unwrap();
+    cpu.cycle();
+    cpu.enable_trace(8);
+    (0..2).for_each(|_| cpu.cycle());
+
+    let mut other = Chip8CPU::new();
+    other.load_rom(&ROM).unwrap();
+    other.enable_trace(8);
+    (0..3).for_each(|_| other.cycle());
+
+    let trace = cpu.get_trace().unwrap().to_lines();
+    let other_trace = other.get_trace().unwrap().to_lines();
+    assert!(trace.starts_with("00000001 PC:0202"));
+    assert!(other_trace.ends_with(&trace));
+}
+
+#[test]
+fn finds_the_first_divergent_line() {
+    assert_eq!(first_divergence("a\nb\n", "a\nb"), None);
+
+    let divergence = first_divergence("a\nb\nc\n", "a\nx\nc\n").unwrap();
+    assert_eq!(divergence.line_number, 2);
+    assert_eq!((divergence.left, divergence.right), (Some("b"), Some("x")));
+    assert_eq!(
+        divergence.to_string(),
+        "Traces diverge at line 2:\n< b\n> x"
+    );
+
+    let divergence = first_divergence("a\n", "a\nb\n").unwrap();
+    assert_eq!((divergence.left, divergence.right), (None, Some("b")));
+}
+
+#[test]
+fn compares_trace_lines_by_their_fields() {
+    let ours = "00000002 PC:0204 OP:A22A LD I, 0x22A        \
+                V:05 0A 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:00 DT:00 ST:00";
+    let theirs = "2 PC:0204 OP:a22a ld i,22a V:05 0a 00 00 00 00 00 00 00 00 00 00 00 00 00 00 \
+                  I:0000 SP:00 DT:00 ST:00 FRAME:0";
+    assert_eq!(first_divergence(ours, theirs), None);
+
+    let different = theirs.replace("I:0000", "I:0001");
+    let divergence = first_divergence(ours, &different).unwrap();
+    assert_eq!(divergence.line_number, 1);
+    assert_eq!(divergence.right, Some(different.as_str()));
+}
+
+#[test]
+fn reads_trace_lines_from_elsewhere() {
+    // Lower case, no disassembly and a field this crate doesn't write.
+    let line = "17 PC:02a4 OP:d015 V:05 0a 00 00 00 00 00 00 00 00 00 00 00 00 00 ff \
+                I:022a SP:01 DT:3c ST:00 FRAME:2";
+    let mut registers = [0; 16];
+    registers[..2].copy_from_slice(&[0x05, 0x0A]);
+    registers[15] = 0xFF;
+    let entry = TraceEntry {
+        cycle: 17,
+        program_counter: 0x2A4,
+        opcode: 0xD015,
+        registers,
+        index_register: 0x22A,
+        stack_pointer: 1,
+        delay_timer: 0x3C,
+        sound_timer: 0,
+    };
+    assert_eq!(TraceEntry::parse(line), Ok(entry));
+    assert_eq!(TraceEntry::parse(&entry.to_string()), Ok(entry));
+
+    assert_eq!(
+        TraceEntry::parse("17 PC:02a4 OP:d015"),
+        Err(TraceError::InvalidField("V"))
+    );
+    assert_eq!(
+        TraceEntry::parse(&line.replace("SP:01", "SP:100")),
+        Err(TraceError::InvalidField("SP"))
+    );
+    assert_eq!(
+        TraceEntry::parse(&line.replace("PC:", "PC")),
+        Err(TraceError::InvalidField("PC"))
+    );
+}
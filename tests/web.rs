@@ -11,3 +11,42 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+fn get(object: &js_sys::Object, name: &str) -> wasm_bindgen::JsValue {
+    js_sys::Reflect::get(object, &name.into()).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn reports_the_state_and_what_changed() {
+    let mut chip8 = chip8_wasm::chip8::Chip8::new();
+    chip8.load_rom("PONG".to_string()).unwrap();
+
+    let state = chip8.get_state();
+    assert_eq!(get(&state, "mode").as_string().unwrap(), "live");
+    assert_eq!(js_sys::Array::from(&get(&state, "registers")).length(), 16);
+
+    let generation = chip8.get_generation();
+    chip8.get_state_diff(generation);
+    chip8.set_delay_timer(5);
+    let diff = chip8.get_state_diff(generation);
+    assert_eq!(get(&diff, "delayTimer").as_f64(), Some(5.0));
+    assert!(get(&diff, "registers").is_undefined());
+}
+
+#[wasm_bindgen_test]
+fn resets_quirks_for_games_without_them() {
+    let mut chip8 = chip8_wasm::chip8::Chip8::new();
+    chip8
+        .load_archive(include_bytes!("data/homebrew.zip"))
+        .unwrap();
+    let shift_uses_vy = |chip8: &chip8_wasm::chip8::Chip8| {
+        let quirks = js_sys::Object::from(get(&chip8.get_state(), "quirks"));
+        get(&quirks, "shiftUsesVy").as_bool()
+    };
+
+    chip8.load_rom("Maze".to_string()).unwrap();
+    assert_eq!(shift_uses_vy(&chip8), Some(true));
+    // PONG's metadata has no quirks.
+    chip8.load_rom("PONG".to_string()).unwrap();
+    assert_eq!(shift_uses_vy(&chip8), Some(false));
+}
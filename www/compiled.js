@@ -0,0 +1,35 @@
+import { Chip8 } from "chip8";
+
+// Runs the current ROM of `chip8` as a compiled module. Needs the wasm package
+// built with the `recompiler` feature.
+export async function compile(chip8) {
+  const { instance } = await WebAssembly.instantiate(chip8.compile_rom());
+  const { memory, run, invalidate, end_frame } = instance.exports;
+  const state = () => new Uint8Array(memory.buffer, 0, Chip8.get_state_size());
+  state().set(chip8.save_state());
+
+  return {
+    runFrame(instructionsPerFrame) {
+      let remaining = instructionsPerFrame;
+      while (remaining > 0) {
+        remaining -= run(remaining);
+        if (remaining === 0) {
+          break;
+        }
+
+        // The compiled code stopped short, so run an instruction on the interpreter.
+        const written = chip8.step_compiled_state(state());
+        if (written.length > 0) {
+          invalidate(written[0], written[1]);
+        }
+        remaining -= 1;
+      }
+      end_frame();
+    },
+
+    // Copy the state back, to draw the display or carry on interpreting.
+    sync() {
+      chip8.load_state(state());
+    },
+  };
+}